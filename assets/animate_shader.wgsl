@@ -1,7 +1,136 @@
-// The time since startup data is in the globals binding which is part of the mesh_view_bindings import
-#import bevy_pbr::{
-    mesh_view_bindings::globals,
-    forward_io::VertexOutput,
+#import bevy_pbr::mesh_functions::{get_world_from_local, mesh_position_local_to_world, mesh_normal_local_to_world}
+#import bevy_pbr::view_transformations::position_world_to_clip
+#import bevy_pbr::mesh_view_bindings::{globals, view}
+
+// Bevy's own vertex shader doesn't know about wetness or curvature, so the
+// terrain has its own.
+struct Vertex {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // see wetness.rs
+    @location(8) wetness: f32,
+    // see curvature.rs
+    @location(9) curvature: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) wetness: f32,
+    @location(3) curvature: f32,
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    let world_from_local = get_world_from_local(vertex.instance_index);
+    out.world_position = mesh_position_local_to_world(world_from_local, vec4<f32>(vertex.position, 1.0));
+    out.position = position_world_to_clip(out.world_position.xyz);
+    out.world_normal = mesh_normal_local_to_world(vertex.normal, vertex.instance_index);
+    out.wetness = vertex.wetness;
+    out.curvature = vertex.curvature;
+    return out;
+}
+
+// trail map of the player's footprints, see footprints.rs
+@group(2) @binding(0) var footprint_texture: texture_2d<f32>;
+@group(2) @binding(1) var footprint_sampler: sampler;
+// min x, min z, size x, size z of the area the trail map covers
+@group(2) @binding(2) var<uniform> footprint_bounds: vec4<f32>;
+// added to the mip level of every texture lookup, see texture_filtering.rs
+@group(2) @binding(3) var<uniform> mip_bias: f32;
+
+// see height_gradient.rs
+struct HeightGradient {
+    // oklab color in xyz, normalized height in w, sorted by height
+    stops: array<vec4<f32>, 8>,
+    count: u32,
+    min_height: f32,
+    max_height: f32,
+}
+@group(2) @binding(4) var<uniform> height_gradient: HeightGradient;
+
+// baked sun shadows, see lightmap.rs
+@group(2) @binding(5) var lightmap_texture: texture_2d<f32>;
+@group(2) @binding(6) var lightmap_sampler: sampler;
+// min x, min z, size x, size z of the area the lightmap covers
+@group(2) @binding(7) var<uniform> lightmap_bounds: vec4<f32>;
+// how much fully shadowed ground is darkened, 0.0 when the lightmap isn't used
+@group(2) @binding(8) var<uniform> shadow_strength: f32;
+
+// drifting cloud shadows, see clouds.rs
+@group(2) @binding(9) var cloud_texture: texture_2d<f32>;
+@group(2) @binding(10) var cloud_sampler: sampler;
+struct Clouds {
+    // world units per second on the xz plane
+    wind: vec2<f32>,
+    // world units per repeat of the texture
+    scale: f32,
+    coverage: f32,
+    softness: f32,
+    darkness: f32,
+}
+@group(2) @binding(11) var<uniform> clouds: Clouds;
+
+// how wet ground near the water looks, see wetness.rs
+struct Wetness {
+    // fraction fully wet ground is darkened by
+    darkening: f32,
+    // brightness of the shine on wet ground seen at a low angle
+    sheen: f32,
+}
+@group(2) @binding(12) var<uniform> wetness: Wetness;
+
+// grid lines on the surface, see surface_grid.rs
+struct SurfaceGrid {
+    // linear rgb, alpha is 0.0 while the grid is off
+    color: vec4<f32>,
+    // world units between lines
+    spacing: f32,
+    // pixels
+    line_width: f32,
+}
+@group(2) @binding(13) var<uniform> surface_grid: SurfaceGrid;
+
+// tint by the shape of the ground, see curvature.rs
+struct Curvature {
+    // fraction the deepest hollows are darkened by
+    cavity_darkening: f32,
+    // fraction the sharpest ridges are lightened by
+    ridge_lightening: f32,
+}
+@group(2) @binding(14) var<uniform> curvature: Curvature;
+
+// lines at height intervals, see contours.rs
+struct Contours {
+    // linear rgb, alpha is 0.0 while the lines are off
+    color: vec4<f32>,
+    // world units of height between lines
+    interval: f32,
+    // pixels
+    line_width: f32,
+}
+@group(2) @binding(15) var<uniform> contours: Contours;
+
+// How much of this pixel a grid line covers, 0.0 to 1.0.
+fn grid_coverage(world_xz: vec2<f32>) -> f32 {
+    let coord = world_xz / surface_grid.spacing;
+    // distance to the nearest line along each axis, in pixels
+    let pixels = abs(fract(coord - 0.5) - 0.5) / max(fwidth(coord), vec2<f32>(0.00001));
+    let distance = min(pixels.x, pixels.y);
+    // a one pixel wide ramp at the line's edge smooths out the stairs
+    return 1.0 - clamp(distance - surface_grid.line_width * 0.5 + 0.5, 0.0, 1.0);
+}
+
+// How much of this pixel a contour line covers, 0.0 to 1.0.
+fn contour_coverage(height: f32) -> f32 {
+    let coord = height / contours.interval;
+    // distance to the nearest line in pixels, fwidth is how much the height
+    // changes across a pixel so steep ground gets as thin a line as flat ground
+    let pixels = abs(fract(coord - 0.5) - 0.5) / max(fwidth(coord), 0.00001);
+    return 1.0 - clamp(pixels - contours.line_width * 0.5 + 0.5, 0.0, 1.0);
 }
 
 fn oklab_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
@@ -24,22 +153,62 @@ fn oklab_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
     );
 }
 
+// Oklab color of the gradient at a world space height.
+fn gradient_color(height: f32) -> vec3<f32> {
+    let range = max(height_gradient.max_height - height_gradient.min_height, 0.0001);
+    let h = clamp((height - height_gradient.min_height) / range, 0.0, 1.0);
+    // each stop takes over from the one before it as h passes between them,
+    // so below the first stop and above the last one the end colors are used
+    var color = height_gradient.stops[0].xyz;
+    for (var i = 1u; i < height_gradient.count; i++) {
+        let previous = height_gradient.stops[i - 1u];
+        let next = height_gradient.stops[i];
+        let t = clamp((h - previous.w) / max(next.w - previous.w, 0.0001), 0.0, 1.0);
+        color = mix(color, next.xyz, t);
+    }
+    return color;
+}
+
 @fragment
 fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
-    let speed = 2.0;
-    // The globals binding contains various global values like time
-    // which is the time since startup in seconds
-    let t_1 = sin(globals.time * speed) * 0.5 + 0.5;
-    let t_2 = cos(globals.time * speed);
+    // blending is done in a perceptual color space: https://bottosson.github.io/posts/oklab/
+    let mixed = gradient_color(in.world_position.y);
 
-    let distance_to_center = distance(in.uv, vec2<f32>(0.5)) * 1.4;
+    // darken the ground where the player has walked recently
+    let footprint_uv = (in.world_position.xz - footprint_bounds.xy) / footprint_bounds.zw;
+    let inside_trail = all(footprint_uv >= vec2<f32>(0.0)) && all(footprint_uv <= vec2<f32>(1.0));
+    let footprint = textureSampleBias(footprint_texture, footprint_sampler, footprint_uv, mip_bias).r;
+    let darkening = select(0.0, footprint * 0.5, inside_trail);
 
-    // blending is done in a perceptual color space: https://bottosson.github.io/posts/oklab/
-    let red = vec3<f32>(0.627955, 0.224863, 0.125846);
-    let green = vec3<f32>(0.86644, -0.233887, 0.179498);
-    let blue = vec3<f32>(0.701674, 0.274566, -0.169156);
-    let white = vec3<f32>(1.0, 0.0, 0.0);
-    let mixed = mix(mix(red, blue, t_1), mix(green, white, t_2), distance_to_center);
+    let lightmap_uv = (in.world_position.xz - lightmap_bounds.xy) / lightmap_bounds.zw;
+    let lit = textureSampleBias(lightmap_texture, lightmap_sampler, lightmap_uv, mip_bias).r;
+    let shadow = (1.0 - lit) * shadow_strength;
+
+    // noise above the threshold is under a cloud, more coverage lowers the threshold
+    let cloud_uv = (in.world_position.xz - clouds.wind * globals.time) / clouds.scale;
+    let density = textureSample(cloud_texture, cloud_sampler, cloud_uv).r;
+    let threshold = 1.0 - clouds.coverage;
+    let cloud = smoothstep(threshold - clouds.softness, threshold + clouds.softness, density);
+    let cloud_shadow = cloud * clouds.darkness;
+
+    let wet = clamp(in.wetness, 0.0, 1.0);
+    let wet_darkening = wet * wetness.darkening;
+
+    // hollows are negative and ridges positive
+    let bend = clamp(in.curvature, -1.0, 1.0);
+    let cavity = max(-bend, 0.0) * curvature.cavity_darkening;
+    let ridge = max(bend, 0.0) * curvature.ridge_lightening;
 
-    return vec4<f32>(oklab_to_linear_srgb(mixed), 1.0);
+    let shading = (1.0 - darkening) * (1.0 - shadow) * (1.0 - cloud_shadow) * (1.0 - wet_darkening)
+        * (1.0 - cavity) * (1.0 + ridge);
+    // wet ground shines like water when looked at from low down, a Schlick style fresnel term
+    let to_camera = normalize(view.world_position - in.world_position.xyz);
+    let facing = max(dot(normalize(in.world_normal), to_camera), 0.0);
+    let shine = wet * wetness.sheen * pow(1.0 - facing, 5.0) * (1.0 - cloud_shadow);
+    // derivatives are only defined outside branches, so it's worked out even while the grid is off
+    let grid = grid_coverage(in.world_position.xz) * surface_grid.color.a;
+    let contour = contour_coverage(in.world_position.y) * contours.color.a;
+    let color = oklab_to_linear_srgb(mixed) * shading + vec3<f32>(shine);
+    let lined = mix(color, contours.color.rgb, contour);
+    return vec4<f32>(mix(lined, surface_grid.color.rgb, grid), 1.0);
 }
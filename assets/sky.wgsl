@@ -0,0 +1,31 @@
+#import bevy_pbr::forward_io::VertexOutput
+#import bevy_pbr::mesh_view_bindings::view
+
+// see sky.rs
+struct Sky {
+    // linear colors
+    horizon: vec4<f32>,
+    zenith: vec4<f32>,
+    // the sun disc's color in rgb and how visible it is in a
+    sun_color: vec4<f32>,
+    // towards the sun in xyz, cosine of the disc's angular radius in w
+    sun_direction: vec4<f32>,
+}
+@group(2) @binding(0) var<uniform> sky: Sky;
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // the dome is centered on the camera, so this is the direction being looked in
+    let direction = normalize(in.world_position.xyz - view.world_position);
+    // the color changes fastest near the horizon, and everything below it is
+    // horizon colored, the terrain covers most of that anyway
+    let t = sqrt(clamp(direction.y, 0.0, 1.0));
+    var color = mix(sky.horizon.rgb, sky.zenith.rgb, t);
+
+    // a slightly soft edge so the disc doesn't alias
+    let along = dot(direction, sky.sun_direction.xyz);
+    let edge = sky.sun_direction.w;
+    let disc = smoothstep(edge - 0.00005, edge + 0.00005, along);
+    color = mix(color, sky.sun_color.rgb, disc * sky.sun_color.a);
+    return vec4<f32>(color, 1.0);
+}
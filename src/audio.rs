@@ -0,0 +1,408 @@
+//! Sound. Everything plays on a channel, and a sound's volume is the master
+//! volume times its channel's volume times its own, kept up to date on playing
+//! sounds whenever the settings change. There are no sound files yet, so the
+//! wind and footsteps are synthesized, footsteps differently for each kind of
+//! ground.
+//!
+//! Sounds in the world are spatial: they pan between the camera's ears and get
+//! quieter with distance, fading out completely at the edge of their range.
+//! Footsteps echo and the wind dies down in valleys, see reverb.rs.
+//! Sounds spawned with `sound` aren't, they're for menus, music and the wind.
+
+use std::time::Duration;
+
+use bevy::audio::{
+    AudioSink, AudioSinkPlayback, Decodable, DefaultSpatialScale, PlaybackMode, Source,
+    SpatialAudioSink, SpatialListener, SpatialScale, Volume,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::footprints::Footstep;
+use crate::reverb::{Enclosure, ReverbConfig};
+use crate::surface::{Surface, SurfaceConfig};
+
+// All volumes are 0.0 to 1.0.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub sfx: f32,
+    pub ambient: f32,
+    // nothing plays on this yet
+    pub music: f32,
+    // whether valleys echo, see reverb.rs
+    pub reverb: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master: 1.0,
+            sfx: 1.0,
+            ambient: 0.6,
+            music: 0.8,
+            reverb: true,
+        }
+    }
+}
+
+// there's no music yet, the channel is there so its volume can already be set
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannel {
+    Sfx,
+    Ambient,
+    Music,
+}
+
+impl AudioSettings {
+    pub fn channel(&self, channel: AudioChannel) -> f32 {
+        let volume = match channel {
+            AudioChannel::Sfx => self.sfx,
+            AudioChannel::Ambient => self.ambient,
+            AudioChannel::Music => self.music,
+        };
+        self.master * volume
+    }
+}
+
+// Which channel a sound plays on, and its volume within that channel.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ChannelVolume {
+    pub channel: AudioChannel,
+    pub volume: f32,
+}
+
+// Everything needed to spawn a sound on a channel. Playback starts at the right
+// volume, `update_volumes` keeps it right afterwards.
+pub fn sound(
+    settings: &AudioSettings,
+    source: Handle<Synth>,
+    mode: PlaybackMode,
+    channel: AudioChannel,
+    volume: f32,
+) -> (AudioSourceBundle<Synth>, ChannelVolume) {
+    (
+        AudioSourceBundle {
+            source,
+            settings: PlaybackSettings {
+                mode,
+                volume: Volume::new(settings.channel(channel) * volume),
+                ..default()
+            },
+        },
+        ChannelVolume { channel, volume },
+    )
+}
+
+pub fn update_volumes(
+    settings: Res<AudioSettings>,
+    sinks: Query<(Ref<AudioSink>, &ChannelVolume)>,
+) {
+    for (sink, channel) in sinks.iter() {
+        if settings.is_changed() || sink.is_added() {
+            sink.set_volume(settings.channel(channel.channel) * channel.volume);
+        }
+    }
+}
+
+// How sounds in the world fall off, for all of them at once.
+#[derive(Resource, Debug, Clone)]
+pub struct SpatialAudioConfig {
+    // world units between the listener's ears, wider pans harder
+    pub ear_gap: f32,
+    // distances are multiplied by this before panning. Bevy's panning also makes
+    // sounds quieter with the square of the scaled distance once it's past 1.0,
+    // so smaller values carry sounds further
+    pub scale: f32,
+    // shape of the fade towards the edge of an emitter's range, 1.0 is linear,
+    // higher keeps sounds loud for longer and then drops them quickly
+    pub rolloff: f32,
+}
+
+impl Default for SpatialAudioConfig {
+    fn default() -> Self {
+        SpatialAudioConfig {
+            ear_gap: 0.4,
+            // full volume within 20 units
+            scale: 0.05,
+            rolloff: 1.0,
+        }
+    }
+}
+
+impl SpatialAudioConfig {
+    // Volume multiplier from fading towards the edge of `range`, 0.0 past it.
+    pub fn fade(&self, distance: f32, range: f32) -> f32 {
+        if range <= 0.0 {
+            return 0.0;
+        }
+        let t = (distance / range).clamp(0.0, 1.0);
+        1.0 - t.powf(self.rolloff.max(f32::EPSILON))
+    }
+}
+
+// A looping sound coming from an entity in the world, silent past `range` world
+// units from the listener. Plays on the sfx channel.
+// nothing in the world makes a sound yet
+#[allow(dead_code)]
+#[derive(Component, Debug, Clone)]
+pub struct SpatialEmitter {
+    pub sound: Handle<Synth>,
+    pub range: f32,
+}
+
+pub fn listener(config: &SpatialAudioConfig) -> SpatialListener {
+    SpatialListener::new(config.ear_gap)
+}
+
+// Starts emitters playing when they're added.
+pub fn start_emitters(
+    mut commands: Commands,
+    emitters: Query<(Entity, &SpatialEmitter), Added<SpatialEmitter>>,
+) {
+    for (entity, emitter) in emitters.iter() {
+        commands.entity(entity).insert(AudioSourceBundle {
+            source: emitter.sound.clone(),
+            // silent until `update_emitters` works out how far away it is
+            settings: PlaybackSettings {
+                mode: PlaybackMode::Loop,
+                volume: Volume::new(0.0),
+                spatial: true,
+                ..default()
+            },
+        });
+    }
+}
+
+// Fades emitters with their distance from the listener. Panning and Bevy's own
+// falloff happen in Bevy's spatial audio.
+pub fn update_emitters(
+    settings: Res<AudioSettings>,
+    config: Res<SpatialAudioConfig>,
+    listener: Query<&GlobalTransform, With<SpatialListener>>,
+    emitters: Query<(&SpatialEmitter, &GlobalTransform, &SpatialAudioSink)>,
+) {
+    let Ok(listener) = listener.get_single() else {
+        return;
+    };
+    let volume = settings.channel(AudioChannel::Sfx);
+    for (emitter, transform, sink) in emitters.iter() {
+        let distance = transform.translation().distance(listener.translation());
+        sink.set_volume(volume * config.fade(distance, emitter.range));
+    }
+}
+
+pub fn apply_spatial_config(
+    config: Res<SpatialAudioConfig>,
+    mut scale: ResMut<DefaultSpatialScale>,
+    mut listeners: Query<&mut SpatialListener>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    scale.0 = SpatialScale::new(config.scale);
+    for mut listener in listeners.iter_mut() {
+        *listener = self::listener(&config);
+    }
+}
+
+const SAMPLE_RATE: u32 = 22050;
+// seconds between a sound and its echo
+const ECHO_DELAY: f32 = 0.06;
+// seconds a fully echoing sound rings on for after it ends
+const ECHO_TAIL: f32 = 0.5;
+
+// Procedurally generated sounds.
+#[derive(Asset, TypePath, Debug, Clone, Copy)]
+pub enum Synth {
+    // endless low rumbling noise with slow gusts
+    Wind,
+    // a short burst of filtered noise, how it sounds depends on the ground. The
+    // second value is how much it echoes, 0.0 to 1.0.
+    Footstep(FootstepSound, f32),
+}
+
+// Low cutoffs make a soft thud, high ones a sharp crunch or click.
+#[derive(Debug, Clone, Copy)]
+pub struct FootstepSound {
+    // low pass filter strength, 0.0 to 1.0
+    pub cutoff: f32,
+    // how quickly the step dies away, larger is shorter
+    pub decay: f32,
+}
+
+impl Synth {
+    fn length(self) -> Option<u64> {
+        match self {
+            Synth::Wind => None,
+            Synth::Footstep(_, echo) => {
+                let tail = ECHO_TAIL * echo.clamp(0.0, 1.0);
+                Some(SAMPLE_RATE as u64 * 8 / 100 + (tail * SAMPLE_RATE as f32) as u64)
+            }
+        }
+    }
+}
+
+impl Decodable for Synth {
+    type DecoderItem = f32;
+    type Decoder = SynthDecoder;
+
+    fn decoder(&self) -> SynthDecoder {
+        let echo = match *self {
+            Synth::Footstep(_, echo) if echo > 0.0 => echo.min(1.0),
+            _ => 0.0,
+        };
+        SynthDecoder {
+            synth: *self,
+            sample: 0,
+            noise: 0x2545_f491_4f6c_dd1d,
+            filtered: 0.0,
+            echo,
+            delay_line: if echo > 0.0 {
+                vec![0.0; (ECHO_DELAY * SAMPLE_RATE as f32) as usize]
+            } else {
+                vec![]
+            },
+        }
+    }
+}
+
+pub struct SynthDecoder {
+    synth: Synth,
+    sample: u64,
+    // xorshift state for the white noise both sounds start from
+    noise: u64,
+    // state of the low pass filter that turns it into a rumble
+    filtered: f32,
+    // how much of the delay line is mixed back in, 0.0 for none
+    echo: f32,
+    // the last `ECHO_DELAY` seconds of output, empty without echo
+    delay_line: Vec<f32>,
+}
+
+impl SynthDecoder {
+    fn white_noise(&mut self) -> f32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 7;
+        self.noise ^= self.noise << 17;
+        (self.noise >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+impl Iterator for SynthDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self
+            .synth
+            .length()
+            .is_some_and(|length| self.sample >= length)
+        {
+            return None;
+        }
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        self.sample += 1;
+
+        let (cutoff, amplitude) = match self.synth {
+            // two slow sines beating against each other make irregular gusts
+            Synth::Wind => (0.02, 0.4 + 0.3 * (t * 0.37).sin() * (t * 0.13 + 1.0).sin()),
+            // quick attack, exponential decay
+            Synth::Footstep(step, _) => {
+                (step.cutoff, (t * 400.0).min(1.0) * (-t * step.decay).exp())
+            }
+        };
+        let noise = self.white_noise();
+        self.filtered += cutoff * (noise - self.filtered);
+        // the filter takes a lot of energy out, make up for it
+        let dry = self.filtered * amplitude / cutoff.sqrt() * 0.3;
+        if self.delay_line.is_empty() {
+            return Some(dry);
+        }
+        // a feedback delay, each echo quieter than the last
+        let index = (self.sample as usize) % self.delay_line.len();
+        let delayed = self.delay_line[index];
+        self.delay_line[index] = dry + delayed * self.echo * 0.6;
+        Some(dry + delayed * self.echo)
+    }
+}
+
+impl Source for SynthDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.synth
+            .length()
+            .map(|length| Duration::from_secs_f32(length as f32 / SAMPLE_RATE as f32))
+    }
+}
+
+#[derive(Resource)]
+pub struct SoundAssets {
+    pub wind: Handle<Synth>,
+}
+
+impl FromWorld for SoundAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut synths = world.resource_mut::<Assets<Synth>>();
+        SoundAssets {
+            wind: synths.add(Synth::Wind),
+        }
+    }
+}
+
+// Marker for the wind, so it can be quieted in sheltered places.
+#[derive(Component)]
+pub struct WindSound {}
+
+pub fn start_wind(mut commands: Commands, settings: Res<AudioSettings>, sounds: Res<SoundAssets>) {
+    // the wind never ends, so playing it once plays it forever
+    commands.spawn((
+        sound(
+            &settings,
+            sounds.wind.clone(),
+            PlaybackMode::Once,
+            AudioChannel::Ambient,
+            1.0,
+        ),
+        WindSound {},
+    ));
+}
+
+pub fn play_footsteps(
+    mut commands: Commands,
+    settings: Res<AudioSettings>,
+    reverb: Res<ReverbConfig>,
+    enclosure: Res<Enclosure>,
+    surfaces: Res<SurfaceConfig>,
+    mut synths: ResMut<Assets<Synth>>,
+    mut footsteps: EventReader<Footstep>,
+) {
+    let echo = if settings.reverb {
+        reverb.strength * enclosure.0
+    } else {
+        0.0
+    };
+    for footstep in footsteps.read() {
+        let effect = surfaces.effect(footstep.surface.unwrap_or(Surface::Grass));
+        // the synth is dropped again once the step has played
+        commands.spawn(sound(
+            &settings,
+            synths.add(Synth::Footstep(effect.footstep, echo)),
+            PlaybackMode::Despawn,
+            AudioChannel::Sfx,
+            effect.volume,
+        ));
+    }
+}
@@ -0,0 +1,579 @@
+//! Camera settings, and switching between the normal perspective view and an
+//! orthographic one for looking at the terrain like a map or diorama. The switch
+//! is a short dolly zoom rather than a cut, see `ProjectionTransition`.
+
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::render::camera::{PerspectiveProjection, ScalingMode};
+use bevy_rapier3d::prelude::{QueryFilter, RapierContext};
+
+use crate::controls::KeyBindings;
+use crate::player::PlayerLanded;
+use crate::settings::Settings;
+use crate::terrain::TerrainHeights;
+use crate::{Mountain, Player};
+
+// how far away the look at key can pick something to look at
+const LOOK_AT_REACH: f32 = 500.0;
+
+// Bevy already renders with reversed-Z and an infinite far plane: depth is
+// stored as near / z, so precision is highest far away rather than up close and
+// the terrain doesn't z-fight in the distance. `far` therefore doesn't clip
+// anything visually, it only bounds frustum culling. Logarithmic depth would
+// need every material to write its own depth, which isn't worth it on top of
+// reversed-Z. The one knob that still matters for precision is `near`: keep it
+// as large as the game allows.
+#[derive(Resource, Debug, Clone)]
+pub struct CameraConfig {
+    pub near: f32,
+    pub far: f32,
+    // Most mouse movement applied in one frame, in the same units as
+    // `MouseMotion::delta`. During a hitch many motion events pile up, and
+    // turning by all of them at once snaps the camera around.
+    pub max_look_delta: f32,
+    // whether the camera currently uses the orthographic projection
+    pub orthographic: bool,
+    // world units visible from the bottom to the top of the screen in
+    // orthographic mode, changed with the scroll wheel
+    pub ortho_height: f32,
+    pub min_ortho_height: f32,
+    pub max_ortho_height: f32,
+    // fraction the view grows or shrinks by per scroll wheel line
+    pub zoom_speed: f32,
+    // closest the camera gets to terrain in front of it, in world units. It's
+    // pulled back when it's closer, so the near plane doesn't cut into steep slopes.
+    pub terrain_clearance: f32,
+    // how far up or down the camera can look, in degrees from level
+    pub max_pitch: f32,
+    // radians per second the roll keys tilt the camera
+    pub roll_speed: f32,
+    // seconds the level camera key takes to bring the view back to level
+    pub level_duration: f32,
+    // seconds switching between perspective and orthographic takes, 0.0 cuts
+    // straight to the other one
+    pub projection_transition_duration: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            near: 0.05,
+            far: 1000.0,
+            max_look_delta: 200.0,
+            orthographic: false,
+            // a bit more than the whole terrain
+            ortho_height: 500.0,
+            min_ortho_height: 20.0,
+            max_ortho_height: 2000.0,
+            zoom_speed: 0.1,
+            // a few times the near plane's half diagonal at the widest field of view
+            terrain_clearance: 0.25,
+            // short of straight up or down, where yaw stops meaning anything
+            max_pitch: 89.0,
+            roll_speed: 3.0,
+            level_duration: 0.4,
+            projection_transition_duration: 0.6,
+        }
+    }
+}
+
+impl CameraConfig {
+    // `fov` is the vertical field of view in degrees, only used in perspective mode.
+    pub fn projection(&self, fov: f32) -> Projection {
+        if self.orthographic {
+            Projection::Orthographic(OrthographicProjection {
+                // the camera sits right above the ground, so also draw what's
+                // behind it, otherwise the terrain it stands on gets cut in half
+                near: -self.far,
+                far: self.far,
+                scaling_mode: ScalingMode::FixedVertical(self.ortho_height),
+                ..default()
+            })
+        } else {
+            Projection::Perspective(PerspectiveProjection {
+                near: self.near,
+                far: self.far,
+                fov: fov.to_radians(),
+                ..default()
+            })
+        }
+    }
+}
+
+// Perspective can't turn into orthographic, but it can get close: narrowing the
+// field of view while backing away keeps things at one distance the same size
+// and flattens everything else, until it's hard to tell from orthographic. The
+// switch is hidden at the narrow end. The distance kept the same size is where
+// the orthographic view's height fits the normal field of view exactly, so
+// the cut to orthographic doesn't change the size of anything there.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct ProjectionTransition {
+    pub active: bool,
+    // 0.0 is the normal perspective view, 1.0 the narrowest one, right before
+    // it turns orthographic
+    pub blend: f32,
+}
+
+// degrees, the field of view at the orthographic end of the transition
+const NARROWEST_FOV: f32 = 2.0;
+
+// The vertical field of view in degrees and how far back along the view the
+// camera is pulled, `blend` of the way into the transition.
+pub fn transition_view(ortho_height: f32, fov: f32, blend: f32) -> (f32, f32) {
+    // distance per unit of visible height, which is what changes evenly
+    let depth = |fov: f32| 1.0 / (2.0 * (fov.to_radians() / 2.0).tan());
+    let (wide, narrow) = (depth(fov), depth(NARROWEST_FOV.min(fov)));
+    let t = blend.clamp(0.0, 1.0);
+    let t = t * t * (3.0 - 2.0 * t);
+    let current = wide + (narrow - wide) * t;
+    let fov = (2.0 * (1.0 / (2.0 * current)).atan()).to_degrees();
+    (fov, ortho_height * (current - wide))
+}
+
+// Swaps between perspective and orthographic with the toggle key, and zooms with
+// the scroll wheel while orthographic. Only the projection is replaced, the camera's
+// transform stays as it is, so it keeps looking the same way. Bevy works out the
+// aspect ratio for either projection whenever the window is resized or the
+// projection changes, so a fresh projection gets the right one straight away.
+// Unless motion is reduced the swap goes through a transition, and the key and
+// zooming do nothing until it's over.
+pub fn toggle_projection(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    settings: Res<Settings>,
+    mut config: ResMut<CameraConfig>,
+    mut transition: ResMut<ProjectionTransition>,
+    mut scroll: EventReader<MouseWheel>,
+    mut camera: Query<&mut Projection, With<Camera3d>>,
+) {
+    let zoom: f32 = scroll
+        .read()
+        .map(|ev| match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            // roughly one line's worth of pixels
+            MouseScrollUnit::Pixel => ev.y / 100.0,
+        })
+        .sum();
+
+    if transition.active {
+        return;
+    }
+    let toggled = keys.just_pressed(bindings.toggle_projection);
+    if toggled {
+        config.orthographic = !config.orthographic;
+        if config.projection_transition_duration > 0.0 && !settings.reduce_motion {
+            // `update_projection_transition` takes it from here
+            *transition = ProjectionTransition {
+                active: true,
+                blend: if config.orthographic { 0.0 } else { 1.0 },
+            };
+            return;
+        }
+    }
+    let zoomed = config.orthographic && zoom != 0.0;
+    if zoomed {
+        // scrolling up zooms in
+        let height = config.ortho_height * (1.0 - config.zoom_speed).powf(zoom);
+        config.ortho_height = height.clamp(config.min_ortho_height, config.max_ortho_height);
+    }
+    if !toggled && !zoomed {
+        return;
+    }
+    for mut projection in camera.iter_mut() {
+        *projection = config.projection(settings.fov);
+    }
+}
+
+// Runs the dolly zoom between perspective and orthographic. The camera is
+// pulled back after everything else has put it where it goes this frame, which
+// the player puts back every frame, so the pull doesn't add up.
+pub fn update_projection_transition(
+    time: Res<Time>,
+    config: Res<CameraConfig>,
+    settings: Res<Settings>,
+    mut transition: ResMut<ProjectionTransition>,
+    mut camera: Query<(&mut Transform, &mut Projection), With<Camera3d>>,
+) {
+    if !transition.active {
+        return;
+    }
+    let step = time.delta_seconds() / config.projection_transition_duration.max(f32::EPSILON);
+    let (target, blend) = if config.orthographic {
+        (1.0, (transition.blend + step).min(1.0))
+    } else {
+        (0.0, (transition.blend - step).max(0.0))
+    };
+    transition.blend = blend;
+    let Ok((mut transform, mut projection)) = camera.get_single_mut() else {
+        return;
+    };
+    if blend == target {
+        transition.active = false;
+        *projection = config.projection(settings.fov);
+        return;
+    }
+    let (fov, pullback) = transition_view(config.ortho_height, settings.fov, blend);
+    *projection = Projection::Perspective(PerspectiveProjection {
+        near: config.near,
+        // whatever the camera saw before it backed away is still in front of it
+        far: config.far + pullback,
+        fov: fov.to_radians(),
+        ..default()
+    });
+    let back = transform.back().as_vec3();
+    transform.translation += back * pullback;
+}
+
+// Something for the camera to turn towards, for intros and cutscenes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LookTarget {
+    Point(Vec3),
+    // follows the entity as it moves
+    Entity(Entity),
+}
+
+// While there's a target the camera turns towards it on its own and mouse look
+// is ignored. Clearing it hands the camera back from wherever it's pointing, so
+// there's no jump.
+#[derive(Resource, Debug, Clone)]
+pub struct CameraTarget {
+    pub target: Option<LookTarget>,
+    // how quickly the camera turns, higher is faster. About a third of the
+    // remaining turn is left after 1 / speed seconds.
+    pub speed: f32,
+}
+
+impl Default for CameraTarget {
+    fn default() -> Self {
+        CameraTarget {
+            target: None,
+            speed: 2.0,
+        }
+    }
+}
+
+impl CameraTarget {
+    pub fn is_active(&self) -> bool {
+        self.target.is_some()
+    }
+}
+
+pub fn look_at_target(
+    time: Res<Time>,
+    target: Res<CameraTarget>,
+    targets: Query<&GlobalTransform>,
+    mut camera: Query<&mut Transform, With<Camera3d>>,
+) {
+    let Some(look_target) = target.target else {
+        return;
+    };
+    let point = match look_target {
+        LookTarget::Point(point) => point,
+        LookTarget::Entity(entity) => match targets.get(entity) {
+            Ok(transform) => transform.translation(),
+            Err(_) => return,
+        },
+    };
+    let Ok(mut camera) = camera.get_single_mut() else {
+        return;
+    };
+    if camera.translation.distance_squared(point) < f32::EPSILON {
+        return;
+    }
+    let wanted = camera.looking_at(point, Vec3::Y).rotation;
+    // frame rate independent easing
+    let t = 1.0 - (-target.speed * time.delta_seconds()).exp();
+    camera.rotation = camera.rotation.slerp(wanted, t);
+}
+
+// Locks the camera onto whatever's under the crosshair when the look at key is
+// pressed, and lets go when it's pressed again. The terrain is looked at where
+// it was hit, anything else is followed as it moves.
+pub fn look_at_crosshair(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    context: Res<RapierContext>,
+    mut target: ResMut<CameraTarget>,
+    camera: Query<&Transform, With<Camera3d>>,
+    player: Query<Entity, With<Player>>,
+    mountain: Query<(), With<Mountain>>,
+) {
+    if !keys.just_pressed(bindings.look_at) {
+        return;
+    }
+    if target.is_active() {
+        target.target = None;
+        return;
+    }
+    let (Ok(camera), Ok(player)) = (camera.get_single(), player.get_single()) else {
+        return;
+    };
+    let direction = camera.forward().as_vec3();
+    let filter = QueryFilter::default()
+        .exclude_collider(player)
+        .exclude_sensors();
+    let Some((hit, distance)) =
+        context.cast_ray(camera.translation, direction, LOOK_AT_REACH, true, filter)
+    else {
+        return;
+    };
+    target.target = Some(if mountain.contains(hit) {
+        LookTarget::Point(camera.translation + direction * distance)
+    } else {
+        LookTarget::Entity(hit)
+    });
+}
+
+// An animation bringing the camera's pitch and roll back to zero, started with
+// the level camera key. Yaw is left alone, so the mouse can still turn while it plays.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct LevelCamera {
+    // pitch and roll when the key was pressed, in radians
+    pub start_pitch: f32,
+    pub start_roll: f32,
+    // seconds since the key was pressed, None when not leveling
+    pub elapsed: Option<f32>,
+}
+
+// Levels the camera when its key is pressed, straight away with Shift held.
+pub fn level_camera(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    config: Res<CameraConfig>,
+    mut level: ResMut<LevelCamera>,
+    mut camera: Query<&mut Transform, With<Camera3d>>,
+) {
+    let Ok(mut camera) = camera.get_single_mut() else {
+        return;
+    };
+    let (yaw, pitch, roll) = camera.rotation.to_euler(EulerRot::YXZ);
+    if keys.just_pressed(bindings.level_camera) {
+        let snap = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        if snap || config.level_duration <= 0.0 {
+            camera.rotation = Quat::from_rotation_y(yaw);
+            level.elapsed = None;
+            return;
+        }
+        *level = LevelCamera {
+            start_pitch: pitch,
+            start_roll: roll,
+            elapsed: Some(0.0),
+        };
+    }
+    let Some(elapsed) = level.elapsed.as_mut() else {
+        return;
+    };
+    *elapsed += time.delta_seconds();
+    let t = (*elapsed / config.level_duration).min(1.0);
+    // eases out and in again so it doesn't jerk at either end
+    let remaining = 1.0 - t * t * (3.0 - 2.0 * t);
+    camera.rotation = Quat::from_euler(
+        EulerRot::YXZ,
+        yaw,
+        level.start_pitch * remaining,
+        level.start_roll * remaining,
+    );
+    if t >= 1.0 {
+        level.elapsed = None;
+    }
+}
+
+// A quick narrowing of the field of view on hard landings that springs back.
+// It's only on top of the field of view from the settings, nothing else changes.
+#[derive(Resource, Debug, Clone)]
+pub struct FovKickConfig {
+    // landings slower than this, in units per second, don't kick
+    pub min_speed: f32,
+    // degrees the view narrows per unit per second of landing speed above the minimum
+    pub degrees_per_speed: f32,
+    pub max_degrees: f32,
+    // how hard the spring pulls back to the normal field of view, higher is snappier
+    pub stiffness: f32,
+    // how quickly the spring settles, too low and it wobbles past the normal view
+    pub damping: f32,
+}
+
+impl Default for FovKickConfig {
+    fn default() -> Self {
+        FovKickConfig {
+            min_speed: 4.0,
+            degrees_per_speed: 0.8,
+            max_degrees: 8.0,
+            stiffness: 150.0,
+            // a little under critical damping, 2 * sqrt(stiffness), for a small bounce
+            damping: 20.0,
+        }
+    }
+}
+
+// The spring's state: degrees off the normal field of view and how fast that's changing.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct FovKick {
+    pub offset: f32,
+    pub velocity: f32,
+}
+
+pub fn update_fov_kick(
+    time: Res<Time>,
+    config: Res<FovKickConfig>,
+    settings: Res<Settings>,
+    (camera_config, transition): (Res<CameraConfig>, Res<ProjectionTransition>),
+    mut kick: ResMut<FovKick>,
+    mut landings: EventReader<PlayerLanded>,
+    mut camera: Query<&mut Projection, With<Camera3d>>,
+) {
+    if settings.reduce_motion {
+        landings.clear();
+        *kick = FovKick::default();
+    }
+    for landing in landings.read() {
+        let over = landing.speed - config.min_speed;
+        if over > 0.0 {
+            kick.offset = (kick.offset - over * config.degrees_per_speed).max(-config.max_degrees);
+        }
+    }
+    let dt = time.delta_seconds();
+    if kick.offset != 0.0 || kick.velocity != 0.0 {
+        let kick = &mut *kick;
+        kick.velocity += (-config.stiffness * kick.offset - config.damping * kick.velocity) * dt;
+        kick.offset += kick.velocity * dt;
+        // settled, stop touching the projection
+        if kick.offset.abs() < 0.01 && kick.velocity.abs() < 0.01 {
+            *kick = FovKick::default();
+        }
+    }
+
+    // the transition has the field of view until it's done
+    if camera_config.orthographic || transition.active {
+        return;
+    }
+    let fov = (settings.fov + kick.offset).to_radians();
+    for mut projection in camera.iter_mut() {
+        if let Projection::Perspective(perspective) = &*projection {
+            if perspective.fov != fov {
+                if let Projection::Perspective(perspective) = &mut *projection {
+                    perspective.fov = fov;
+                }
+            }
+        }
+    }
+}
+
+// The camera sits inside the player's collider, which normally keeps it clear
+// of the ground, but standing against a steep face the view can still get
+// close enough for the near plane to clip into it. Casts a short ray along the
+// view and pulls the camera back from whatever it hits, and warns if the camera
+// ends up under the terrain anyway.
+pub fn keep_camera_out_of_terrain(
+    config: Res<CameraConfig>,
+    heights: Res<TerrainHeights>,
+    context: Res<RapierContext>,
+    player: Query<Entity, With<Player>>,
+    mut camera: Query<&mut Transform, With<Camera3d>>,
+    mut was_inside: Local<bool>,
+) {
+    if config.orthographic {
+        return;
+    }
+    let (Ok(player), Ok(mut camera)) = (player.get_single(), camera.get_single_mut()) else {
+        return;
+    };
+    let forward = camera.forward().as_vec3();
+    let filter = QueryFilter::default()
+        .exclude_collider(player)
+        .exclude_sensors();
+    if let Some((_, distance)) = context.cast_ray(
+        camera.translation,
+        forward,
+        config.terrain_clearance,
+        true,
+        filter,
+    ) {
+        camera.translation -= forward * (config.terrain_clearance - distance);
+    }
+
+    let position = camera.translation;
+    let inside = heights
+        .height_at(position.x, position.z)
+        .is_some_and(|ground| position.y < ground);
+    // only once per time it happens, not every frame
+    if inside && !*was_inside {
+        warn!("the camera is inside the terrain at {}", position);
+    }
+    *was_inside = inside;
+}
+
+// The camera's rotation after turning by `look` (radians of yaw in x, pitch in y)
+// and rolling by `roll` radians. The rotation is taken apart into yaw, pitch and
+// roll every time rather than kept separately, so whatever else sets the camera's
+// rotation (looking at a target, teleporting) is picked up. Yaw turns around the
+// world's up however the camera is rolled, and roll is applied last so it stays
+// put when pitch hits its limit. Without `allow_roll` the camera is level.
+pub fn look_rotation(
+    rotation: Quat,
+    look: Vec2,
+    roll: f32,
+    allow_roll: bool,
+    config: &CameraConfig,
+) -> Quat {
+    let (yaw, pitch, current_roll) = rotation.to_euler(EulerRot::YXZ);
+    let max_pitch = config.max_pitch.to_radians();
+    let roll = if allow_roll { current_roll + roll } else { 0.0 };
+    Quat::from_euler(
+        EulerRot::YXZ,
+        yaw - look.x,
+        (pitch - look.y).clamp(-max_pitch, max_pitch),
+        roll,
+    )
+}
+
+// Adds up a frame's worth of mouse motion, limiting its length to `max` while
+// keeping its direction.
+pub fn look_delta(deltas: impl IntoIterator<Item = Vec2>, max: f32) -> Vec2 {
+    deltas
+        .into_iter()
+        .fold(Vec2::ZERO, |total, delta| total + delta)
+        .clamp_length_max(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hitch_look_delta_is_clamped() {
+        let max = CameraConfig::default().max_look_delta;
+        // a long hitch's worth of fast mouse movement
+        let delta = look_delta(vec![Vec2::new(30.0, -40.0); 100], max);
+        assert!((delta.length() - max).abs() < 1e-3);
+        // the direction is kept
+        assert!((delta.normalize() - Vec2::new(0.6, -0.8)).length() < 1e-5);
+        // normal movement goes through untouched
+        assert_eq!(
+            look_delta([Vec2::new(3.0, 4.0), Vec2::new(1.0, 0.0)], max),
+            Vec2::new(4.0, 4.0)
+        );
+    }
+
+    #[test]
+    fn transition_keeps_the_ortho_height_in_view() {
+        let (ortho_height, fov) = (500.0, 70.0);
+        let (start_fov, start_pullback) = transition_view(ortho_height, fov, 0.0);
+        assert!((start_fov - fov).abs() < 1e-4 && start_pullback.abs() < 1e-3);
+        // at the distance where the normal field of view shows the orthographic
+        // view's height, the same height stays in view all the way through
+        let distance = ortho_height / (2.0 * (fov.to_radians() / 2.0).tan());
+        for blend in [0.25, 0.5, 0.75, 1.0] {
+            let (fov, pullback) = transition_view(ortho_height, fov, blend);
+            let visible = 2.0 * (distance + pullback) * (fov.to_radians() / 2.0).tan();
+            assert!(
+                (visible - ortho_height).abs() < 1e-2,
+                "{} at {}",
+                visible,
+                blend
+            );
+        }
+        assert!((transition_view(ortho_height, fov, 1.0).0 - NARROWEST_FOV).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,162 @@
+//! Shadows of clouds drifting over the terrain. There are no actual clouds, the
+//! mountain shader scrolls a tiling noise texture along the wind with Bevy's
+//! global time and darkens the ground wherever the noise is above a threshold.
+//! The noise is the same every run, so the shadows are too.
+
+use bevy::prelude::*;
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{Mountain, MountainMaterial};
+
+#[derive(Resource, Debug, Clone)]
+pub struct CloudConfig {
+    // direction and speed the clouds drift in, world units per second on the xz plane
+    pub wind: Vec2,
+    // world units before the cloud pattern repeats
+    pub scale: f32,
+    // fraction of the sky covered in clouds, 0.0 to 1.0
+    pub coverage: f32,
+    // how blurry the edges of the shadows are, in noise values
+    pub softness: f32,
+    // how much a cloud darkens the ground under it, 0.0 to 1.0
+    pub darkness: f32,
+}
+
+impl Default for CloudConfig {
+    fn default() -> Self {
+        CloudConfig {
+            wind: Vec2::new(4.0, 1.5),
+            scale: 600.0,
+            coverage: 0.35,
+            softness: 0.12,
+            darkness: 0.3,
+        }
+    }
+}
+
+pub use uniform::CloudUniform;
+
+// ShaderType's generated size checks count as dead code in test builds, see
+// height_gradient.rs
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    // The config as the shader sees it.
+    #[derive(ShaderType, Debug, Clone, Copy, Default, PartialEq)]
+    pub struct CloudUniform {
+        pub wind: Vec2,
+        pub scale: f32,
+        pub coverage: f32,
+        pub softness: f32,
+        pub darkness: f32,
+    }
+}
+
+impl CloudConfig {
+    pub fn uniform(&self) -> CloudUniform {
+        CloudUniform {
+            wind: self.wind,
+            scale: self.scale.max(f32::EPSILON),
+            coverage: self.coverage.clamp(0.0, 1.0),
+            softness: self.softness.max(0.0),
+            darkness: self.darkness.clamp(0.0, 1.0),
+        }
+    }
+}
+
+const NOISE_SIZE: usize = 256;
+// cells of the coarsest octave across the texture
+const NOISE_CELLS: usize = 4;
+const NOISE_OCTAVES: u32 = 4;
+
+// Tiling value noise, several octaves of it, from 0.0 to 1.0 spread over 0 to 255.
+fn cloud_noise() -> Vec<u8> {
+    // the same clouds every run
+    let mut rng = ChaCha8Rng::seed_from_u64(0xc10d5);
+    let mut noise = vec![0.0f32; NOISE_SIZE * NOISE_SIZE];
+    let mut amplitude = 1.0;
+    for octave in 0..NOISE_OCTAVES {
+        let cells = NOISE_CELLS << octave;
+        let lattice: Vec<f32> = (0..cells * cells).map(|_| rng.gen()).collect();
+        // wraps around so the texture tiles
+        let value = |x: usize, y: usize| lattice[(y % cells) * cells + x % cells];
+        for y in 0..NOISE_SIZE {
+            for x in 0..NOISE_SIZE {
+                let fx = x as f32 * cells as f32 / NOISE_SIZE as f32;
+                let fy = y as f32 * cells as f32 / NOISE_SIZE as f32;
+                let (cx, cy) = (fx as usize, fy as usize);
+                // smoothstep between the lattice points hides the grid
+                let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+                let (tx, ty) = (smooth(fx.fract()), smooth(fy.fract()));
+                let near = value(cx, cy) * (1.0 - tx) + value(cx + 1, cy) * tx;
+                let far = value(cx, cy + 1) * (1.0 - tx) + value(cx + 1, cy + 1) * tx;
+                noise[y * NOISE_SIZE + x] += (near * (1.0 - ty) + far * ty) * amplitude;
+            }
+        }
+        amplitude *= 0.5;
+    }
+    // octaves pile up towards the middle, stretch the result back out to 0.0 to 1.0
+    let (min, max) = noise.iter().fold((f32::MAX, f32::MIN), |(min, max), &n| {
+        (min.min(n), max.max(n))
+    });
+    let range = (max - min).max(f32::EPSILON);
+    noise
+        .iter()
+        .map(|&n| ((n - min) / range * 255.0) as u8)
+        .collect()
+}
+
+#[derive(Resource)]
+pub struct CloudTexture {
+    pub image: Handle<Image>,
+}
+
+impl FromWorld for CloudTexture {
+    fn from_world(world: &mut World) -> Self {
+        let mut image = Image::new(
+            Extent3d {
+                width: NOISE_SIZE as u32,
+                height: NOISE_SIZE as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            cloud_noise(),
+            TextureFormat::R8Unorm,
+            RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+            address_mode_u: ImageAddressMode::Repeat,
+            address_mode_v: ImageAddressMode::Repeat,
+            ..ImageSamplerDescriptor::linear()
+        });
+        CloudTexture {
+            image: world.resource_mut::<Assets<Image>>().add(image),
+        }
+    }
+}
+
+// Passes config changes on to the terrain. The drifting itself happens in the
+// shader, so nothing needs updating every frame.
+pub fn apply_cloud_config(
+    config: Res<CloudConfig>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let uniform = config.uniform();
+    for handle in mountain.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.clouds = uniform;
+        }
+    }
+}
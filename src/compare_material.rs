@@ -0,0 +1,98 @@
+//! Swapping the terrain between its own shader and Bevy's `StandardMaterial`,
+//! for checking the shader against plain PBR lighting. Only the material is
+//! swapped, the mesh and collider stay, and both materials are kept around so
+//! switching back and forth is instant.
+
+use bevy::prelude::*;
+
+use crate::controls::KeyBindings;
+use crate::height_gradient::HeightGradient;
+use crate::{Mountain, MountainCopy, MountainMaterial};
+
+#[derive(Resource)]
+pub struct ComparisonMaterial {
+    pub standard: Handle<StandardMaterial>,
+    // the terrain's own material, kept while the standard one is in use
+    pub mountain: Handle<MountainMaterial>,
+    pub using_standard: bool,
+}
+
+impl ComparisonMaterial {
+    // A flat colored stand in for the mountain material, in the middle color of
+    // the height gradient.
+    pub fn new(
+        mountain: Handle<MountainMaterial>,
+        gradient: &HeightGradient,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> ComparisonMaterial {
+        let mut stops = gradient.stops.clone();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let base_color = stops
+            .get(stops.len() / 2)
+            .map_or(Color::srgb(0.4, 0.5, 0.3), |stop| stop.1);
+        ComparisonMaterial {
+            standard: materials.add(StandardMaterial {
+                base_color,
+                perceptual_roughness: 0.9,
+                ..default()
+            }),
+            mountain,
+            using_standard: false,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        if self.using_standard {
+            "Terrain material: StandardMaterial"
+        } else {
+            "Terrain material: MountainMaterial"
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct MaterialText {}
+
+#[allow(clippy::type_complexity)]
+pub fn toggle_terrain_material(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    comparison: Option<ResMut<ComparisonMaterial>>,
+    terrain: Query<Entity, Or<(With<Mountain>, With<MountainCopy>)>>,
+    mut text: Query<&mut Text, With<MaterialText>>,
+) {
+    let Some(mut comparison) = comparison else {
+        return;
+    };
+    if !keys.just_pressed(bindings.toggle_material) {
+        return;
+    }
+    comparison.using_standard = !comparison.using_standard;
+    for entity in terrain.iter() {
+        let mut entity = commands.entity(entity);
+        if comparison.using_standard {
+            entity
+                .remove::<Handle<MountainMaterial>>()
+                .insert(comparison.standard.clone());
+        } else {
+            entity
+                .remove::<Handle<StandardMaterial>>()
+                .insert(comparison.mountain.clone());
+        }
+    }
+
+    if let Ok(mut text) = text.get_single_mut() {
+        text.sections[0].value = comparison.label().to_string();
+    } else {
+        commands.spawn((
+            TextBundle::from_section(comparison.label(), TextStyle::default()).with_style(Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(40.0),
+                left: Val::Px(12.0),
+                ..default()
+            }),
+            MaterialText {},
+        ));
+    }
+}
@@ -0,0 +1,92 @@
+//! Contour lines drawn by the terrain shader, like on a topographic map: one
+//! wherever the ground crosses a multiple of `interval` in height. They make the
+//! slopes easy to read at a glance, closely packed lines being steep ground.
+//! Like the surface grid they're anti-aliased with screen space derivatives, so
+//! they stay `line_width` pixels wide however steep or far away the ground is.
+
+use bevy::prelude::*;
+
+use crate::controls::KeyBindings;
+use crate::{Mountain, MountainMaterial};
+
+#[derive(Resource, Debug, Clone)]
+pub struct ContourConfig {
+    pub enabled: bool,
+    // world units of height between lines
+    pub interval: f32,
+    // in pixels on screen
+    pub line_width: f32,
+    // the alpha is how strongly the lines cover the ground
+    pub color: Color,
+}
+
+impl Default for ContourConfig {
+    fn default() -> Self {
+        ContourConfig {
+            enabled: false,
+            interval: 5.0,
+            line_width: 1.0,
+            color: Color::srgba(0.35, 0.2, 0.1, 0.7),
+        }
+    }
+}
+
+pub use uniform::ContourUniform;
+
+// ShaderType's generated size checks count as dead code in test builds, see
+// height_gradient.rs
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    // The config as the shader sees it.
+    #[derive(ShaderType, Debug, Clone, Copy, Default, PartialEq)]
+    pub struct ContourUniform {
+        // linear rgb, and the alpha is 0.0 while the lines are off
+        pub color: Vec4,
+        pub interval: f32,
+        pub line_width: f32,
+    }
+}
+
+impl ContourConfig {
+    pub fn uniform(&self) -> ContourUniform {
+        let mut color = self.color.to_linear().to_vec4();
+        if !self.enabled {
+            color.w = 0.0;
+        }
+        ContourUniform {
+            color,
+            interval: self.interval.max(f32::EPSILON),
+            line_width: self.line_width.max(0.0),
+        }
+    }
+}
+
+pub fn toggle_contours(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut config: ResMut<ContourConfig>,
+) {
+    if keys.just_pressed(bindings.toggle_contours) {
+        config.enabled = !config.enabled;
+    }
+}
+
+// Passes config changes on to the terrain.
+pub fn apply_contours(
+    config: Res<ContourConfig>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let uniform = config.uniform();
+    for handle in mountain.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.contours = uniform;
+        }
+    }
+}
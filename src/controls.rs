@@ -0,0 +1,442 @@
+//! Keys for every action, kept in one place so they're easy to find and rebind.
+//! They're saved with the rest of the settings.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub forward: KeyCode,
+    pub left: KeyCode,
+    pub back: KeyCode,
+    pub right: KeyCode,
+    pub jump: KeyCode,
+    pub roll_left: KeyCode,
+    pub roll_right: KeyCode,
+    pub auto_walk: KeyCode,
+    pub pause: KeyCode,
+    pub toggle_grid: KeyCode,
+    pub toggle_movement_gizmos: KeyCode,
+    pub toggle_recording: KeyCode,
+    pub toggle_projection: KeyCode,
+    pub dump_mesh_stats: KeyCode,
+    pub toggle_axes: KeyCode,
+    pub toggle_physics_debug: KeyCode,
+    pub teleport: KeyCode,
+    pub sun_lock: KeyCode,
+    pub toggle_cross_section: KeyCode,
+    pub measure: KeyCode,
+    pub freeze_time: KeyCode,
+    pub spawn_cube: KeyCode,
+    pub toggle_material: KeyCode,
+    pub path_tool: KeyCode,
+    pub brush_mode: KeyCode,
+    pub flatten: KeyCode,
+    pub level_camera: KeyCode,
+    pub look_at: KeyCode,
+    pub flythrough: KeyCode,
+    pub flythrough_keyframe: KeyCode,
+    pub toggle_surface_grid: KeyCode,
+    pub reload_material: KeyCode,
+    pub toggle_normals: KeyCode,
+    pub next_preset: KeyCode,
+    pub toggle_faceted: KeyCode,
+    pub toggle_contours: KeyCode,
+    pub save_preset: KeyCode,
+    pub toggle_trimesh: KeyCode,
+    pub toggle_shadows: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            forward: KeyCode::KeyW,
+            left: KeyCode::KeyA,
+            back: KeyCode::KeyS,
+            right: KeyCode::KeyD,
+            jump: KeyCode::Space,
+            roll_left: KeyCode::KeyQ,
+            roll_right: KeyCode::KeyE,
+            auto_walk: KeyCode::KeyX,
+            pause: KeyCode::Escape,
+            toggle_grid: KeyCode::KeyG,
+            toggle_movement_gizmos: KeyCode::F3,
+            toggle_recording: KeyCode::KeyR,
+            toggle_projection: KeyCode::KeyP,
+            dump_mesh_stats: KeyCode::F4,
+            toggle_axes: KeyCode::F5,
+            toggle_physics_debug: KeyCode::F6,
+            teleport: KeyCode::KeyT,
+            sun_lock: KeyCode::F7,
+            toggle_cross_section: KeyCode::F8,
+            measure: KeyCode::KeyM,
+            freeze_time: KeyCode::F9,
+            spawn_cube: KeyCode::KeyB,
+            toggle_material: KeyCode::F10,
+            path_tool: KeyCode::KeyN,
+            brush_mode: KeyCode::KeyV,
+            flatten: KeyCode::KeyF,
+            level_camera: KeyCode::KeyH,
+            look_at: KeyCode::KeyO,
+            flythrough: KeyCode::KeyK,
+            flythrough_keyframe: KeyCode::KeyJ,
+            toggle_surface_grid: KeyCode::F2,
+            reload_material: KeyCode::F11,
+            toggle_normals: KeyCode::F1,
+            next_preset: KeyCode::F12,
+            toggle_faceted: KeyCode::KeyL,
+            toggle_contours: KeyCode::KeyC,
+            save_preset: KeyCode::KeyU,
+            toggle_trimesh: KeyCode::KeyI,
+            toggle_shadows: KeyCode::KeyY,
+        }
+    }
+}
+
+// Everything that can be rebound, so the options menu can list them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Forward,
+    Left,
+    Back,
+    Right,
+    Jump,
+    RollLeft,
+    RollRight,
+    AutoWalk,
+    Pause,
+    ToggleGrid,
+    ToggleMovementGizmos,
+    ToggleRecording,
+    ToggleProjection,
+    DumpMeshStats,
+    ToggleAxes,
+    TogglePhysicsDebug,
+    Teleport,
+    SunLock,
+    ToggleCrossSection,
+    Measure,
+    FreezeTime,
+    SpawnCube,
+    ToggleMaterial,
+    PathTool,
+    BrushMode,
+    Flatten,
+    LevelCamera,
+    LookAt,
+    Flythrough,
+    FlythroughKeyframe,
+    ToggleSurfaceGrid,
+    ReloadMaterial,
+    ToggleNormals,
+    NextPreset,
+    ToggleFaceted,
+    ToggleContours,
+    SavePreset,
+    ToggleTrimesh,
+    ToggleShadows,
+}
+
+impl Action {
+    pub const ALL: [Action; 39] = [
+        Action::Forward,
+        Action::Left,
+        Action::Back,
+        Action::Right,
+        Action::Jump,
+        Action::RollLeft,
+        Action::RollRight,
+        Action::AutoWalk,
+        Action::Pause,
+        Action::ToggleGrid,
+        Action::ToggleMovementGizmos,
+        Action::ToggleRecording,
+        Action::ToggleProjection,
+        Action::DumpMeshStats,
+        Action::ToggleAxes,
+        Action::TogglePhysicsDebug,
+        Action::Teleport,
+        Action::SunLock,
+        Action::ToggleCrossSection,
+        Action::Measure,
+        Action::FreezeTime,
+        Action::SpawnCube,
+        Action::ToggleMaterial,
+        Action::PathTool,
+        Action::BrushMode,
+        Action::Flatten,
+        Action::LevelCamera,
+        Action::LookAt,
+        Action::Flythrough,
+        Action::FlythroughKeyframe,
+        Action::ToggleSurfaceGrid,
+        Action::ReloadMaterial,
+        Action::ToggleNormals,
+        Action::NextPreset,
+        Action::ToggleFaceted,
+        Action::ToggleContours,
+        Action::SavePreset,
+        Action::ToggleTrimesh,
+        Action::ToggleShadows,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Forward => "Forward",
+            Action::Left => "Left",
+            Action::Back => "Back",
+            Action::Right => "Right",
+            Action::Jump => "Jump",
+            Action::RollLeft => "Roll left",
+            Action::RollRight => "Roll right",
+            Action::AutoWalk => "Auto-walk",
+            Action::Pause => "Options",
+            Action::ToggleGrid => "Toggle grid",
+            Action::ToggleMovementGizmos => "Toggle movement gizmos",
+            Action::ToggleRecording => "Record ghost",
+            Action::ToggleProjection => "Toggle orthographic",
+            Action::DumpMeshStats => "Log terrain stats",
+            Action::ToggleAxes => "Toggle axes",
+            Action::TogglePhysicsDebug => "Toggle colliders",
+            Action::Teleport => "Teleport",
+            Action::SunLock => "Lock sun to view",
+            Action::ToggleCrossSection => "Toggle cross-section",
+            Action::Measure => "Measure",
+            Action::FreezeTime => "Freeze time",
+            Action::SpawnCube => "Throw a cube",
+            Action::ToggleMaterial => "Toggle terrain material",
+            Action::PathTool => "Path tool",
+            Action::BrushMode => "Brush mode",
+            Action::Flatten => "Flatten tool",
+            Action::LevelCamera => "Level camera",
+            Action::LookAt => "Look at crosshair",
+            Action::Flythrough => "Play flythrough",
+            Action::FlythroughKeyframe => "Flythrough keyframe",
+            Action::ToggleSurfaceGrid => "Toggle surface grid",
+            Action::ReloadMaterial => "Reload terrain material",
+            Action::ToggleNormals => "Toggle terrain normals",
+            Action::NextPreset => "Next terrain preset",
+            Action::ToggleFaceted => "Toggle faceted shading",
+            Action::ToggleContours => "Toggle contour lines",
+            Action::SavePreset => "Save terrain preset",
+            Action::ToggleTrimesh => "Toggle trimesh collider",
+            Action::ToggleShadows => "Toggle real-time shadows",
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::Forward => self.forward,
+            Action::Left => self.left,
+            Action::Back => self.back,
+            Action::Right => self.right,
+            Action::Jump => self.jump,
+            Action::RollLeft => self.roll_left,
+            Action::RollRight => self.roll_right,
+            Action::AutoWalk => self.auto_walk,
+            Action::Pause => self.pause,
+            Action::ToggleGrid => self.toggle_grid,
+            Action::ToggleMovementGizmos => self.toggle_movement_gizmos,
+            Action::ToggleRecording => self.toggle_recording,
+            Action::ToggleProjection => self.toggle_projection,
+            Action::DumpMeshStats => self.dump_mesh_stats,
+            Action::ToggleAxes => self.toggle_axes,
+            Action::TogglePhysicsDebug => self.toggle_physics_debug,
+            Action::Teleport => self.teleport,
+            Action::SunLock => self.sun_lock,
+            Action::ToggleCrossSection => self.toggle_cross_section,
+            Action::Measure => self.measure,
+            Action::FreezeTime => self.freeze_time,
+            Action::SpawnCube => self.spawn_cube,
+            Action::ToggleMaterial => self.toggle_material,
+            Action::PathTool => self.path_tool,
+            Action::BrushMode => self.brush_mode,
+            Action::Flatten => self.flatten,
+            Action::LevelCamera => self.level_camera,
+            Action::LookAt => self.look_at,
+            Action::Flythrough => self.flythrough,
+            Action::FlythroughKeyframe => self.flythrough_keyframe,
+            Action::ToggleSurfaceGrid => self.toggle_surface_grid,
+            Action::ReloadMaterial => self.reload_material,
+            Action::ToggleNormals => self.toggle_normals,
+            Action::NextPreset => self.next_preset,
+            Action::ToggleFaceted => self.toggle_faceted,
+            Action::ToggleContours => self.toggle_contours,
+            Action::SavePreset => self.save_preset,
+            Action::ToggleTrimesh => self.toggle_trimesh,
+            Action::ToggleShadows => self.toggle_shadows,
+        }
+    }
+
+    pub fn set_key(&mut self, action: Action, key: KeyCode) {
+        let slot = match action {
+            Action::Forward => &mut self.forward,
+            Action::Left => &mut self.left,
+            Action::Back => &mut self.back,
+            Action::Right => &mut self.right,
+            Action::Jump => &mut self.jump,
+            Action::RollLeft => &mut self.roll_left,
+            Action::RollRight => &mut self.roll_right,
+            Action::AutoWalk => &mut self.auto_walk,
+            Action::Pause => &mut self.pause,
+            Action::ToggleGrid => &mut self.toggle_grid,
+            Action::ToggleMovementGizmos => &mut self.toggle_movement_gizmos,
+            Action::ToggleRecording => &mut self.toggle_recording,
+            Action::ToggleProjection => &mut self.toggle_projection,
+            Action::DumpMeshStats => &mut self.dump_mesh_stats,
+            Action::ToggleAxes => &mut self.toggle_axes,
+            Action::TogglePhysicsDebug => &mut self.toggle_physics_debug,
+            Action::Teleport => &mut self.teleport,
+            Action::SunLock => &mut self.sun_lock,
+            Action::ToggleCrossSection => &mut self.toggle_cross_section,
+            Action::Measure => &mut self.measure,
+            Action::FreezeTime => &mut self.freeze_time,
+            Action::SpawnCube => &mut self.spawn_cube,
+            Action::ToggleMaterial => &mut self.toggle_material,
+            Action::PathTool => &mut self.path_tool,
+            Action::BrushMode => &mut self.brush_mode,
+            Action::Flatten => &mut self.flatten,
+            Action::LevelCamera => &mut self.level_camera,
+            Action::LookAt => &mut self.look_at,
+            Action::Flythrough => &mut self.flythrough,
+            Action::FlythroughKeyframe => &mut self.flythrough_keyframe,
+            Action::ToggleSurfaceGrid => &mut self.toggle_surface_grid,
+            Action::ReloadMaterial => &mut self.reload_material,
+            Action::ToggleNormals => &mut self.toggle_normals,
+            Action::NextPreset => &mut self.next_preset,
+            Action::ToggleFaceted => &mut self.toggle_faceted,
+            Action::ToggleContours => &mut self.toggle_contours,
+            Action::SavePreset => &mut self.save_preset,
+            Action::ToggleTrimesh => &mut self.toggle_trimesh,
+            Action::ToggleShadows => &mut self.toggle_shadows,
+        };
+        *slot = key;
+    }
+
+    // Binds `key` to `action`. If another action already had that key it gets
+    // `action`'s old one, so no key ever ends up doing two things, and that
+    // action is returned.
+    pub fn rebind(&mut self, action: Action, key: KeyCode) -> Option<Action> {
+        let old = self.key(action);
+        let taken = Action::ALL
+            .into_iter()
+            .find(|&other| other != action && self.key(other) == key);
+        if let Some(other) = taken {
+            self.set_key(other, old);
+        }
+        self.set_key(action, key);
+        taken
+    }
+}
+
+// A short name for a key, "W" rather than "KeyW".
+pub fn key_name(key: KeyCode) -> String {
+    let name = format!("{:?}", key);
+    match name
+        .strip_prefix("Key")
+        .or_else(|| name.strip_prefix("Digit"))
+    {
+        Some(short) => short.to_string(),
+        None => name,
+    }
+}
+
+// Marker for the on screen list of controls.
+#[derive(Component)]
+pub struct ControlsText {}
+
+pub fn controls_text(bindings: &KeyBindings) -> String {
+    format!(
+        "Controls:\nMouse to look around\n{}{}{}{}: movement\n{}: jump\n{}/{}: roll the camera\n{}: auto-walk forward, any movement key stops it\n{}: level the camera (hold Shift to snap)\n{}: keep looking at what's under the crosshair, again to stop\nLeft/right click: raise/lower terrain\n{}: switch to the smoothing brush and back\n{}: flatten a rectangle (click two corners, Enter to flatten)\n{}: toggle grid\n{}: toggle grid lines on the whole terrain\n{}: toggle contour lines\n{}: toggle movement gizmos\n{}: start/stop recording a ghost\n{}: toggle orthographic view (scroll to zoom)\n{}: log terrain stats and slope histogram\n{}: toggle axes\n{}: toggle terrain normal lines\n{}: switch between smooth and faceted shading\n{}: toggle collider wireframes\n{}: switch the terrain's collider between a heightfield and a trimesh\n{}: switch between baked and real-time shadows\n{}: teleport to coordinates\n{}: point the sun where you look, again to release\n{}: toggle terrain cross-section\n{}: measure distances (click two points)\n{}: freeze/resume time\n{}: throw a physics cube\n{}: swap the terrain shader for StandardMaterial\n{}: reload the terrain material from the config\n{}: regenerate the terrain from the next built in preset\n{}: save the terrain and settings to a preset file\n{}: lay out a path (click to add waypoints, right click to undo)\n{}: add a flythrough keyframe (hold Shift to remove the last)\n{}: play/stop the flythrough\n{}: options",
+        key_name(bindings.forward),
+        key_name(bindings.left),
+        key_name(bindings.back),
+        key_name(bindings.right),
+        key_name(bindings.jump),
+        key_name(bindings.roll_left),
+        key_name(bindings.roll_right),
+        key_name(bindings.auto_walk),
+        key_name(bindings.level_camera),
+        key_name(bindings.look_at),
+        key_name(bindings.brush_mode),
+        key_name(bindings.flatten),
+        key_name(bindings.toggle_grid),
+        key_name(bindings.toggle_surface_grid),
+        key_name(bindings.toggle_contours),
+        key_name(bindings.toggle_movement_gizmos),
+        key_name(bindings.toggle_recording),
+        key_name(bindings.toggle_projection),
+        key_name(bindings.dump_mesh_stats),
+        key_name(bindings.toggle_axes),
+        key_name(bindings.toggle_normals),
+        key_name(bindings.toggle_faceted),
+        key_name(bindings.toggle_physics_debug),
+        key_name(bindings.toggle_trimesh),
+        key_name(bindings.toggle_shadows),
+        key_name(bindings.teleport),
+        key_name(bindings.sun_lock),
+        key_name(bindings.toggle_cross_section),
+        key_name(bindings.measure),
+        key_name(bindings.freeze_time),
+        key_name(bindings.spawn_cube),
+        key_name(bindings.toggle_material),
+        key_name(bindings.reload_material),
+        key_name(bindings.next_preset),
+        key_name(bindings.save_preset),
+        key_name(bindings.path_tool),
+        key_name(bindings.flythrough_keyframe),
+        key_name(bindings.flythrough),
+        key_name(bindings.pause),
+    )
+}
+
+pub fn update_controls_text(
+    bindings: Res<KeyBindings>,
+    mut text: Query<&mut Text, With<ControlsText>>,
+) {
+    if !bindings.is_changed() {
+        return;
+    }
+    for mut text in text.iter_mut() {
+        text.sections[0].value = controls_text(&bindings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_keys_unique(bindings: &KeyBindings) {
+        for (i, &action) in Action::ALL.iter().enumerate() {
+            for &other in &Action::ALL[i + 1..] {
+                assert_ne!(
+                    bindings.key(action),
+                    bindings.key(other),
+                    "{} and {} share a key",
+                    action.name(),
+                    other.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rebinding_a_taken_key_swaps() {
+        let mut bindings = KeyBindings::default();
+        assert_keys_unique(&bindings);
+        // jump onto the forward key, forward gets space
+        let swapped = bindings.rebind(Action::Jump, bindings.forward);
+        assert_eq!(swapped, Some(Action::Forward));
+        assert_eq!(bindings.jump, KeyCode::KeyW);
+        assert_eq!(bindings.forward, KeyCode::Space);
+        assert_keys_unique(&bindings);
+        // a free key doesn't move anything else, nor does an action's own key
+        assert_eq!(bindings.rebind(Action::Jump, KeyCode::KeyZ), None);
+        assert_eq!(bindings.rebind(Action::Jump, KeyCode::KeyZ), None);
+        assert_eq!(bindings.jump, KeyCode::KeyZ);
+        assert_keys_unique(&bindings);
+    }
+}
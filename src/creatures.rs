@@ -0,0 +1,213 @@
+//! Small ambient creatures that wander around the walkable parts of the terrain.
+//! Each one idles for a bit, picks a nearby walkable spot it can walk to in a
+//! straight line, walks there and repeats. They're spawned from the terrain seed,
+//! so the same seed gives the same creatures, and the ones far from the player
+//! are hidden and stop thinking.
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::navigation::WalkabilityGrid;
+use crate::terrain::{TerrainHeights, TerrainSeed};
+use crate::Player;
+
+#[derive(Resource, Debug, Clone)]
+pub struct CreatureConfig {
+    pub count: usize,
+    // world units per second
+    pub speed: f32,
+    // how far away a creature looks for its next target
+    pub wander_radius: f32,
+    // seconds spent standing still between walks
+    pub min_idle: f32,
+    pub max_idle: f32,
+    // creatures further than this from the player are hidden and frozen
+    pub cull_distance: f32,
+    pub radius: f32,
+}
+
+impl Default for CreatureConfig {
+    fn default() -> Self {
+        CreatureConfig {
+            count: 12,
+            speed: 1.0,
+            wander_radius: 10.0,
+            min_idle: 0.5,
+            max_idle: 2.5,
+            cull_distance: 60.0,
+            radius: 0.3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WanderState {
+    Idle { remaining: f32 },
+    Walking { target: Vec2 },
+}
+
+#[derive(Component)]
+pub struct Creature {
+    pub state: WanderState,
+    // each creature has its own stream of random numbers so they stay
+    // deterministic regardless of update order
+    rng: ChaCha8Rng,
+}
+
+// tries at finding a walkable spot before giving up for now
+const MAX_TRIES: usize = 8;
+// spacing of the checks along a path that it stays walkable
+const PATH_CHECK_STEP: f32 = 0.5;
+
+fn path_is_walkable(walkability: &WalkabilityGrid, from: Vec2, to: Vec2) -> bool {
+    let steps = (from.distance(to) / PATH_CHECK_STEP).ceil() as usize;
+    (0..=steps).all(|i| {
+        let point = from.lerp(to, i as f32 / steps.max(1) as f32);
+        walkability.is_walkable(point.x, point.y)
+    })
+}
+
+fn random_walkable_spot(walkability: &WalkabilityGrid, rng: &mut ChaCha8Rng) -> Option<Vec2> {
+    let terrain = walkability.bounds();
+    (0..MAX_TRIES * 8)
+        .map(|_| {
+            Vec2::new(
+                rng.gen_range(terrain.min.x..terrain.max.x),
+                rng.gen_range(terrain.min.y..terrain.max.y),
+            )
+        })
+        .find(|spot| walkability.is_walkable(spot.x, spot.y))
+}
+
+impl Creature {
+    fn pick_target(
+        &mut self,
+        config: &CreatureConfig,
+        walkability: &WalkabilityGrid,
+        position: Vec2,
+    ) {
+        let rng = &mut self.rng;
+        let target = (0..MAX_TRIES)
+            .map(|_| {
+                let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                let distance = rng.gen_range(0.2..1.0) * config.wander_radius;
+                position + Vec2::from_angle(angle) * distance
+            })
+            .find(|&target| path_is_walkable(walkability, position, target));
+        self.state = match target {
+            Some(target) => WanderState::Walking { target },
+            None => self.idle(config),
+        };
+    }
+
+    fn idle(&mut self, config: &CreatureConfig) -> WanderState {
+        WanderState::Idle {
+            remaining: self.rng.gen_range(config.min_idle..config.max_idle),
+        }
+    }
+}
+
+pub fn spawn_creatures(
+    mut commands: Commands,
+    config: Res<CreatureConfig>,
+    seed: Res<TerrainSeed>,
+    walkability: Res<WalkabilityGrid>,
+    heights: Res<TerrainHeights>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mesh = meshes.add(Sphere::new(config.radius));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::srgb(0.55, 0.4, 0.25),
+        ..default()
+    });
+    // a different stream than the terrain itself uses
+    let mut rng = ChaCha8Rng::seed_from_u64(seed.0 ^ 0xc0ffee);
+    for _ in 0..config.count {
+        let Some(spot) = random_walkable_spot(&walkability, &mut rng) else {
+            continue;
+        };
+        let Some(height) = heights.height_at(spot.x, spot.y) else {
+            continue;
+        };
+        let mut creature = Creature {
+            state: WanderState::Idle { remaining: 0.0 },
+            rng: ChaCha8Rng::seed_from_u64(rng.gen()),
+        };
+        creature.state = creature.idle(&config);
+        commands.spawn((
+            PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_xyz(spot.x, height + config.radius, spot.y),
+                ..default()
+            },
+            creature,
+        ));
+    }
+}
+
+pub fn wander(
+    time: Res<Time>,
+    config: Res<CreatureConfig>,
+    walkability: Res<WalkabilityGrid>,
+    heights: Res<TerrainHeights>,
+    player: Query<&Transform, (With<Player>, Without<Creature>)>,
+    mut creatures: Query<(&mut Creature, &mut Transform, &mut Visibility)>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+    for (mut creature, mut transform, mut visibility) in creatures.iter_mut() {
+        let position = transform.translation.xz();
+        let near = position.distance(player.translation.xz()) <= config.cull_distance;
+        let new_visibility = if near {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != new_visibility {
+            *visibility = new_visibility;
+        }
+        if !near {
+            continue;
+        }
+        // stay on the surface even while standing still, it can be edited
+        if let Some(height) = heights.height_at(position.x, position.y) {
+            transform.translation.y = height + config.radius;
+        }
+
+        match creature.state {
+            WanderState::Idle { remaining } if remaining > dt => {
+                creature.state = WanderState::Idle {
+                    remaining: remaining - dt,
+                };
+            }
+            WanderState::Idle { .. } => creature.pick_target(&config, &walkability, position),
+            WanderState::Walking { target } => {
+                let step = config.speed * dt;
+                let next = if position.distance(target) <= step {
+                    creature.state = creature.idle(&config);
+                    target
+                } else {
+                    position + (target - position).normalize() * step
+                };
+                // the terrain may have been edited under it since it set off
+                if !walkability.is_walkable(next.x, next.y) {
+                    creature.state = creature.idle(&config);
+                    continue;
+                }
+                let Some(height) = heights.height_at(next.x, next.y) else {
+                    continue;
+                };
+                transform.translation = Vec3::new(next.x, height + config.radius, next.y);
+                let heading = target - position;
+                if let Ok(heading) = Dir3::new(Vec3::new(heading.x, 0.0, heading.y)) {
+                    transform.look_to(heading, Vec3::Y);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Shading by the shape of the ground. Every terrain vertex gets a curvature
+//! from -1.0 to 1.0 in its own vertex attribute, from how far it sits above or
+//! below the ground around it: ridges and bumps are positive, valleys and
+//! hollows negative. The terrain shader darkens the hollows and lightens the
+//! ridges, a cheap stand in for ambient occlusion that makes the shape of the
+//! terrain easier to read. Edits rewrite it along with the mesh.
+
+use bevy::prelude::*;
+use bevy::render::mesh::{MeshVertexAttribute, VertexAttributeValues};
+use bevy::render::render_resource::VertexFormat;
+
+use crate::terrain::{self, TerrainHeights};
+use crate::{Mountain, MountainMaterial};
+
+pub const ATTRIBUTE_CURVATURE: MeshVertexAttribute =
+    MeshVertexAttribute::new("Curvature", 988_540_918, VertexFormat::Float32);
+
+#[derive(Resource, Debug, Clone)]
+pub struct CurvatureConfig {
+    // how many vertices away the surrounding ground is sampled, bigger picks out
+    // bigger shapes and ignores small bumps
+    pub radius: usize,
+    // how far above or below the surrounding ground a vertex has to be for the
+    // full tint, in world units per world unit of distance to the samples
+    pub full_curvature: f32,
+    // fraction the deepest hollows are darkened by, 0.0 to 1.0
+    pub cavity_darkening: f32,
+    // fraction the sharpest ridges are lightened by
+    pub ridge_lightening: f32,
+}
+
+impl Default for CurvatureConfig {
+    fn default() -> Self {
+        CurvatureConfig {
+            radius: 2,
+            full_curvature: 0.15,
+            cavity_darkening: 0.3,
+            ridge_lightening: 0.2,
+        }
+    }
+}
+
+pub use uniform::CurvatureUniform;
+
+// ShaderType's generated size checks count as dead code in test builds, see
+// height_gradient.rs
+#[allow(dead_code)]
+mod uniform {
+    use bevy::render::render_resource::ShaderType;
+
+    // The config as the shader sees it.
+    #[derive(ShaderType, Debug, Clone, Copy, Default, PartialEq)]
+    pub struct CurvatureUniform {
+        pub cavity_darkening: f32,
+        pub ridge_lightening: f32,
+    }
+}
+
+impl CurvatureConfig {
+    pub fn uniform(&self) -> CurvatureUniform {
+        CurvatureUniform {
+            cavity_darkening: self.cavity_darkening.clamp(0.0, 1.0),
+            ridge_lightening: self.ridge_lightening.max(0.0),
+        }
+    }
+}
+
+// How far the vertex at (xi, zi) sits above the average of the four vertices
+// `radius` away along x and z, over the distance to them, scaled so
+// `full_curvature` is 1.0. Vertices near the edge use the edge ones instead of
+// the ones past it.
+fn vertex_curvature(
+    heights: &TerrainHeights,
+    config: &CurvatureConfig,
+    xi: usize,
+    zi: usize,
+) -> f32 {
+    let (x_max, y_max) = (heights.x_max, heights.y_max);
+    let radius = config.radius.max(1);
+    let distance = heights.spacing() * radius as f32;
+    let full = config.full_curvature.max(f32::EPSILON);
+    let height = |xi: usize, zi: usize| heights.heights[heights.index(xi, zi)];
+    let center = height(xi, zi);
+    let along_x =
+        (height(xi.saturating_sub(radius), zi) + height((xi + radius).min(x_max - 1), zi)) / 2.0;
+    let along_z =
+        (height(xi, zi.saturating_sub(radius)) + height(xi, (zi + radius).min(y_max - 1))) / 2.0;
+    let curvature = ((center - along_x) / distance.x + (center - along_z) / distance.y) / 2.0;
+    (curvature / full).clamp(-1.0, 1.0)
+}
+
+// The curvature of every vertex, see `vertex_curvature`.
+pub fn vertex_curvatures(heights: &TerrainHeights, config: &CurvatureConfig) -> Vec<f32> {
+    let mut curvatures = Vec::with_capacity(heights.heights.len());
+    for xi in 0..heights.x_max {
+        for zi in 0..heights.y_max {
+            curvatures.push(vertex_curvature(heights, config, xi, zi));
+        }
+    }
+    curvatures
+}
+
+// Sets the curvature of every vertex of the terrain mesh from the heights. A
+// faceted mesh has a copy of a height's vertex for every triangle around it,
+// each copy gets the curvature of the height it's on.
+pub fn write_curvature(mesh: &mut Mesh, heights: &TerrainHeights, config: &CurvatureConfig) {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    let curvatures = vertex_curvatures(heights, config);
+    if positions.len() == heights.heights.len() {
+        mesh.insert_attribute(ATTRIBUTE_CURVATURE, curvatures);
+        return;
+    }
+    let (spacing, half) = (heights.spacing(), heights.extent / 2.0);
+    let copies: Vec<f32> = positions
+        .iter()
+        .map(|position| {
+            let grid = ((Vec2::new(position[0], position[2]) + half) / spacing).round();
+            let xi = (grid.x.max(0.0) as usize).min(heights.x_max - 1);
+            let zi = (grid.y.max(0.0) as usize).min(heights.y_max - 1);
+            curvatures[heights.index(xi, zi)]
+        })
+        .collect();
+    mesh.insert_attribute(ATTRIBUTE_CURVATURE, copies);
+}
+
+// Rewrites the curvature of the terrain mesh around the heights at `changed`, as
+// far out as `radius` reaches, for when only those have changed since the mesh's
+// curvature was last written.
+pub fn write_curvature_around(
+    mesh: &mut Mesh,
+    heights: &TerrainHeights,
+    config: &CurvatureConfig,
+    changed: &[usize],
+) {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    let faceted = positions.len() != heights.heights.len();
+    let (x_max, y_max) = (heights.x_max, heights.y_max);
+    let mut curvatures = vec![];
+    for index in terrain::grid_neighborhood(changed, x_max, y_max, config.radius.max(1)) {
+        let curvature = vertex_curvature(heights, config, index / y_max, index % y_max);
+        if faceted {
+            curvatures.extend(
+                terrain::facet_copies(index, x_max, y_max).map(|vertex| (vertex, curvature)),
+            );
+        } else {
+            curvatures.push((index, curvature));
+        }
+    }
+    if let Some(VertexAttributeValues::Float32(values)) = mesh.attribute_mut(ATTRIBUTE_CURVATURE) {
+        for (vertex, curvature) in curvatures {
+            values[vertex] = curvature;
+        }
+    }
+}
+
+// Passes config changes on to the terrain.
+pub fn apply_curvature_config(
+    config: Res<CurvatureConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<(&Handle<Mesh>, &Handle<MountainMaterial>), With<Mountain>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let Some(heights) = heights else {
+        return;
+    };
+    for (mesh, material) in mountain.iter() {
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            write_curvature(mesh, &heights, &config);
+        }
+        if let Some(material) = materials.get_mut(material) {
+            material.curvature = config.uniform();
+        }
+    }
+}
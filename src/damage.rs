@@ -0,0 +1,138 @@
+//! Fall damage, without any health to take it from yet. Landing faster than a
+//! threshold sends `PlayerDamaged`, for whatever wants to react to getting hurt,
+//! and flashes a red vignette over the screen that fades away. The vignette is
+//! a full screen UI image rather than a render pass, which is all a flash needs.
+
+use bevy::prelude::*;
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+
+use crate::physics::EARTH_GRAVITY;
+use crate::player::PlayerLanded;
+
+// meters the player can drop without getting hurt
+const SAFE_FALL_HEIGHT: f32 = 5.0;
+
+#[derive(Resource, Debug, Clone)]
+pub struct DamageConfig {
+    // landings slower than this, in units per second, don't hurt. A jump on flat
+    // ground lands at the jump speed.
+    pub threshold: f32,
+    // damage per unit per second of landing speed over the threshold
+    pub damage_per_speed: f32,
+    // damage that flashes the vignette at full strength, less flashes it fainter
+    pub full_flash_damage: f32,
+    // seconds the flash takes to fade
+    pub flash_duration: f32,
+    // color of the vignette at full strength, the alpha is how opaque its edges get
+    pub flash_color: Color,
+}
+
+impl Default for DamageConfig {
+    fn default() -> Self {
+        DamageConfig {
+            // the speed a safe fall lands at
+            threshold: (2.0 * EARTH_GRAVITY * SAFE_FALL_HEIGHT).sqrt(),
+            damage_per_speed: 10.0,
+            full_flash_damage: 50.0,
+            flash_duration: 0.6,
+            flash_color: Color::srgba(0.8, 0.0, 0.0, 0.7),
+        }
+    }
+}
+
+// The player got hurt, by this much.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerDamaged(pub f32);
+
+pub fn fall_damage(
+    config: Res<DamageConfig>,
+    mut landings: EventReader<PlayerLanded>,
+    mut damaged: EventWriter<PlayerDamaged>,
+) {
+    for landing in landings.read() {
+        let over = landing.speed - config.threshold;
+        if over > 0.0 {
+            damaged.send(PlayerDamaged(over * config.damage_per_speed));
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Vignette {
+    // 0.0 to 1.0, how strong the flash is right now
+    strength: f32,
+}
+
+const VIGNETTE_SIZE: u32 = 128;
+
+// White, transparent in the middle and opaque towards the edges, tinted by the
+// UI image's color.
+fn vignette_image() -> Image {
+    let center = (VIGNETTE_SIZE as f32 - 1.0) / 2.0;
+    let mut data = Vec::with_capacity((VIGNETTE_SIZE * VIGNETTE_SIZE * 4) as usize);
+    for y in 0..VIGNETTE_SIZE {
+        for x in 0..VIGNETTE_SIZE {
+            // 0.0 in the middle, 1.0 at the middle of each edge
+            let distance = Vec2::new(x as f32 - center, y as f32 - center).length() / center;
+            let t = ((distance - 0.4) / 0.8).clamp(0.0, 1.0);
+            let alpha = t * t * (3.0 - 2.0 * t);
+            data.extend([255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+    Image::new(
+        Extent3d {
+            width: VIGNETTE_SIZE,
+            height: VIGNETTE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+pub fn spawn_vignette(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            image: UiImage::new(images.add(vignette_image())).with_color(Color::NONE),
+            // over the rest of the UI
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        Vignette { strength: 0.0 },
+    ));
+}
+
+// Flashes the vignette when the player is hurt and fades it back out.
+pub fn update_vignette(
+    time: Res<Time>,
+    config: Res<DamageConfig>,
+    mut damaged: EventReader<PlayerDamaged>,
+    mut vignette: Query<(&mut Vignette, &mut UiImage)>,
+) {
+    let Ok((mut vignette, mut image)) = vignette.get_single_mut() else {
+        return;
+    };
+    for PlayerDamaged(damage) in damaged.read() {
+        let strength = (damage / config.full_flash_damage.max(f32::EPSILON)).min(1.0);
+        vignette.strength = vignette.strength.max(strength);
+    }
+    if vignette.strength <= 0.0 {
+        return;
+    }
+    vignette.strength = (vignette.strength
+        - time.delta_seconds() / config.flash_duration.max(f32::EPSILON))
+    .max(0.0);
+    let color = config.flash_color;
+    image.color = color.with_alpha(color.alpha() * vignette.strength);
+}
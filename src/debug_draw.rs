@@ -0,0 +1,500 @@
+//! Design and debugging aids drawn with gizmos.
+
+use bevy::color::palettes::css;
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy_rapier3d::prelude::KinematicCharacterControllerOutput;
+use bevy_rapier3d::render::DebugRenderContext;
+
+use crate::controls::KeyBindings;
+use crate::movement::{MovementConfig, PlayerVelocity};
+use crate::navigation::WalkabilityGrid;
+use crate::terrain::TerrainHeights;
+use crate::{Mountain, Player};
+
+// A grid drawn on top of the terrain around the player, for lining things up.
+// It's drawn in a different color where the terrain isn't walkable.
+#[derive(Resource, Debug, Clone)]
+pub struct GridOverlay {
+    pub enabled: bool,
+    // distance between grid lines in world units
+    pub spacing: f32,
+    pub color: Color,
+    // used instead of `color` where the terrain isn't walkable
+    pub unwalkable_color: Color,
+    // how far from the player the grid extends
+    pub radius: f32,
+}
+
+impl Default for GridOverlay {
+    fn default() -> Self {
+        GridOverlay {
+            enabled: false,
+            spacing: 2.0,
+            color: Color::srgba(1.0, 1.0, 1.0, 0.6),
+            unwalkable_color: Color::srgba(1.0, 0.2, 0.2, 0.6),
+            radius: 20.0,
+        }
+    }
+}
+
+// lift the lines a little so they don't z-fight with the ground
+const GRID_LIFT: f32 = 0.05;
+
+pub fn toggle_grid(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut grid: ResMut<GridOverlay>,
+) {
+    if keys.just_pressed(bindings.toggle_grid) {
+        grid.enabled = !grid.enabled;
+    }
+}
+
+pub fn draw_grid(
+    mut gizmos: Gizmos,
+    grid: Res<GridOverlay>,
+    heights: Res<TerrainHeights>,
+    walkability: Option<Res<WalkabilityGrid>>,
+    player: Query<&Transform, With<Player>>,
+) {
+    if !grid.enabled {
+        return;
+    }
+    let center = player.single().translation.xz();
+    // follow the terrain at the resolution of the mesh
+    let step = heights.spacing().x;
+
+    let first_line = ((center - grid.radius) / grid.spacing).ceil();
+    let last_line = ((center + grid.radius) / grid.spacing).floor();
+    let samples = (2.0 * grid.radius / step) as usize;
+
+    let surface_point = |x: f32, z: f32| {
+        let walkable = walkability
+            .as_ref()
+            .is_none_or(|walkability| walkability.is_walkable(x, z));
+        let color = if walkable {
+            grid.color
+        } else {
+            grid.unwalkable_color
+        };
+        heights
+            .height_at(x, z)
+            .map(|y| (Vec3::new(x, y + GRID_LIFT, z), color))
+    };
+
+    // lines along z at fixed x, then lines along x at fixed z
+    for line in first_line.x as i32..=last_line.x as i32 {
+        let x = line as f32 * grid.spacing;
+        let points = (0..=samples)
+            .filter_map(|i| surface_point(x, center.y - grid.radius + i as f32 * step));
+        gizmos.linestrip_gradient(points);
+    }
+    for line in first_line.y as i32..=last_line.y as i32 {
+        let z = line as f32 * grid.spacing;
+        let points = (0..=samples)
+            .filter_map(|i| surface_point(center.x - grid.radius + i as f32 * step, z));
+        gizmos.linestrip_gradient(points);
+    }
+}
+
+// Arrows showing how the player is moving: velocity in yellow, the ground normal
+// the character controller hit in green, and the camera's forward and right
+// directions in blue and red.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MovementGizmos {
+    pub enabled: bool,
+}
+
+const VELOCITY_COLOR: Srgba = css::YELLOW;
+const GROUND_NORMAL_COLOR: Srgba = css::LIME;
+const CAMERA_FORWARD_COLOR: Srgba = css::BLUE;
+const CAMERA_RIGHT_COLOR: Srgba = css::RED;
+
+pub fn toggle_movement_gizmos(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut movement_gizmos: ResMut<MovementGizmos>,
+) {
+    if keys.just_pressed(bindings.toggle_movement_gizmos) {
+        movement_gizmos.enabled = !movement_gizmos.enabled;
+    }
+}
+
+pub fn draw_movement_gizmos(
+    mut gizmos: Gizmos,
+    movement_gizmos: Res<MovementGizmos>,
+    config: Res<MovementConfig>,
+    player: Query<
+        (
+            &Transform,
+            &PlayerVelocity,
+            &KinematicCharacterControllerOutput,
+        ),
+        With<Player>,
+    >,
+    camera: Query<&Transform, (With<Camera3d>, Without<Player>)>,
+) {
+    if !movement_gizmos.enabled {
+        return;
+    }
+    let (transform, velocity, output) = player.single();
+    let camera = camera.single();
+    // the camera sits inside the player, so draw from its feet to keep
+    // the arrows in view when looking down
+    let feet = transform.translation - Vec3::Y * config.player_half_height();
+
+    gizmos.arrow(feet, feet + velocity.0, VELOCITY_COLOR);
+
+    // the most upward facing thing we bumped into is the ground
+    let ground_normal = output
+        .collisions
+        .iter()
+        .filter_map(|collision| collision.hit.details)
+        .map(|details| details.normal2)
+        .max_by(|a, b| a.y.total_cmp(&b.y));
+    if let Some(normal) = ground_normal {
+        gizmos.arrow(feet, feet + normal * 0.5, GROUND_NORMAL_COLOR);
+    }
+
+    gizmos.arrow(feet, feet + *camera.forward() * 0.5, CAMERA_FORWARD_COLOR);
+    gizmos.arrow(feet, feet + *camera.right() * 0.5, CAMERA_RIGHT_COLOR);
+}
+
+// Red, green and blue arrows along the world x, y and z axes, at the origin and
+// optionally following the player. Handy for keeping track of which way is which,
+// like forward being -z.
+#[derive(Resource, Debug, Clone)]
+pub struct AxisGizmos {
+    pub enabled: bool,
+    pub at_player: bool,
+    // length of each arrow in world units
+    pub length: f32,
+}
+
+impl Default for AxisGizmos {
+    fn default() -> Self {
+        AxisGizmos {
+            enabled: false,
+            at_player: true,
+            length: 5.0,
+        }
+    }
+}
+
+pub fn toggle_axis_gizmos(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut axis_gizmos: ResMut<AxisGizmos>,
+) {
+    if keys.just_pressed(bindings.toggle_axes) {
+        axis_gizmos.enabled = !axis_gizmos.enabled;
+    }
+}
+
+pub fn draw_axis_gizmos(
+    mut gizmos: Gizmos,
+    axis_gizmos: Res<AxisGizmos>,
+    player: Query<&Transform, With<Player>>,
+) {
+    if !axis_gizmos.enabled {
+        return;
+    }
+    let player = player
+        .get_single()
+        .ok()
+        .filter(|_| axis_gizmos.at_player)
+        .map(|transform| transform.translation);
+    for origin in std::iter::once(Vec3::ZERO).chain(player) {
+        for (axis, color) in [
+            (Vec3::X, css::RED),
+            (Vec3::Y, css::LIME),
+            (Vec3::Z, css::BLUE),
+        ] {
+            gizmos.arrow(origin, origin + axis * axis_gizmos.length, color);
+        }
+    }
+}
+
+// A short line from terrain vertices along their normals, straight out of the
+// mesh, so whatever normal smoothing or editing did to them shows. Only every
+// so many vertices along each side get one, the full grid would be far too many
+// lines.
+#[derive(Resource, Debug, Clone)]
+pub struct NormalGizmos {
+    pub enabled: bool,
+    // length of each line in world units
+    pub length: f32,
+    // about the most lines drawn, vertices are skipped evenly to stay under it
+    pub max_lines: usize,
+    pub color: Color,
+}
+
+impl Default for NormalGizmos {
+    fn default() -> Self {
+        NormalGizmos {
+            enabled: false,
+            length: 1.5,
+            max_lines: 4000,
+            color: Color::srgb(0.2, 0.9, 1.0),
+        }
+    }
+}
+
+pub fn toggle_normal_gizmos(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut normal_gizmos: ResMut<NormalGizmos>,
+) {
+    if keys.just_pressed(bindings.toggle_normals) {
+        normal_gizmos.enabled = !normal_gizmos.enabled;
+    }
+}
+
+pub fn draw_normal_gizmos(
+    mut gizmos: Gizmos,
+    normal_gizmos: Res<NormalGizmos>,
+    heights: Res<TerrainHeights>,
+    meshes: Res<Assets<Mesh>>,
+    mountain: Query<(&Handle<Mesh>, &GlobalTransform), With<Mountain>>,
+) {
+    if !normal_gizmos.enabled {
+        return;
+    }
+    let Ok((mesh, transform)) = mountain.get_single() else {
+        return;
+    };
+    let Some(mesh) = meshes.get(mesh) else {
+        return;
+    };
+    let (
+        Some(VertexAttributeValues::Float32x3(positions)),
+        Some(VertexAttributeValues::Float32x3(normals)),
+    ) = (
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+        mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+    )
+    else {
+        return;
+    };
+    let (x_max, y_max) = (heights.x_max, heights.y_max);
+    if normals.len() != positions.len() {
+        return;
+    }
+    if positions.len() != x_max * y_max {
+        // faceted, every triangle has its own three vertices, so a line from the
+        // middle of each drawn triangle
+        let triangles = positions.len() / 3;
+        let stride = triangles.div_ceil(normal_gizmos.max_lines.max(1)).max(1);
+        for triangle in (0..triangles).step_by(stride) {
+            let corners = &positions[triangle * 3..triangle * 3 + 3];
+            let middle = corners.iter().map(|&p| Vec3::from(p)).sum::<Vec3>() / 3.0;
+            let start = transform.transform_point(middle);
+            let normal = transform
+                .affine()
+                .transform_vector3(Vec3::from(normals[triangle * 3]));
+            let end = start + normal.normalize_or_zero() * normal_gizmos.length;
+            gizmos.line(start, end, normal_gizmos.color);
+        }
+        return;
+    }
+    // the same stride along both sides keeps the lines evenly spread
+    let vertices = (x_max * y_max) as f32;
+    let stride = (vertices / normal_gizmos.max_lines.max(1) as f32)
+        .sqrt()
+        .ceil()
+        .max(1.0) as usize;
+    for xi in (0..x_max).step_by(stride) {
+        for zi in (0..y_max).step_by(stride) {
+            let index = heights.index(xi, zi);
+            let start = transform.transform_point(Vec3::from(positions[index]));
+            let normal = transform
+                .affine()
+                .transform_vector3(Vec3::from(normals[index]));
+            let end = start + normal.normalize_or_zero() * normal_gizmos.length;
+            gizmos.line(start, end, normal_gizmos.color);
+        }
+    }
+}
+
+// Rapier's collider wireframes, off unless asked for.
+pub fn toggle_physics_debug(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut debug_render: ResMut<DebugRenderContext>,
+) {
+    if keys.just_pressed(bindings.toggle_physics_debug) {
+        debug_render.enabled = !debug_render.enabled;
+    }
+}
+
+// A graph of the terrain's height along a line from the player in the direction
+// the camera is facing, in a panel in the corner of the screen. The line itself
+// is drawn on the terrain too.
+#[derive(Resource, Debug, Clone)]
+pub struct CrossSection {
+    pub enabled: bool,
+    // how far ahead of the player the line goes, in world units
+    pub distance: f32,
+    // heights sampled along the line, one bar each in the graph
+    pub samples: usize,
+    pub color: Color,
+}
+
+impl Default for CrossSection {
+    fn default() -> Self {
+        CrossSection {
+            enabled: false,
+            distance: 100.0,
+            samples: 100,
+            color: Color::srgb(1.0, 0.85, 0.2),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct CrossSectionPanel {}
+
+// One bar of the graph, the index of its sample along the line.
+#[derive(Component)]
+pub struct CrossSectionBar(usize);
+
+#[derive(Component)]
+pub struct CrossSectionText {}
+
+const CROSS_SECTION_WIDTH: f32 = 300.0;
+const CROSS_SECTION_HEIGHT: f32 = 120.0;
+
+pub fn toggle_cross_section(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut cross_section: ResMut<CrossSection>,
+    panel: Query<Entity, With<CrossSectionPanel>>,
+) {
+    if !keys.just_pressed(bindings.toggle_cross_section) {
+        return;
+    }
+    cross_section.enabled = !cross_section.enabled;
+    for entity in panel.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !cross_section.enabled {
+        return;
+    }
+    let bar_width = CROSS_SECTION_WIDTH / cross_section.samples.max(1) as f32;
+    let color = cross_section.color;
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(12.0),
+                    right: Val::Px(12.0),
+                    width: Val::Px(CROSS_SECTION_WIDTH),
+                    height: Val::Px(CROSS_SECTION_HEIGHT),
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+            CrossSectionPanel {},
+        ))
+        .with_children(|parent| {
+            for i in 0..cross_section.samples {
+                parent.spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            left: Val::Px(i as f32 * bar_width),
+                            bottom: Val::Px(0.0),
+                            width: Val::Px(bar_width),
+                            height: Val::Px(0.0),
+                            ..default()
+                        },
+                        background_color: color.into(),
+                        ..default()
+                    },
+                    CrossSectionBar(i),
+                ));
+            }
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 14.0,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(4.0),
+                    left: Val::Px(4.0),
+                    ..default()
+                }),
+                CrossSectionText {},
+            ));
+        });
+}
+
+pub fn draw_cross_section(
+    mut gizmos: Gizmos,
+    cross_section: Res<CrossSection>,
+    heights: Res<TerrainHeights>,
+    player: Query<&Transform, With<Player>>,
+    camera: Query<&Transform, With<Camera3d>>,
+    mut bars: Query<(&CrossSectionBar, &mut Style)>,
+    mut text: Query<&mut Text, With<CrossSectionText>>,
+) {
+    if !cross_section.enabled {
+        return;
+    }
+    let (Ok(player), Ok(camera)) = (player.get_single(), camera.get_single()) else {
+        return;
+    };
+    let start = player.translation.xz();
+    let direction = camera.forward().xz().normalize_or(Vec2::NEG_Y);
+    let step = cross_section.distance / cross_section.samples.saturating_sub(1).max(1) as f32;
+    let samples: Vec<Option<Vec3>> = (0..cross_section.samples)
+        .map(|i| {
+            let point = start + direction * i as f32 * step;
+            heights
+                .height_at(point.x, point.y)
+                .map(|y| Vec3::new(point.x, y, point.y))
+        })
+        .collect();
+
+    gizmos.linestrip(
+        samples
+            .iter()
+            .flatten()
+            .map(|point| *point + Vec3::Y * GRID_LIFT),
+        cross_section.color,
+    );
+
+    let (min, max) = samples
+        .iter()
+        .flatten()
+        .fold((f32::MAX, f32::MIN), |(min, max), point| {
+            (min.min(point.y), max.max(point.y))
+        });
+    // leave some ground under the lowest point so it still has a bar
+    let floor = min - (max - min).max(1.0) * 0.1;
+    for (bar, mut style) in bars.iter_mut() {
+        let height = match samples.get(bar.0).copied().flatten() {
+            Some(point) => (point.y - floor) / (max - floor) * CROSS_SECTION_HEIGHT,
+            None => 0.0,
+        };
+        style.height = Val::Px(height);
+    }
+    for mut text in text.iter_mut() {
+        text.sections[0].value = if min <= max {
+            format!(
+                "{:.0}m ahead: {:.1} to {:.1}",
+                cross_section.distance, min, max
+            )
+        } else {
+            "off the terrain".to_string()
+        };
+    }
+}
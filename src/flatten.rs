@@ -0,0 +1,197 @@
+//! Flattening a rectangle of terrain, for building on. In flatten mode two clicks
+//! on the terrain pick opposite corners, the rectangle is previewed at the height
+//! it'll be flattened to, and Enter flattens it. The ground around the rectangle
+//! is blended into the new height so there's a slope rather than a cliff. Edits go
+//! through `TerrainEdits` like the brush's.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::RapierContext;
+
+use crate::controls::KeyBindings;
+use crate::terrain::TerrainHeights;
+use crate::terrain_edit::{terrain_under_crosshair, TerrainEdits};
+use crate::{Mountain, Player};
+
+#[derive(Resource, Debug, Clone)]
+pub struct FlattenConfig {
+    // world space height to flatten to, None for the average of the rectangle's corners
+    pub height: Option<f32>,
+    // width of the blend around the rectangle, in world units
+    pub feather: f32,
+    // how far away corners can be clicked
+    pub reach: f32,
+}
+
+impl Default for FlattenConfig {
+    fn default() -> Self {
+        FlattenConfig {
+            height: None,
+            feather: 4.0,
+            reach: 200.0,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct FlattenTool {
+    pub active: bool,
+    // the clicked corners, a third click starts over
+    pub corners: Vec<Vec3>,
+}
+
+const RECT_COLOR: Color = Color::srgb(1.0, 0.85, 0.2);
+const FEATHER_COLOR: Color = Color::srgba(1.0, 0.85, 0.2, 0.35);
+
+impl FlattenTool {
+    // The selected rectangle on the xz plane, once both corners are picked.
+    pub fn rect(&self) -> Option<Rect> {
+        match self.corners[..] {
+            [a, b] => Some(Rect::from_corners(a.xz(), b.xz())),
+            _ => None,
+        }
+    }
+}
+
+// The world space height `rect` gets flattened to.
+pub fn target_height(config: &FlattenConfig, heights: &TerrainHeights, rect: Rect) -> f32 {
+    if let Some(height) = config.height {
+        return height;
+    }
+    let corners = [
+        rect.min,
+        Vec2::new(rect.min.x, rect.max.y),
+        rect.max,
+        Vec2::new(rect.max.x, rect.min.y),
+    ];
+    let found: Vec<f32> = corners
+        .iter()
+        .filter_map(|corner| heights.height_at(corner.x, corner.y))
+        .collect();
+    found.iter().sum::<f32>() / found.len().max(1) as f32
+}
+
+// Turns flatten mode on or off, leaving it clears the corners.
+pub fn toggle_flatten(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut tool: ResMut<FlattenTool>,
+) {
+    if !keys.just_pressed(bindings.flatten) {
+        return;
+    }
+    tool.active = !tool.active;
+    tool.corners.clear();
+}
+
+pub fn flatten_clicks(
+    mouse: Res<ButtonInput<MouseButton>>,
+    config: Res<FlattenConfig>,
+    context: Res<RapierContext>,
+    mut tool: ResMut<FlattenTool>,
+    camera: Query<&Transform, With<Camera3d>>,
+    player: Query<Entity, With<Player>>,
+    mountain: Query<Entity, With<Mountain>>,
+) {
+    if !tool.active || !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let (Ok(camera), Ok(player), Ok(mountain)) = (
+        camera.get_single(),
+        player.get_single(),
+        mountain.get_single(),
+    ) else {
+        return;
+    };
+    let Some(hit) = terrain_under_crosshair(&context, camera, player, mountain, config.reach)
+    else {
+        return;
+    };
+    if tool.corners.len() >= 2 {
+        tool.corners.clear();
+    }
+    tool.corners.push(hit);
+}
+
+// Flattens the selected rectangle when Enter is pressed.
+pub fn apply_flatten(
+    keys: Res<ButtonInput<KeyCode>>,
+    config: Res<FlattenConfig>,
+    mut tool: ResMut<FlattenTool>,
+    mut heights: ResMut<TerrainHeights>,
+    mut edits: ResMut<TerrainEdits>,
+    mountain: Query<&Transform, With<Mountain>>,
+) {
+    if !tool.active || !keys.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    let (Some(rect), Ok(mountain)) = (tool.rect(), mountain.get_single()) else {
+        return;
+    };
+    let target = target_height(&config, &heights, rect);
+    // heights are stored relative to the mountain
+    let local_target = target - mountain.translation.y;
+    let local_rect =
+        Rect::from_center_half_size(rect.center() - mountain.translation.xz(), rect.half_size());
+    let feather = config.feather.max(0.0);
+    for xi in 0..heights.x_max {
+        for zi in 0..heights.y_max {
+            let point = heights.vertex_xz(xi, zi);
+            // distance outside the rectangle, 0.0 inside it
+            let outside = (local_rect.min - point)
+                .max(point - local_rect.max)
+                .max(Vec2::ZERO)
+                .length();
+            if outside > feather {
+                continue;
+            }
+            let blend = if feather > 0.0 {
+                let t = 1.0 - outside / feather;
+                t * t * (3.0 - 2.0 * t)
+            } else {
+                1.0
+            };
+            let index = heights.index(xi, zi);
+            let height = &mut heights.heights[index];
+            *height += (local_target - *height) * blend;
+            edits.mark_dirty(index);
+        }
+    }
+    edits.mark_region(rect.inflate(feather));
+    info!(
+        "flattened {:.1} by {:.1} to a height of {:.2}",
+        rect.width(),
+        rect.height(),
+        target
+    );
+    tool.corners.clear();
+}
+
+pub fn draw_flatten(
+    mut gizmos: Gizmos,
+    config: Res<FlattenConfig>,
+    tool: Res<FlattenTool>,
+    heights: Res<TerrainHeights>,
+) {
+    if !tool.active {
+        return;
+    }
+    for corner in &tool.corners {
+        gizmos.sphere(*corner, Quat::IDENTITY, 0.3, RECT_COLOR);
+    }
+    let Some(rect) = tool.rect() else {
+        return;
+    };
+    let y = target_height(&config, &heights, rect);
+    let outline = |rect: Rect| {
+        [
+            rect.min,
+            Vec2::new(rect.min.x, rect.max.y),
+            rect.max,
+            Vec2::new(rect.max.x, rect.min.y),
+            rect.min,
+        ]
+        .map(|corner| Vec3::new(corner.x, y, corner.y))
+    };
+    gizmos.linestrip(outline(rect), RECT_COLOR);
+    gizmos.linestrip(outline(rect.inflate(config.feather)), FEATHER_COLOR);
+}
@@ -0,0 +1,160 @@
+//! Camera flythroughs for capturing footage. Keyframes are placed where the
+//! camera is, looking where it looks, and playing the flythrough moves the camera
+//! smoothly through them over and over, back from the last to the first, while
+//! the player's input is ignored. Keyframes are saved as they're placed and
+//! loaded back at startup.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::controls::KeyBindings;
+use crate::path::catmull_rom;
+
+const FLYTHROUGH_SAVE_PATH: &str = "flythrough.json";
+
+#[derive(Resource, Debug, Clone)]
+pub struct FlythroughConfig {
+    // seconds from one keyframe to the next
+    pub segment_duration: f32,
+}
+
+impl Default for FlythroughConfig {
+    fn default() -> Self {
+        FlythroughConfig {
+            segment_duration: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub position: Vec3,
+    // the direction the camera looks, not necessarily normalized
+    pub look: Vec3,
+}
+
+// A closed loop through the keyframes.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FlythroughPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl FlythroughPath {
+    // Where the camera is `t` segments into the loop. Both the position and the
+    // look direction follow Catmull-Rom splines, so the camera doesn't jerk as
+    // it passes a keyframe. None without any keyframes.
+    pub fn sample(&self, t: f32) -> Option<Transform> {
+        let keyframes = &self.keyframes;
+        let count = keyframes.len();
+        if count == 0 {
+            return None;
+        }
+        let t = t.rem_euclid(count as f32);
+        let i = (t.floor() as usize).min(count - 1);
+        let local = t - i as f32;
+        let at = |offset: usize| keyframes[(i + count - 1 + offset) % count];
+        let (k0, k1, k2, k3) = (at(0), at(1), at(2), at(3));
+        let position = catmull_rom(k0.position, k1.position, k2.position, k3.position, local);
+        let look = catmull_rom(
+            k0.look.normalize_or_zero(),
+            k1.look.normalize_or_zero(),
+            k2.look.normalize_or_zero(),
+            k3.look.normalize_or_zero(),
+            local,
+        );
+        let look = look.try_normalize().unwrap_or(Vec3::NEG_Z);
+        Some(Transform::from_translation(position).looking_to(look, Vec3::Y))
+    }
+
+    fn load(path: &str) -> Option<FlythroughPath> {
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        let text = std::fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&text) {
+            Ok(flythrough) => Some(flythrough),
+            Err(err) => {
+                warn!("couldn't load the flythrough from {}: {}", path, err);
+                None
+            }
+        }
+    }
+
+    fn save(&self, path: &str) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let result = serde_json::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(path, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("couldn't save the flythrough to {}: {}", path, err);
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Flythrough {
+    pub playing: bool,
+    // seconds since playing started
+    pub elapsed: f32,
+}
+
+pub fn load_flythrough(mut path: ResMut<FlythroughPath>) {
+    if let Some(loaded) = FlythroughPath::load(FLYTHROUGH_SAVE_PATH) {
+        *path = loaded;
+    }
+}
+
+// Adds a keyframe where the camera is, or with Shift held takes the last one
+// off. Starts and stops playing.
+pub fn flythrough_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut path: ResMut<FlythroughPath>,
+    mut flythrough: ResMut<Flythrough>,
+    camera: Query<&Transform, With<Camera3d>>,
+) {
+    if keys.just_pressed(bindings.flythrough) {
+        // a single keyframe would just sit still
+        if !flythrough.playing && path.keyframes.len() < 2 {
+            info!("the flythrough needs at least two keyframes");
+        } else {
+            flythrough.playing = !flythrough.playing;
+            flythrough.elapsed = 0.0;
+        }
+    }
+    if flythrough.playing || !keys.just_pressed(bindings.flythrough_keyframe) {
+        return;
+    }
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        path.keyframes.pop();
+    } else if let Ok(camera) = camera.get_single() {
+        path.keyframes.push(Keyframe {
+            position: camera.translation,
+            look: camera.forward().as_vec3(),
+        });
+    }
+    info!("the flythrough has {} keyframes", path.keyframes.len());
+    path.save(FLYTHROUGH_SAVE_PATH);
+}
+
+// Moves the camera along the flythrough. Runs after everything else that moves
+// the camera, so it wins while playing.
+pub fn play_flythrough(
+    time: Res<Time>,
+    config: Res<FlythroughConfig>,
+    path: Res<FlythroughPath>,
+    mut flythrough: ResMut<Flythrough>,
+    mut camera: Query<&mut Transform, With<Camera3d>>,
+) {
+    if !flythrough.playing {
+        return;
+    }
+    flythrough.elapsed += time.delta_seconds();
+    let t = flythrough.elapsed / config.segment_duration.max(f32::EPSILON);
+    let (Some(transform), Ok(mut camera)) = (path.sample(t), camera.get_single_mut()) else {
+        return;
+    };
+    *camera = transform;
+}
@@ -0,0 +1,253 @@
+//! Footprints left in the terrain. A small single-channel texture acts as a
+//! trail map: the player stamps dark circles into it as they walk, it slowly
+//! fades back to zero, and the mountain shader darkens the ground where it's set.
+
+use bevy::prelude::*;
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use bevy_rapier3d::prelude::KinematicCharacterControllerOutput;
+
+use crate::freeze::FreezeTime;
+use crate::surface::{Surface, SurfaceConfig};
+use crate::terrain::TerrainConfig;
+use crate::terrain::TerrainHeights;
+use crate::texture_filtering::TextureFilteringConfig;
+use crate::{Mountain, MountainMaterial, Player};
+
+#[derive(Resource, Debug, Clone)]
+pub struct FootprintConfig {
+    // true: the trail covers a square of `follow_radius` around the player and moves with them.
+    // false: the trail covers the whole terrain at a lower density.
+    pub follow_player: bool,
+    pub follow_radius: f32,
+    // width of the trail texture in texels
+    pub resolution: u32,
+    // radius of a single print in world units
+    pub stamp_radius: f32,
+    // how far the player walks between prints
+    pub stride: f32,
+    // how much of a print fades away each second, 1.0 means a print lasts one second
+    pub fade_per_second: f32,
+}
+
+impl Default for FootprintConfig {
+    fn default() -> Self {
+        FootprintConfig {
+            follow_player: true,
+            follow_radius: 8.0,
+            resolution: 256,
+            stamp_radius: 0.12,
+            stride: 0.7,
+            fade_per_second: 0.05,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct FootprintTrail {
+    pub image: Handle<Image>,
+    // min x, min z, size x, size z of the area covered by the texture, in world space
+    pub bounds: Vec4,
+    last_stamp: Option<Vec2>,
+    // fading happens in whole texel steps, so keep the leftover between frames
+    fade_accumulator: f32,
+}
+
+impl FromWorld for FootprintTrail {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.resource::<FootprintConfig>().clone();
+        let filtering = world.resource::<TextureFilteringConfig>().clone();
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: config.resolution,
+                height: config.resolution,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0],
+            TextureFormat::R8Unorm,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = filtering.sampler();
+
+        let bounds = if config.follow_player {
+            let size = config.follow_radius * 2.0;
+            Vec4::new(-config.follow_radius, -config.follow_radius, size, size)
+        } else {
+            let extent = world.resource::<TerrainConfig>().extent;
+            Vec4::new(-extent.x / 2.0, -extent.y / 2.0, extent.x, extent.y)
+        };
+
+        FootprintTrail {
+            image: world.resource_mut::<Assets<Image>>().add(image),
+            bounds,
+            last_stamp: None,
+            fade_accumulator: 0.0,
+        }
+    }
+}
+
+// Sent every time the player leaves a print.
+#[derive(Event)]
+pub struct Footstep {
+    // where the print is, on the ground
+    pub position: Vec3,
+    // None when off the terrain
+    pub surface: Option<Surface>,
+}
+
+// Moves every texel of a `resolution`x`resolution` texture by (dx, dz) texels,
+// filling whatever is uncovered with zero.
+fn shift_texels(data: &mut [u8], resolution: usize, dx: i32, dz: i32) {
+    let old = data.to_vec();
+    for z in 0..resolution {
+        for x in 0..resolution {
+            let source_x = x as i32 + dx;
+            let source_z = z as i32 + dz;
+            let in_bounds = (0..resolution as i32).contains(&source_x)
+                && (0..resolution as i32).contains(&source_z);
+            data[z * resolution + x] = if in_bounds {
+                old[source_z as usize * resolution + source_x as usize]
+            } else {
+                0
+            };
+        }
+    }
+}
+
+fn stamp(data: &mut [u8], resolution: usize, bounds: Vec4, center: Vec2, radius: f32) {
+    let texel_size = bounds.z / resolution as f32;
+    let center_texel = (center - bounds.xy()) / bounds.zw() * resolution as f32;
+    let radius_texels = (radius / texel_size).ceil() as i32;
+    for dz in -radius_texels..=radius_texels {
+        for dx in -radius_texels..=radius_texels {
+            let x = center_texel.x as i32 + dx;
+            let z = center_texel.y as i32 + dz;
+            if !(0..resolution as i32).contains(&x) || !(0..resolution as i32).contains(&z) {
+                continue;
+            }
+            let distance = Vec2::new(dx as f32, dz as f32).length() * texel_size;
+            // soft edged circle, full strength in the middle
+            let strength = (1.0 - distance / radius).clamp(0.0, 1.0);
+            let texel = &mut data[z as usize * resolution + x as usize];
+            *texel = (*texel).max((strength * 255.0) as u8);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_footprints(
+    time: Res<Time>,
+    freeze: Res<FreezeTime>,
+    config: Res<FootprintConfig>,
+    mut trail: ResMut<FootprintTrail>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mut footsteps: EventWriter<Footstep>,
+    surfaces: Res<SurfaceConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    player: Query<(&Transform, &KinematicCharacterControllerOutput), With<Player>>,
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+) {
+    let Ok((transform, output)) = player.get_single() else {
+        return;
+    };
+    let resolution = config.resolution as usize;
+    let position = transform.translation.xz();
+    let trail = &mut *trail;
+    // Getting the image mutably has it uploaded again whole, so first work out
+    // whether anything actually changes.
+    let Some(image) = images.get(&trail.image) else {
+        return;
+    };
+
+    // fade old prints, if there are any left
+    if !freeze.0 {
+        trail.fade_accumulator += config.fade_per_second * time.delta_seconds() * 255.0;
+    }
+    let fade = trail.fade_accumulator.floor();
+    let mut fade_by = None;
+    if fade >= 1.0 {
+        trail.fade_accumulator -= fade;
+        if image.data.iter().any(|&texel| texel > 0) {
+            fade_by = Some(fade.min(255.0) as u8);
+        }
+    }
+
+    // keep the trail area centered on the player, moving it in whole texels so
+    // existing prints stay where they are in the world
+    let mut shift = None;
+    let texel_size = trail.bounds.z / resolution as f32;
+    if config.follow_player {
+        let center = trail.bounds.xy() + trail.bounds.zw() / 2.0;
+        let offset = position - center;
+        if offset.abs().max_element() > config.follow_radius / 4.0 {
+            let dx = (offset.x / texel_size).round() as i32;
+            let dz = (offset.y / texel_size).round() as i32;
+            shift = Some((dx, dz));
+        }
+    }
+
+    let stamped = output.grounded
+        && trail
+            .last_stamp
+            .map(|last| last.distance(position) >= config.stride)
+            .unwrap_or(true);
+
+    if fade_by.is_some() || shift.is_some() || stamped {
+        let Some(image) = images.get_mut(&trail.image) else {
+            return;
+        };
+        if let Some(fade) = fade_by {
+            for texel in image.data.iter_mut() {
+                *texel = texel.saturating_sub(fade);
+            }
+        }
+        if let Some((dx, dz)) = shift {
+            shift_texels(&mut image.data, resolution, dx, dz);
+            trail.bounds.x += dx as f32 * texel_size;
+            trail.bounds.y += dz as f32 * texel_size;
+        }
+        if stamped {
+            stamp(
+                &mut image.data,
+                resolution,
+                trail.bounds,
+                position,
+                config.stamp_radius,
+            );
+        }
+        // make sure the material picks up the new texture
+        for handle in mountain.iter() {
+            materials.get_mut(handle);
+        }
+    }
+
+    if stamped {
+        trail.last_stamp = Some(position);
+        let ground = heights
+            .as_ref()
+            .and_then(|heights| heights.height_at(position.x, position.y));
+        footsteps.send(Footstep {
+            position: Vec3::new(
+                position.x,
+                ground.unwrap_or(transform.translation.y),
+                position.y,
+            ),
+            surface: heights
+                .as_ref()
+                .and_then(|heights| surfaces.surface_at(heights, position.x, position.y)),
+        });
+    }
+
+    for handle in mountain.iter() {
+        let Some(material) = materials.get(handle) else {
+            continue;
+        };
+        if material.footprint_bounds != trail.bounds {
+            materials.get_mut(handle).unwrap().footprint_bounds = trail.bounds;
+        }
+    }
+}
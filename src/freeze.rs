@@ -0,0 +1,21 @@
+//! Freezing time for screenshots: the day-night cycle, footprints fading and
+//! particles all stop where they are, while the player can still walk and look
+//! around to frame the shot. The terrain shader has no animation of its own to stop.
+
+use bevy::prelude::*;
+
+use crate::controls::KeyBindings;
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct FreezeTime(pub bool);
+
+pub fn toggle_freeze_time(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut freeze: ResMut<FreezeTime>,
+) {
+    if keys.just_pressed(bindings.freeze_time) {
+        freeze.0 = !freeze.0;
+        info!("time {}", if freeze.0 { "frozen" } else { "resumed" });
+    }
+}
@@ -0,0 +1,199 @@
+//! Recording the player's movement and replaying it as a translucent ghost.
+//! The record key starts a recording, pressing it again stops it and the ghost
+//! starts looping through it. The last recording is saved to disk and loaded
+//! back at startup, so there's something to race against straight away.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::controls::KeyBindings;
+use crate::movement::MovementConfig;
+use crate::Player;
+
+#[derive(Resource, Debug, Clone)]
+pub struct GhostConfig {
+    // seconds between recorded samples, playback interpolates in between
+    pub sample_interval: f32,
+    pub save_path: String,
+}
+
+impl Default for GhostConfig {
+    fn default() -> Self {
+        GhostConfig {
+            sample_interval: 0.1,
+            save_path: "ghost.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GhostRecording {
+    pub sample_interval: f32,
+    pub samples: Vec<(Vec3, Quat)>,
+}
+
+impl GhostRecording {
+    pub fn duration(&self) -> f32 {
+        self.samples.len().saturating_sub(1) as f32 * self.sample_interval
+    }
+
+    // Where the ghost is `time` seconds in, looping back to the start at the end.
+    pub fn sample(&self, time: f32) -> Option<Transform> {
+        let (first, rest) = self.samples.split_first()?;
+        if rest.is_empty() || self.duration() <= 0.0 {
+            return Some(Transform::from_translation(first.0).with_rotation(first.1));
+        }
+        let position = time.rem_euclid(self.duration()) / self.sample_interval;
+        let index = (position.floor() as usize).min(self.samples.len() - 2);
+        let t = position - index as f32;
+        let (from, to) = (self.samples[index], self.samples[index + 1]);
+        let translation = from.0.lerp(to.0, t);
+        let rotation = from.1.slerp(to.1, t);
+        Some(Transform::from_translation(translation).with_rotation(rotation))
+    }
+
+    fn load(path: &str) -> Option<GhostRecording> {
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        let text = std::fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&text) {
+            Ok(recording) => Some(recording),
+            Err(err) => {
+                warn!("couldn't load the ghost from {}: {}", path, err);
+                None
+            }
+        }
+    }
+
+    fn save(&self, path: &str) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let result = serde_json::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(path, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("couldn't save the ghost to {}: {}", path, err);
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct GhostRecorder {
+    // the recording in progress, if there is one
+    recording: Option<GhostRecording>,
+    since_sample: f32,
+}
+
+#[derive(Component)]
+pub struct Ghost {
+    recording: GhostRecording,
+    time: f32,
+}
+
+fn spawn_ghost(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    radius: f32,
+    recording: GhostRecording,
+) {
+    commands.spawn((
+        PbrBundle {
+            // same shape as the player's body
+            mesh: meshes.add(Capsule3d::new(radius * 0.6, radius)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::srgba(0.7, 0.85, 1.0, 0.35),
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            }),
+            ..default()
+        },
+        Ghost {
+            recording,
+            time: 0.0,
+        },
+    ));
+}
+
+pub fn load_ghost(
+    mut commands: Commands,
+    config: Res<GhostConfig>,
+    movement: Res<MovementConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if let Some(recording) = GhostRecording::load(&config.save_path) {
+        spawn_ghost(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            movement.player_radius,
+            recording,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn record_ghost(
+    mut commands: Commands,
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    config: Res<GhostConfig>,
+    movement: Res<MovementConfig>,
+    mut recorder: ResMut<GhostRecorder>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    player: Query<&Transform, With<Player>>,
+    ghosts: Query<Entity, With<Ghost>>,
+) {
+    let player = player.single();
+    if keys.just_pressed(bindings.toggle_recording) {
+        match recorder.recording.take() {
+            None => {
+                recorder.recording = Some(GhostRecording {
+                    sample_interval: config.sample_interval,
+                    samples: vec![],
+                });
+                // take the first sample right away
+                recorder.since_sample = config.sample_interval;
+            }
+            Some(recording) => {
+                recording.save(&config.save_path);
+                // the new ghost replaces the old one
+                for ghost in ghosts.iter() {
+                    commands.entity(ghost).despawn_recursive();
+                }
+                spawn_ghost(
+                    &mut commands,
+                    &mut meshes,
+                    &mut materials,
+                    movement.player_radius,
+                    recording,
+                );
+            }
+        }
+    }
+
+    let recorder = &mut *recorder;
+    if let Some(recording) = &mut recorder.recording {
+        recorder.since_sample += time.delta_seconds();
+        while recorder.since_sample >= recording.sample_interval {
+            recorder.since_sample -= recording.sample_interval;
+            recording
+                .samples
+                .push((player.translation, player.rotation));
+        }
+    }
+}
+
+pub fn play_ghost(time: Res<Time>, mut ghosts: Query<(&mut Ghost, &mut Transform)>) {
+    for (mut ghost, mut transform) in ghosts.iter_mut() {
+        ghost.time += time.delta_seconds();
+        if let Some(sample) = ghost.recording.sample(ghost.time) {
+            *transform = sample;
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! The terrain's color ramp. Colors are given at heights between the lowest (0.0)
+//! and highest (1.0) point of the terrain, and the mountain shader blends between
+//! them in Oklab so the ramp stays perceptually smooth. Heights outside the
+//! first and last stop get the end colors.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::terrain::TerrainHeights;
+use crate::{Mountain, MountainMaterial, TERRAIN_BASE_HEIGHT};
+
+// has to match the array size in animate_shader.wgsl
+pub const MAX_GRADIENT_STOPS: usize = 8;
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeightGradient {
+    // (normalized height, color), in any order
+    pub stops: Vec<(f32, Color)>,
+}
+
+impl Default for HeightGradient {
+    fn default() -> Self {
+        HeightGradient {
+            stops: vec![
+                (0.0, Color::srgb(0.15, 0.3, 0.2)),
+                (0.2, Color::srgb(0.3, 0.55, 0.25)),
+                (0.4, Color::srgb(0.45, 0.6, 0.3)),
+                (0.6, Color::srgb(0.5, 0.42, 0.32)),
+                (0.8, Color::srgb(0.55, 0.55, 0.58)),
+                (0.92, Color::srgb(0.95, 0.95, 1.0)),
+            ],
+        }
+    }
+}
+
+pub use uniform::HeightGradientUniform;
+
+// ShaderType generates size checks for every field that count as dead code in
+// test builds, this keeps the allow from covering the rest of the file.
+#[allow(dead_code)]
+mod uniform {
+    use super::MAX_GRADIENT_STOPS;
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    // The gradient as the shader sees it.
+    #[derive(ShaderType, Debug, Clone, Default, PartialEq)]
+    pub struct HeightGradientUniform {
+        // Oklab lightness, a, b and the normalized height of each stop, sorted by height
+        pub stops: [Vec4; MAX_GRADIENT_STOPS],
+        pub count: u32,
+        // world space heights that map to 0.0 and 1.0
+        pub min_height: f32,
+        pub max_height: f32,
+    }
+}
+
+impl HeightGradient {
+    pub fn uniform(&self, min_height: f32, max_height: f32) -> HeightGradientUniform {
+        if self.stops.len() > MAX_GRADIENT_STOPS {
+            warn!(
+                "the height gradient has {} stops, only the first {} are used",
+                self.stops.len(),
+                MAX_GRADIENT_STOPS
+            );
+        }
+        let mut stops: Vec<(f32, Color)> = self
+            .stops
+            .iter()
+            .take(MAX_GRADIENT_STOPS)
+            .copied()
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut uniform = HeightGradientUniform {
+            count: stops.len() as u32,
+            min_height,
+            max_height,
+            ..default()
+        };
+        for (slot, (height, color)) in uniform.stops.iter_mut().zip(stops) {
+            let oklab = Oklaba::from(color);
+            *slot = Vec4::new(oklab.lightness, oklab.a, oklab.b, height);
+        }
+        uniform
+    }
+}
+
+// World space height of the lowest and highest vertex.
+pub fn height_range(heights: &TerrainHeights) -> (f32, f32) {
+    let (min, max) = heights
+        .heights
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(min, max), &h| {
+            (min.min(h), max.max(h))
+        });
+    (min + TERRAIN_BASE_HEIGHT, max + TERRAIN_BASE_HEIGHT)
+}
+
+// Uploads the gradient again when it's changed, so it can be tuned live.
+pub fn update_height_gradient(
+    gradient: Res<HeightGradient>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+) {
+    if !gradient.is_changed() {
+        return;
+    }
+    for handle in mountain.iter() {
+        let Some(material) = materials.get_mut(handle) else {
+            continue;
+        };
+        let range = &material.height_gradient;
+        material.height_gradient = gradient.uniform(range.min_height, range.max_height);
+    }
+}
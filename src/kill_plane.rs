@@ -0,0 +1,86 @@
+//! The bottom of the world. Anything that falls below the terrain's kill plane,
+//! through a hole in the collider or off the edge, fires `FellOutOfWorld`. The
+//! player is put back on the ground at the spawn point, and thrown props are
+//! removed. With `BoundaryMode::None` there's no kill plane.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::RigidBody;
+
+use crate::movement::{MovementConfig, PlayerVelocity};
+use crate::sandbox::SandboxCubes;
+use crate::terrain::{BoundaryMode, TerrainConfig, TerrainHeights};
+use crate::Player;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FellOutOfWorld {
+    pub entity: Entity,
+    // where it was when it was noticed, just under the kill plane
+    pub position: Vec3,
+    pub is_player: bool,
+}
+
+// where the player comes back, the middle of the terrain
+const SPAWN_POINT: Vec2 = Vec2::ZERO;
+
+pub fn detect_fall_out_of_world(
+    config: Res<TerrainConfig>,
+    mut fell: EventWriter<FellOutOfWorld>,
+    player: Query<(Entity, &Transform), With<Player>>,
+    props: Query<(Entity, &Transform, &RigidBody), Without<Player>>,
+) {
+    if config.boundary == BoundaryMode::None {
+        return;
+    }
+    let kill_plane = config.kill_plane_y();
+    for (entity, transform) in player.iter() {
+        if transform.translation.y < kill_plane {
+            fell.send(FellOutOfWorld {
+                entity,
+                position: transform.translation,
+                is_player: true,
+            });
+        }
+    }
+    for (entity, transform, body) in props.iter() {
+        if *body == RigidBody::Dynamic && transform.translation.y < kill_plane {
+            fell.send(FellOutOfWorld {
+                entity,
+                position: transform.translation,
+                is_player: false,
+            });
+        }
+    }
+}
+
+pub fn respawn_fallen(
+    mut commands: Commands,
+    movement: Res<MovementConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    mut cubes: ResMut<SandboxCubes>,
+    mut fell: EventReader<FellOutOfWorld>,
+    mut player: Query<(&mut Transform, &mut PlayerVelocity), With<Player>>,
+) {
+    for event in fell.read() {
+        if !event.is_player {
+            info!("a prop fell out of the world at {}", event.position);
+            commands.entity(event.entity).despawn_recursive();
+            // so it isn't despawned again once there are too many cubes
+            cubes.remove(event.entity);
+            continue;
+        }
+        // usually there's a hole in the terrain's collider around here
+        warn!("the player fell out of the world at {}", event.position);
+        let ground = heights
+            .as_ref()
+            .and_then(|heights| heights.height_at(SPAWN_POINT.x, SPAWN_POINT.y))
+            .unwrap_or(0.0);
+        if let Ok((mut transform, mut velocity)) = player.get_mut(event.entity) {
+            transform.translation = Vec3::new(
+                SPAWN_POINT.x,
+                ground + movement.player_half_height(),
+                SPAWN_POINT.y,
+            );
+            velocity.0 = Vec3::ZERO;
+        }
+    }
+}
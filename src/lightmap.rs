@@ -0,0 +1,290 @@
+//! Sun shadows on the terrain baked into a texture. For every texel a ray is
+//! marched from the ground towards the sun over `TerrainHeights`, and the texel is
+//! lit if nothing sticks up in the way. The mountain shader darkens the ground by
+//! it, which costs one texture lookup instead of a shadow map pass every frame.
+//! The bake runs in the background and is redone when the sun has moved far
+//! enough or the terrain has been edited.
+//!
+//! The real-time shadow map is split into cascades out to `shadow_distance`, each
+//! blending into the next over part of its range so the steps in resolution don't
+//! pop. Bevy has no fade past the last cascade, so shadows still end there, and
+//! the distance is set where the objects casting them are small on screen.
+
+use bevy::pbr::{CascadeShadowConfig, CascadeShadowConfigBuilder};
+use bevy::prelude::*;
+use bevy::render::{
+    render_asset::RenderAssetUsages,
+    render_resource::{Extent3d, TextureDimension, TextureFormat},
+    texture::{ImageAddressMode, ImageSampler},
+};
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+
+use crate::controls::KeyBindings;
+use crate::sky::{DayNight, SkyPart};
+use crate::terrain::{TerrainConfig, TerrainHeights};
+use crate::texture_filtering::TextureFilteringConfig;
+use crate::{Mountain, MountainMaterial, TERRAIN_BASE_HEIGHT, TERRAIN_SIZE};
+
+// Where the terrain's sun shadows come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowMode {
+    // the lightmap, cheap but only shades the terrain itself
+    Baked,
+    // a shadow map on the sun, which the terrain shader doesn't read since it
+    // isn't lit, so only the objects on top of it get shadows
+    RealTime,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct LightmapConfig {
+    pub mode: ShadowMode,
+    // width of the lightmap in texels, it covers the whole terrain
+    pub resolution: u32,
+    // how far the sun moves, in degrees, before the lightmap is baked again
+    pub rebake_angle: f32,
+    // how much fully shadowed ground is darkened, 0.0 to 1.0
+    pub shadow_strength: f32,
+    // softness of shadow edges, as a difference in the tangent of the angle
+    // between the sun and whatever blocks it
+    pub penumbra: f32,
+    // real-time shadows stop this far from the camera, in world units
+    pub shadow_distance: f32,
+    // how far into the next cascade each cascade's shadows fade, 0.0 to 1.0 of its range
+    pub shadow_fade: f32,
+    // more cascades keeps nearby shadows sharp over a longer distance, at the cost
+    // of a shadow map pass each
+    pub shadow_cascades: usize,
+}
+
+impl LightmapConfig {
+    pub fn cascades(&self) -> CascadeShadowConfig {
+        CascadeShadowConfigBuilder {
+            num_cascades: self.shadow_cascades.max(1),
+            maximum_distance: self.shadow_distance,
+            // the first cascade covers about what's at the player's feet
+            first_cascade_far_bound: (self.shadow_distance / 20.0).max(1.0),
+            overlap_proportion: self.shadow_fade.clamp(0.0, 1.0),
+            ..default()
+        }
+        .build()
+    }
+}
+
+impl Default for LightmapConfig {
+    fn default() -> Self {
+        LightmapConfig {
+            mode: ShadowMode::Baked,
+            resolution: 256,
+            rebake_angle: 2.0,
+            shadow_strength: 0.6,
+            penumbra: 0.05,
+            // half the terrain, past that the objects are small enough not to miss them
+            shadow_distance: TERRAIN_SIZE / 2.0,
+            shadow_fade: 0.3,
+            shadow_cascades: 4,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Lightmap {
+    pub image: Handle<Image>,
+    // min x, min z, size x, size z of the area covered by the texture, in world space
+    pub bounds: Vec4,
+    // sun direction the current texture was baked for
+    baked_sun: Option<Vec3>,
+    // the terrain changed since the last bake started
+    stale: bool,
+    task: Option<Task<(Vec3, Vec<u8>)>>,
+}
+
+impl FromWorld for Lightmap {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.resource::<LightmapConfig>().clone();
+        let filtering = world.resource::<TextureFilteringConfig>().clone();
+        let terrain = world.resource::<TerrainConfig>();
+        let (wrap, extent) = (terrain.wraps(), terrain.extent);
+        // fully lit until the first bake is done
+        let mut image = Image::new_fill(
+            Extent3d {
+                width: config.resolution,
+                height: config.resolution,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[255],
+            TextureFormat::R8Unorm,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+        image.sampler = filtering.sampler();
+        // the copies around a wrapping terrain sample it outside of its bounds
+        if let (ImageSampler::Descriptor(descriptor), true) = (&mut image.sampler, wrap) {
+            descriptor.address_mode_u = ImageAddressMode::Repeat;
+            descriptor.address_mode_v = ImageAddressMode::Repeat;
+        }
+        Lightmap {
+            image: world.resource_mut::<Assets<Image>>().add(image),
+            bounds: Vec4::new(-extent.x / 2.0, -extent.y / 2.0, extent.x, extent.y),
+            baked_sun: None,
+            stale: true,
+            task: None,
+        }
+    }
+}
+
+impl Lightmap {
+    // The lightmap's darkening for the mountain material, nothing unless it's in use.
+    pub fn shadow_strength(config: &LightmapConfig) -> f32 {
+        match config.mode {
+            ShadowMode::Baked => config.shadow_strength,
+            ShadowMode::RealTime => 0.0,
+        }
+    }
+}
+
+// How much sun reaches each texel, 255 fully lit, row by row along z.
+// `sun_direction` points towards the sun.
+pub fn bake_lightmap(
+    heights: &TerrainHeights,
+    sun_direction: Vec3,
+    resolution: u32,
+    penumbra: f32,
+) -> Vec<u8> {
+    let resolution = resolution as usize;
+    let texel_size = heights.extent / resolution as f32;
+    // one step per vertex is as fine as the terrain gets
+    let step = heights.spacing().x;
+    let highest = heights.heights.iter().copied().fold(f32::MIN, f32::max) + TERRAIN_BASE_HEIGHT;
+    let horizontal = sun_direction.xz().length();
+    // rise of the ray towards the sun per unit of horizontal distance
+    let sun_slope = if horizontal > 0.0 {
+        sun_direction.y / horizontal
+    } else {
+        f32::INFINITY
+    };
+    let toward_sun = sun_direction.xz().normalize_or_zero();
+
+    let mut data = vec![0u8; resolution * resolution];
+    // under the horizon everything is in shadow
+    if sun_direction.y <= -penumbra {
+        return data;
+    }
+    for zi in 0..resolution {
+        for xi in 0..resolution {
+            let start =
+                -heights.extent / 2.0 + (Vec2::new(xi as f32, zi as f32) + 0.5) * texel_size;
+            let Some(ground) = heights.height_at(start.x, start.y) else {
+                continue;
+            };
+            // steepest slope up to anything between here and the sun
+            let mut horizon = f32::MIN;
+            let mut distance = step;
+            while toward_sun != Vec2::ZERO && ground + sun_slope * distance <= highest {
+                let point = start + toward_sun * distance;
+                let Some(height) = heights.height_at(point.x, point.y) else {
+                    break;
+                };
+                horizon = horizon.max((height - ground) / distance);
+                distance += step;
+            }
+            let lit = ((sun_slope - horizon) / penumbra * 0.5 + 0.5).clamp(0.0, 1.0);
+            data[zi * resolution + xi] = (lit * 255.0) as u8;
+        }
+    }
+    data
+}
+
+// Starts a bake when the sun has moved or the terrain changed, and uploads the
+// result once it's done.
+pub fn update_lightmap(
+    config: Res<LightmapConfig>,
+    day_night: Res<DayNight>,
+    heights: Option<Res<TerrainHeights>>,
+    mut lightmap: ResMut<Lightmap>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+) {
+    let Some(heights) = heights else {
+        return;
+    };
+    let lightmap = &mut *lightmap;
+    if heights.is_changed() {
+        lightmap.stale = true;
+    }
+
+    if let Some(task) = &mut lightmap.task {
+        let Some((sun, data)) = block_on(future::poll_once(task)) else {
+            return;
+        };
+        lightmap.task = None;
+        lightmap.baked_sun = Some(sun);
+        if let Some(image) = images.get_mut(&lightmap.image) {
+            if image.data.len() == data.len() {
+                image.data = data;
+            }
+        }
+        // make sure the material picks up the new texture
+        for handle in mountain.iter() {
+            materials.get_mut(handle);
+        }
+    }
+
+    if config.mode != ShadowMode::Baked {
+        return;
+    }
+    let sun = day_night.sun_direction();
+    let sun_moved = lightmap
+        .baked_sun
+        .is_none_or(|baked| baked.angle_between(sun) > config.rebake_angle.to_radians());
+    if !sun_moved && !lightmap.stale {
+        return;
+    }
+    lightmap.stale = false;
+    let heights = heights.clone();
+    let (resolution, penumbra) = (config.resolution, config.penumbra);
+    lightmap.task = Some(
+        AsyncComputeTaskPool::get()
+            .spawn(async move { (sun, bake_lightmap(&heights, sun, resolution, penumbra)) }),
+    );
+}
+
+// Swaps between baked and real-time shadows when its key is pressed.
+pub fn toggle_shadow_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut config: ResMut<LightmapConfig>,
+) {
+    if !keys.just_pressed(bindings.toggle_shadows) {
+        return;
+    }
+    config.mode = match config.mode {
+        ShadowMode::Baked => ShadowMode::RealTime,
+        ShadowMode::RealTime => ShadowMode::Baked,
+    };
+    info!("shadows: {:?}", config.mode);
+}
+
+// Switches between the lightmap and the sun's shadow map when the mode changes,
+// and sets up the shadow map's cascades.
+pub fn apply_shadow_mode(
+    config: Res<LightmapConfig>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+    mut lights: Query<(&SkyPart, &mut DirectionalLight, &mut CascadeShadowConfig)>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for handle in mountain.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.shadow_strength = Lightmap::shadow_strength(&config);
+        }
+    }
+    for (part, mut light, mut cascades) in lights.iter_mut() {
+        if *part == SkyPart::Sun {
+            light.shadows_enabled = config.mode == ShadowMode::RealTime;
+            *cascades = config.cascades();
+        }
+    }
+}
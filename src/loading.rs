@@ -0,0 +1,174 @@
+//! The loading screen. The game starts in `GameState::Loading` while the terrain
+//! is generated on a background task and the assets it needs load, and only
+//! moves on to the menu once everything is ready. Regenerating the terrain
+//! comes back through here too.
+
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy_rapier3d::prelude::Collider;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::terrain::{self, SeedLabel, TerrainConfig, TerrainHeights, TerrainSeed};
+use crate::GameState;
+
+// Assets that have to finish loading before we leave the loading screen.
+#[derive(Resource, Default)]
+pub struct LoadingAssets(pub Vec<UntypedHandle>);
+
+// The terrain being generated in the background. It's removed once the
+// mountain has been spawned.
+#[derive(Resource)]
+pub struct TerrainTask {
+    pub task: Task<(Collider, Mesh, TerrainHeights)>,
+    // steps done so far, counted up by the task, out of `total_steps`
+    pub progress: Arc<AtomicUsize>,
+    pub total_steps: usize,
+}
+
+impl TerrainTask {
+    // Starts generating a terrain in the background, `spawn_terrain` picks it up
+    // when it's done.
+    pub fn start(config: &TerrainConfig, seed: TerrainSeed) -> TerrainTask {
+        let config = config.clone();
+        let progress = Arc::new(AtomicUsize::new(0));
+        let task_progress = progress.clone();
+        let total_steps = terrain::generation_steps(&config);
+        let task = AsyncComputeTaskPool::get()
+            .spawn(async move { terrain::create_mountain_mesh(&config, seed, &task_progress) });
+        TerrainTask {
+            task,
+            progress,
+            total_steps,
+        }
+    }
+
+    // 0.0 to 1.0
+    pub fn fraction_done(&self) -> f32 {
+        let done = self.progress.load(Ordering::Relaxed);
+        (done as f32 / self.total_steps.max(1) as f32).min(1.0)
+    }
+}
+
+// Where the game goes once the terrain and assets are ready. The menu at
+// startup, straight back to playing when the terrain is regenerated.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct AfterLoading(pub GameState);
+
+impl Default for AfterLoading {
+    fn default() -> Self {
+        AfterLoading(GameState::Menu)
+    }
+}
+
+#[derive(Component)]
+pub struct LoadingScreen {}
+
+#[derive(Component)]
+pub struct LoadingText {}
+
+#[derive(Component)]
+pub struct ProgressBar {}
+
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+pub fn spawn_loading_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(12.0),
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                // draw above the controls text
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            LoadingScreen {},
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("Loading", TextStyle::default()),
+                LoadingText {},
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(300.0),
+                        height: Val::Px(12.0),
+                        ..default()
+                    },
+                    background_color: Color::srgb(0.2, 0.2, 0.2).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            ..default()
+                        },
+                        ProgressBar {},
+                    ));
+                });
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_loading_screen(
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    seed: Res<SeedLabel>,
+    loading_assets: Res<LoadingAssets>,
+    after_loading: Res<AfterLoading>,
+    terrain_task: Option<Res<TerrainTask>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut text: Query<&mut Text, With<LoadingText>>,
+    mut bar: Query<&mut Style, With<ProgressBar>>,
+) {
+    let loaded_assets = loading_assets
+        .0
+        .iter()
+        .filter(|handle| asset_server.is_loaded_with_dependencies(handle.id()))
+        .count();
+    // the terrain counts as much as one asset, filled in as it's generated
+    let terrain_fraction = terrain_task
+        .as_ref()
+        .map_or(1.0, |task| task.fraction_done());
+    let total = loading_assets.0.len() + 1;
+    let done = loaded_assets as f32 + terrain_fraction;
+
+    for mut style in bar.iter_mut() {
+        style.width = Val::Percent(100.0 * done / total as f32);
+    }
+
+    let spinner = SPINNER[(time.elapsed_seconds() * 8.0) as usize % SPINNER.len()];
+    let status = if terrain_task.is_none() {
+        "Loading assets".to_string()
+    } else {
+        format!("Generating terrain {:.0}%", terrain_fraction * 100.0)
+    };
+    for mut text in text.iter_mut() {
+        text.sections[0].value = format!("{} ({}) {}", status, seed.0, spinner);
+    }
+
+    if terrain_task.is_none() && loaded_assets == loading_assets.0.len() {
+        next_state.set(after_loading.0);
+    }
+}
+
+pub fn finish_loading(mut commands: Commands, loading_screen: Query<Entity, With<LoadingScreen>>) {
+    for entity in loading_screen.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
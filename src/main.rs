@@ -2,20 +2,117 @@
 //! assign a custom UV mapping for a custom texture,
 //! and how to change the UV mapping at run-time.
 
+mod audio;
+mod camera;
+mod clouds;
+mod compare_material;
+mod contours;
+mod controls;
+mod creatures;
+mod curvature;
+mod damage;
+mod debug_draw;
+mod flatten;
+mod flythrough;
+mod footprints;
+mod freeze;
+mod ghost;
+mod height_gradient;
+mod kill_plane;
+mod lightmap;
+mod loading;
+mod measure;
+mod menu;
+mod mesh_stats;
+mod movement;
+mod navigation;
+mod particles;
+mod path;
+mod physics;
+mod player;
+mod post_process;
+mod preset;
+mod render_scale;
+mod reverb;
+mod rocks;
+mod sandbox;
+mod scatter;
+mod settings;
+mod shading;
+mod sky;
+mod surface;
+mod surface_grid;
+mod teleport;
+mod terrain;
+mod terrain_cache;
+mod terrain_edit;
+mod texture_filtering;
+mod wetness;
+mod window;
+
+use audio::{SoundAssets, SpatialAudioConfig, Synth};
+use bevy::audio::AddAudioSource;
 use bevy::input::mouse::MouseMotion;
-use bevy::window::{CursorGrabMode, PrimaryWindow};
+use bevy::tasks::{block_on, futures_lite::future};
+use bevy::window::PrimaryWindow;
 use bevy_rapier3d::plugin::{NoUserData, RapierConfiguration, RapierPhysicsPlugin};
-use bevy_rapier3d::prelude::{Collider, GravityScale, KinematicCharacterController, RigidBody};
+use bevy_rapier3d::prelude::{
+    CharacterLength, Group, KinematicCharacterController, KinematicCharacterControllerOutput,
+    RigidBody,
+};
 use bevy_rapier3d::render::RapierDebugRenderPlugin;
-use rand::prelude::*;
-use std::f32::consts::PI;
-
+use camera::{
+    CameraConfig, CameraTarget, FovKick, FovKickConfig, LevelCamera, ProjectionTransition,
+};
+use clouds::{CloudConfig, CloudTexture, CloudUniform};
+use compare_material::ComparisonMaterial;
+use contours::{ContourConfig, ContourUniform};
+use controls::{ControlsText, KeyBindings};
+use creatures::CreatureConfig;
+use curvature::{CurvatureConfig, CurvatureUniform};
+use damage::{DamageConfig, PlayerDamaged};
+use debug_draw::{AxisGizmos, CrossSection, GridOverlay, MovementGizmos, NormalGizmos};
+use flatten::{FlattenConfig, FlattenTool};
+use flythrough::{Flythrough, FlythroughConfig, FlythroughPath};
+use footprints::{FootprintConfig, FootprintTrail, Footstep};
+use freeze::FreezeTime;
+use ghost::{GhostConfig, GhostRecorder};
+use height_gradient::{HeightGradient, HeightGradientUniform};
+use kill_plane::FellOutOfWorld;
+use lightmap::{Lightmap, LightmapConfig};
+use loading::{AfterLoading, LoadingAssets, TerrainTask};
+use measure::MeasureTool;
+use menu::Rebinding;
+use mesh_stats::MeshStatsConfig;
+use movement::{AutoWalk, JumpTimer, MovementConfig, PlayerVelocity, SlopeSpeedConfig};
+use navigation::{NavigationConfig, WalkabilityGrid};
+use particles::{ParticleAssets, ParticleConfig};
+use path::PathTool;
+use physics::PhysicsConfig;
+use player::PlayerLanded;
+use preset::{PresetCycle, TerrainPreset};
+use render_scale::RenderScaleConfig;
+use reverb::{Enclosure, ReverbConfig};
+use rocks::{RockAssets, RockConfig};
+use sandbox::{SandboxConfig, SandboxCubes};
+use scatter::{RescatterRegion, ScatterAssets, ScatterConfig};
+use settings::Settings;
+use shading::TerrainShading;
+use sky::{DayNight, SkyGradient, SkyMaterial};
+use surface::SurfaceConfig;
+use surface_grid::{SurfaceGridConfig, SurfaceGridUniform};
+use teleport::TeleportPrompt;
+use terrain::{BoundaryMode, TerrainConfig, TerrainHeights, TerrainSeed};
+use terrain_edit::{BrushConfig, TerrainEditBudget, TerrainEdits};
+use texture_filtering::TextureFilteringConfig;
+use wetness::{WetnessConfig, WetnessUniform};
+use window::{FrameWork, WindowConfig};
+
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
 use bevy::prelude::*;
-use bevy::render::render_resource::{AsBindGroup, ShaderRef};
-use bevy::render::{
-    mesh::{Indices, VertexAttributeValues},
-    render_asset::RenderAssetUsages,
-    render_resource::PrimitiveTopology,
+use bevy::render::mesh::{MeshVertexBufferLayoutRef, VertexAttributeDescriptor};
+use bevy::render::render_resource::{
+    AsBindGroup, Face, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
 };
 
 // Define a "marker" component to mark the custom mesh. Marker components are often used in Bevy for
@@ -23,56 +120,434 @@ use bevy::render::{
 #[derive(Component)]
 struct Mountain {}
 
+// The copies of the mountain around it when the terrain wraps.
+#[derive(Component)]
+struct MountainCopy {}
+
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
-struct MountainMaterial {}
+#[bind_group_data(MountainMaterialKey)]
+struct MountainMaterial {
+    // trail map written by the footprints system
+    #[texture(0)]
+    #[sampler(1)]
+    footprints: Handle<Image>,
+    // min x, min z, size x, size z of the world area the trail map covers
+    #[uniform(2)]
+    footprint_bounds: Vec4,
+    // see TextureFilteringConfig
+    #[uniform(3)]
+    mip_bias: f32,
+    // terrain color by height, see height_gradient.rs
+    #[uniform(4)]
+    height_gradient: HeightGradientUniform,
+    // baked sun shadows, see lightmap.rs
+    #[texture(5)]
+    #[sampler(6)]
+    lightmap: Handle<Image>,
+    // min x, min z, size x, size z of the world area the lightmap covers
+    #[uniform(7)]
+    lightmap_bounds: Vec4,
+    // how much the lightmap darkens shadowed ground, 0.0 when it's not in use
+    #[uniform(8)]
+    shadow_strength: f32,
+    // noise the cloud shadows are made of, see clouds.rs
+    #[texture(9)]
+    #[sampler(10)]
+    cloud_texture: Handle<Image>,
+    #[uniform(11)]
+    clouds: CloudUniform,
+    // how the shore looks, see wetness.rs
+    #[uniform(12)]
+    wetness: WetnessUniform,
+    // grid lines on the surface, see surface_grid.rs
+    #[uniform(13)]
+    surface_grid: SurfaceGridUniform,
+    // tint by the shape of the ground, see curvature.rs
+    #[uniform(14)]
+    curvature: CurvatureUniform,
+    // lines at height intervals, see contours.rs
+    #[uniform(15)]
+    contours: ContourUniform,
+    // which side of the triangles isn't drawn. The terrain is only ever seen from
+    // above, so its backs are culled. None draws both sides, which thin geometry
+    // seen from either side needs, or anything the camera can end up underneath.
+    cull_mode: Option<Face>,
+}
+
+// The parts of the material that need a different pipeline when they change.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct MountainMaterialKey {
+    cull_mode: Option<Face>,
+}
+
+impl From<&MountainMaterial> for MountainMaterialKey {
+    fn from(material: &MountainMaterial) -> Self {
+        MountainMaterialKey {
+            cull_mode: material.cull_mode,
+        }
+    }
+}
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum GameState {
+    #[default]
+    Loading,
+    Menu,
+    Playing,
+}
+
+const MOUNTAIN_SHADER: &str = "animate_shader.wgsl";
+
+// One world unit is one meter, everything else (player size, speeds, gravity,
+// camera planes) is tuned to match.
+// default width and depth of the terrain in world units, see TerrainConfig::extent
+const TERRAIN_SIZE: f32 = 400.0;
+// world space y of the mountain mesh's origin
+const TERRAIN_BASE_HEIGHT: f32 = 0.5;
+// collision group of things lying around on the terrain, rocks and thrown cubes
+const PROPS_GROUP: Group = Group::GROUP_2;
 
 fn main() {
+    let window_config = WindowConfig::default();
+    let (preset, preset_error) = TerrainPreset::load();
+    // huge terrains would hang the loading screen for ages, make them coarser instead
+    let (terrain_config, budget_warning) = preset.terrain.fit_budget();
+    let (seed_mode, seed_source) = preset.seed.with_env_override();
+    let (seed, seed_label) = seed_mode.resolve();
+    let settings = Settings::load();
+
     App::new()
         .add_plugins((
-            DefaultPlugins,
+            DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(window_config.window(Some(&seed_label))),
+                ..default()
+            }),
             MaterialPlugin::<MountainMaterial>::default(),
+            MaterialPlugin::<SkyMaterial>::default(),
+        ))
+        .insert_resource(window_config)
+        .insert_resource(seed)
+        .insert_resource(seed_label)
+        .insert_resource(PresetCycle::new(
+            terrain_config.clone(),
+            preset.name.clone(),
         ))
+        .insert_resource(terrain_config)
+        .insert_resource(preset.height_gradient)
+        .insert_resource(settings.key_bindings.clone())
+        .insert_resource(settings.audio.clone())
+        .insert_resource(settings.post_process.clone())
+        .insert_resource(settings.movement_config())
+        .insert_resource(settings)
+        .add_audio_source::<Synth>()
+        .init_resource::<SoundAssets>()
+        .init_resource::<SpatialAudioConfig>()
+        .init_resource::<ReverbConfig>()
+        .init_resource::<Enclosure>()
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
-        .add_plugins(RapierDebugRenderPlugin::default())
-        .add_systems(Startup, setup)
-        .add_systems(Update, (player_update, input_handler))
+        // off until toggled with the physics debug key
+        .add_plugins(RapierDebugRenderPlugin {
+            enabled: false,
+            ..default()
+        })
+        .init_resource::<Rebinding>()
+        .init_resource::<CameraConfig>()
+        .init_resource::<CameraTarget>()
+        .init_resource::<ProjectionTransition>()
+        .init_resource::<LevelCamera>()
+        .init_resource::<FlythroughConfig>()
+        .init_resource::<FlythroughPath>()
+        .init_resource::<Flythrough>()
+        .init_resource::<FovKickConfig>()
+        .init_resource::<FovKick>()
+        .init_resource::<PhysicsConfig>()
+        .init_resource::<RenderScaleConfig>()
+        .init_resource::<AutoWalk>()
+        .init_resource::<SlopeSpeedConfig>()
+        .init_resource::<TerrainEditBudget>()
+        .init_resource::<BrushConfig>()
+        .init_resource::<TerrainEdits>()
+        .init_resource::<MeshStatsConfig>()
+        .init_resource::<GridOverlay>()
+        .init_resource::<MovementGizmos>()
+        .init_resource::<AxisGizmos>()
+        .init_resource::<NormalGizmos>()
+        .init_resource::<TerrainShading>()
+        .init_resource::<CrossSection>()
+        .init_resource::<MeasureTool>()
+        .init_resource::<PathTool>()
+        .init_resource::<FlattenConfig>()
+        .init_resource::<FlattenTool>()
+        .init_resource::<SandboxConfig>()
+        .init_resource::<SandboxCubes>()
+        .init_resource::<TeleportPrompt>()
+        .init_resource::<ScatterConfig>()
+        .init_resource::<ScatterAssets>()
+        .init_resource::<RockConfig>()
+        .init_resource::<RockAssets>()
+        .add_event::<RescatterRegion>()
+        .add_event::<PlayerDamaged>()
+        .init_resource::<DamageConfig>()
+        .init_resource::<TextureFilteringConfig>()
+        .init_resource::<FootprintConfig>()
+        .init_resource::<FootprintTrail>()
+        .init_resource::<SurfaceConfig>()
+        .init_resource::<ParticleConfig>()
+        .init_resource::<ParticleAssets>()
+        .add_event::<Footstep>()
+        .add_event::<PlayerLanded>()
+        .add_event::<FellOutOfWorld>()
+        .init_resource::<LoadingAssets>()
+        .init_resource::<AfterLoading>()
+        .init_resource::<DayNight>()
+        .init_resource::<SkyGradient>()
+        .init_resource::<FreezeTime>()
+        .init_resource::<NavigationConfig>()
+        .init_resource::<CreatureConfig>()
+        .init_resource::<GhostConfig>()
+        .init_resource::<GhostRecorder>()
+        .init_resource::<LightmapConfig>()
+        .init_resource::<Lightmap>()
+        .init_resource::<CloudConfig>()
+        .init_resource::<CloudTexture>()
+        .init_resource::<WetnessConfig>()
+        .init_resource::<CurvatureConfig>()
+        .init_resource::<SurfaceGridConfig>()
+        .init_resource::<ContourConfig>()
+        .init_state::<GameState>()
+        .add_systems(
+            Startup,
+            (
+                setup,
+                player::spawn_player_body.after(setup),
+                render_scale::setup_render_scale.after(setup),
+                preset::spawn_preset_label,
+                audio::start_wind,
+                sky::spawn_sky,
+                ghost::load_ghost,
+                path::load_path,
+                flythrough::load_flythrough,
+                damage::spawn_vignette,
+                // the preset and seed are read before logging is set up
+                move || {
+                    if let Some(err) = &preset_error {
+                        error!("{}", err);
+                    }
+                    if let Some(warning) = &budget_warning {
+                        warn!("{}", warning);
+                    }
+                    info!("{}: {}", seed_source, seed.0);
+                },
+            ),
+        )
+        .add_systems(
+            Update,
+            (spawn_terrain, loading::update_loading_screen)
+                .chain()
+                .run_if(in_state(GameState::Loading)),
+        )
+        .init_resource::<FrameWork>()
+        .add_systems(Last, window::limit_frame_rate)
+        .add_systems(Update, window::update_window_title)
+        .add_systems(Update, physics::apply_physics_config)
+        .add_systems(Update, render_scale::adapt_render_scale)
+        .add_systems(
+            OnEnter(GameState::Loading),
+            (preset::clear_old_terrain, loading::spawn_loading_screen),
+        )
+        .add_systems(OnExit(GameState::Loading), loading::finish_loading)
+        .add_systems(OnEnter(GameState::Menu), menu::spawn_menu)
+        .add_systems(OnExit(GameState::Menu), menu::despawn_menu)
+        .add_systems(
+            Update,
+            (
+                menu::menu_buttons,
+                menu::menu_keys,
+                menu::update_menu_text
+                    .after(menu::menu_buttons)
+                    .after(menu::menu_keys),
+            )
+                .run_if(in_state(GameState::Menu)),
+        )
+        .add_systems(OnEnter(GameState::Playing), menu::start_playing)
+        .add_systems(OnExit(GameState::Playing), menu::stop_playing)
+        .add_systems(
+            Update,
+            (
+                scatter::handle_rescatter_events,
+                scatter::update_scatter_density,
+                rocks::handle_rock_rescatter,
+                navigation::update_walkability,
+                window::handle_focus_change,
+                creatures::spawn_creatures.run_if(resource_added::<WalkabilityGrid>),
+                settings::apply_settings,
+                camera::update_fov_kick.after(settings::apply_settings),
+                controls::update_controls_text.after(settings::apply_settings),
+                audio::update_volumes.after(settings::apply_settings),
+                audio::play_footsteps,
+                audio::apply_spatial_config,
+                audio::start_emitters,
+                audio::update_emitters.after(settings::apply_settings),
+                particles::emit_particles,
+                sky::update_sky.after(player_update),
+                lightmap::update_lightmap,
+                (lightmap::toggle_shadow_mode, lightmap::apply_shadow_mode).chain(),
+                // config changes the terrain's material needs to hear about
+                (
+                    clouds::apply_cloud_config,
+                    wetness::apply_wetness_config,
+                    curvature::apply_curvature_config,
+                    surface_grid::apply_surface_grid,
+                    contours::apply_contours,
+                ),
+                post_process::apply_post_process.after(settings::apply_settings),
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                menu::open_menu
+                    .run_if(teleport::prompt_closed)
+                    .before(teleport::teleport_prompt),
+                camera::toggle_projection,
+                sky::advance_day_night.before(sky::update_sky),
+                player_update,
+                input_handler,
+                camera::keep_camera_out_of_terrain
+                    .after(player_update)
+                    .after(input_handler),
+                movement::apply_velocity.after(input_handler),
+                player::update_player_state.after(movement::apply_velocity),
+                player::animate_player_body.after(player::update_player_state),
+                footprints::update_footprints.after(movement::apply_velocity),
+                terrain_edit::apply_brush,
+                creatures::wander.after(movement::apply_velocity),
+                ghost::record_ghost.after(movement::apply_velocity),
+                ghost::play_ghost,
+                height_gradient::update_height_gradient,
+                terrain_edit::apply_terrain_edits.after(terrain_edit::apply_brush),
+                terrain::wrap_player.after(movement::apply_velocity),
+                particles::update_particles,
+                kill_plane::detect_fall_out_of_world.after(movement::apply_velocity),
+                kill_plane::respawn_fallen.after(kill_plane::detect_fall_out_of_world),
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (
+                mesh_stats::log_mesh_stats,
+                debug_draw::toggle_grid,
+                debug_draw::draw_grid.after(debug_draw::toggle_grid),
+                debug_draw::toggle_movement_gizmos,
+                debug_draw::draw_movement_gizmos
+                    .after(debug_draw::toggle_movement_gizmos)
+                    .after(movement::apply_velocity),
+                teleport::open_teleport_prompt,
+                teleport::teleport_prompt
+                    .after(teleport::open_teleport_prompt)
+                    .after(movement::apply_velocity),
+                debug_draw::toggle_physics_debug,
+                sky::toggle_sun_lock.before(sky::update_sky),
+                debug_draw::toggle_axis_gizmos,
+                debug_draw::draw_axis_gizmos.after(debug_draw::toggle_axis_gizmos),
+                debug_draw::toggle_cross_section,
+                debug_draw::draw_cross_section
+                    .after(debug_draw::toggle_cross_section)
+                    .after(movement::apply_velocity),
+                measure::toggle_measure,
+                freeze::toggle_freeze_time,
+                sandbox::spawn_cubes,
+                compare_material::toggle_terrain_material,
+                camera::look_at_target
+                    .after(input_handler)
+                    .before(camera::keep_camera_out_of_terrain),
+                measure::measure_clicks.after(measure::toggle_measure),
+                measure::draw_measure.after(measure::measure_clicks),
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
+        .add_systems(
+            Update,
+            (
+                path::toggle_path_tool,
+                terrain_edit::toggle_brush_mode.before(terrain_edit::apply_brush),
+                flatten::toggle_flatten,
+                flatten::flatten_clicks.after(flatten::toggle_flatten),
+                flatten::apply_flatten
+                    .after(flatten::flatten_clicks)
+                    .before(terrain_edit::apply_terrain_edits),
+                flatten::draw_flatten.after(flatten::apply_flatten),
+                rocks::update_rock_colliders,
+                (
+                    camera::level_camera.after(input_handler),
+                    camera::look_at_crosshair.before(camera::look_at_target),
+                ),
+                reverb::update_enclosure,
+                reverb::shelter_wind.after(reverb::update_enclosure),
+                flythrough::flythrough_keys,
+                (surface_grid::toggle_surface_grid, contours::toggle_contours),
+                reload_mountain_material,
+                (
+                    damage::fall_damage,
+                    damage::update_vignette.after(damage::fall_damage),
+                ),
+                (
+                    preset::cycle_presets,
+                    preset::save_preset,
+                    preset::update_preset_label.after(preset::cycle_presets),
+                ),
+                flythrough::play_flythrough
+                    .after(flythrough::flythrough_keys)
+                    .after(camera::keep_camera_out_of_terrain)
+                    .after(camera::level_camera)
+                    .after(camera::look_at_target),
+                path::path_clicks.after(path::toggle_path_tool),
+                path::draw_path.after(path::path_clicks),
+                camera::update_projection_transition
+                    .after(camera::toggle_projection)
+                    .after(camera::keep_camera_out_of_terrain)
+                    .after(flythrough::play_flythrough),
+                (
+                    debug_draw::toggle_normal_gizmos,
+                    debug_draw::draw_normal_gizmos.after(debug_draw::toggle_normal_gizmos),
+                    shading::toggle_faceted_shading,
+                    terrain_edit::toggle_terrain_collider,
+                ),
+            )
+                .run_if(in_state(GameState::Playing)),
+        )
         .run();
 }
 
 #[derive(Component)]
 struct Player {}
 
+#[allow(clippy::too_many_arguments)]
 fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<MountainMaterial>>,
-    mut std_materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut q_windows: Query<&mut Window, With<PrimaryWindow>>,
+    mut loading_assets: ResMut<LoadingAssets>,
+    movement_config: Res<MovementConfig>,
+    physics_config: Res<PhysicsConfig>,
+    terrain_config: Res<TerrainConfig>,
+    seed: Res<TerrainSeed>,
+    camera_config: Res<CameraConfig>,
+    bindings: Res<KeyBindings>,
+    settings: Res<Settings>,
+    spatial_audio: Res<SpatialAudioConfig>,
 ) {
     let mut config = RapierConfiguration::new(1.0);
+    config.gravity = physics_config.rapier_gravity();
+    // nothing should move until the terrain is there to stand on
+    config.physics_pipeline_active = false;
     commands.insert_resource(config);
 
-    let (collider, mountain_mesh) = create_mountain_mesh();
-    // Create and save a handle to the mesh.
-    let cube_mesh_handle: Handle<Mesh> = meshes.add(mountain_mesh);
-
-    commands
-        .spawn((
-            MaterialMeshBundle {
-                mesh: cube_mesh_handle,
-                transform: Transform::from_xyz(0.0, 0.5, 0.0),
-                material: materials.add(MountainMaterial {}),
-                /*material: std_materials.add(StandardMaterial {
-                    metallic: 1.0,
-                    base_color: Color::srgb(1.0, 0.5, 0.5),
-                    ..default()
-                }),*/
-                ..default()
-            },
-            Mountain {},
-        ))
-        .insert(collider);
+    // Generate the terrain in the background, spawn_terrain picks it up when it's done.
+    commands.insert_resource(TerrainTask::start(&terrain_config, *seed));
+    loading_assets
+        .0
+        .push(asset_server.load::<Shader>(MOUNTAIN_SHADER).untyped());
 
     // Transform for the camera and lighting, looking at (0,0,0) (the position of the mesh).
     let camera_and_light_transform =
@@ -80,19 +555,34 @@ fn setup(
 
     commands
         .spawn(Player {})
-        .insert(camera_and_light_transform)
+        // the player itself stays upright, only the camera looks around
+        .insert(SpatialBundle::from_transform(Transform::from_translation(
+            camera_and_light_transform.translation,
+        )))
         .insert(RigidBody::KinematicPositionBased)
-        .insert(Collider::ball(0.5))
-        .insert(SpatialBundle::default())
+        .insert(movement_config.player_collider())
+        .insert(PlayerVelocity::default())
         .insert(KinematicCharacterController {
+            offset: CharacterLength::Absolute(movement_config.skin_width),
             ..KinematicCharacterController::default()
-        });
+        })
+        .insert(KinematicCharacterControllerOutput::default());
 
     // Camera in 3D space.
-    commands.spawn(Camera3dBundle {
-        transform: camera_and_light_transform,
-        ..default()
-    });
+    commands.spawn((
+        Camera3dBundle {
+            // HDR so bright highlights can bloom, see `post_process`
+            camera: Camera {
+                hdr: true,
+                ..default()
+            },
+            transform: camera_and_light_transform,
+            projection: camera_config.projection(settings.fov),
+            ..default()
+        },
+        // sounds in the world are heard from the camera
+        audio::listener(&spatial_audio),
+    ));
 
     // Light up the scene.
     commands.spawn(PointLightBundle {
@@ -101,210 +591,334 @@ fn setup(
     });
 
     // Text to describe the controls.
-    commands.spawn(
-        TextBundle::from_section(
-            "Controls:\nMouse to look around\nWASD: movement",
-            TextStyle::default(),
-        )
-        .with_style(Style {
-            position_type: PositionType::Absolute,
-            top: Val::Px(12.0),
-            left: Val::Px(12.0),
+    commands.spawn((
+        TextBundle::from_section(controls::controls_text(&bindings), TextStyle::default())
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.0),
+                left: Val::Px(12.0),
+                ..default()
+            }),
+        ControlsText {},
+    ));
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn spawn_terrain(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mut std_materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    trail: Res<FootprintTrail>,
+    filtering: Res<TextureFilteringConfig>,
+    gradient: Res<HeightGradient>,
+    lightmap: Res<Lightmap>,
+    (clouds, cloud_texture): (Res<CloudConfig>, Res<CloudTexture>),
+    (wetness, surface_grid, curvature, shading, contours): (
+        Res<WetnessConfig>,
+        Res<SurfaceGridConfig>,
+        Res<CurvatureConfig>,
+        Res<TerrainShading>,
+        Res<ContourConfig>,
+    ),
+    lightmap_config: Res<LightmapConfig>,
+    movement: Res<MovementConfig>,
+    terrain_config: Res<TerrainConfig>,
+    task: Option<ResMut<TerrainTask>>,
+    mut rescatter: EventWriter<RescatterRegion>,
+    mut player: Query<&mut Transform, With<Player>>,
+) {
+    let Some(mut task) = task else {
+        return;
+    };
+    let Some((collider, mut mountain_mesh, heights)) = block_on(future::poll_once(&mut task.task))
+    else {
+        return;
+    };
+    commands.remove_resource::<TerrainTask>();
+    let (min_height, max_height) = height_gradient::height_range(&heights);
+    // start out standing on the ground rather than buried in it or high above it
+    for mut player in player.iter_mut() {
+        let position = player.translation.xz();
+        if let Some(height) = heights.height_at(position.x, position.y) {
+            player.translation.y = height + movement.player_half_height();
+        }
+    }
+    // Create and save a handle to the mesh.
+    wetness::write_wetness(&mut mountain_mesh, &wetness);
+    curvature::write_curvature(&mut mountain_mesh, &heights, &curvature);
+    if shading.faceted {
+        terrain::facet_mesh(&mut mountain_mesh);
+    }
+    rescatter.send(RescatterRegion(heights.bounds()));
+    commands.insert_resource(heights);
+    let cube_mesh_handle: Handle<Mesh> = meshes.add(mountain_mesh);
+
+    let material = materials.add(MountainMaterial {
+        footprints: trail.image.clone(),
+        footprint_bounds: trail.bounds,
+        mip_bias: filtering.mip_bias,
+        height_gradient: gradient.uniform(min_height, max_height),
+        lightmap: lightmap.image.clone(),
+        lightmap_bounds: lightmap.bounds,
+        shadow_strength: Lightmap::shadow_strength(&lightmap_config),
+        cloud_texture: cloud_texture.image.clone(),
+        clouds: clouds.uniform(),
+        wetness: wetness.uniform(),
+        surface_grid: surface_grid.uniform(),
+        curvature: curvature.uniform(),
+        contours: contours.uniform(),
+        cull_mode: Some(Face::Back),
+    });
+
+    // for swapping to StandardMaterial, see compare_material.rs
+    commands.insert_resource(ComparisonMaterial::new(
+        material.clone(),
+        &gradient,
+        &mut std_materials,
+    ));
+
+    let mut mountain = commands.spawn((
+        MaterialMeshBundle {
+            mesh: cube_mesh_handle.clone(),
+            transform: Transform::from_xyz(0.0, TERRAIN_BASE_HEIGHT, 0.0),
+            material: material.clone(),
             ..default()
-        }),
-    );
+        },
+        Mountain {},
+    ));
+    mountain.insert(collider);
+
+    if terrain_config.boundary == BoundaryMode::Walls {
+        mountain.with_children(|parent| {
+            for (transform, collider) in terrain_config.boundary_walls() {
+                parent.spawn((TransformBundle::from_transform(transform), collider));
+            }
+        });
+    }
 
-    let mut primary_window = q_windows.single_mut();
-    // for a game that doesn't use the cursor (like a shooter):
-    // use `Locked` mode to keep the cursor in one place
-    primary_window.cursor.grab_mode = CursorGrabMode::Locked;
+    // when the terrain wraps, surround it with copies of itself so there's no edge
+    // to see, they're only for looks and share the mesh so edits show up on them too
+    if terrain_config.wraps() {
+        mountain.with_children(|parent| {
+            for (dx, dz) in (-1..=1).flat_map(|dx| (-1..=1).map(move |dz| (dx, dz))) {
+                if (dx, dz) == (0, 0) {
+                    continue;
+                }
+                parent.spawn((
+                    MaterialMeshBundle {
+                        mesh: cube_mesh_handle.clone(),
+                        transform: Transform::from_xyz(
+                            dx as f32 * terrain_config.extent.x,
+                            0.0,
+                            dz as f32 * terrain_config.extent.y,
+                        ),
+                        material: material.clone(),
+                        ..default()
+                    },
+                    MountainCopy {},
+                ));
+            }
+        });
+    }
+}
 
-    // also hide the cursor
-    primary_window.cursor.visible = false;
+// Sets every uniform of the terrain material from the current config again,
+// leaving the mesh alone. With the shader hot reloading, that's all a tweak to
+// the shader's parameters needs to show up.
+#[allow(clippy::too_many_arguments)]
+fn reload_mountain_material(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    filtering: Res<TextureFilteringConfig>,
+    gradient: Res<HeightGradient>,
+    heights: Option<Res<TerrainHeights>>,
+    (clouds, lightmap_config): (Res<CloudConfig>, Res<LightmapConfig>),
+    (wetness, surface_grid, curvature, contours): (
+        Res<WetnessConfig>,
+        Res<SurfaceGridConfig>,
+        Res<CurvatureConfig>,
+        Res<ContourConfig>,
+    ),
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+) {
+    if !keys.just_pressed(bindings.reload_material) {
+        return;
+    }
+    let Some(heights) = heights else {
+        return;
+    };
+    let (min_height, max_height) = height_gradient::height_range(&heights);
+    for handle in mountain.iter() {
+        let Some(material) = materials.get_mut(handle) else {
+            continue;
+        };
+        material.mip_bias = filtering.mip_bias;
+        material.height_gradient = gradient.uniform(min_height, max_height);
+        material.shadow_strength = Lightmap::shadow_strength(&lightmap_config);
+        material.clouds = clouds.uniform();
+        material.wetness = wetness.uniform();
+        material.surface_grid = surface_grid.uniform();
+        material.curvature = curvature.uniform();
+        material.contours = contours.uniform();
+        info!("reloaded the terrain material");
+    }
 }
 
 fn player_update(
-    mut camera: Query<&mut Transform, With<Camera>>,
-    mut player: Query<&mut Transform, (With<Player>, Without<Camera>)>,
+    movement: Res<MovementConfig>,
+    mut camera: Query<&mut Transform, With<Camera3d>>,
+    mut player: Query<&mut Transform, (With<Player>, Without<Camera3d>)>,
 ) {
     let player = player.get_single_mut().unwrap();
     let mut camera = camera.get_single_mut().unwrap();
-    camera.translation = player.translation;
+    camera.translation = player.translation + movement.eye_offset();
 }
 
 // System to receive input from the user,
 // check out examples/input/ for more examples about user input.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn input_handler(
+    time: Res<Time>,
+    config: Res<MovementConfig>,
+    camera_config: Res<CameraConfig>,
+    settings: Res<Settings>,
+    bindings: Res<KeyBindings>,
+    slope_config: Res<SlopeSpeedConfig>,
+    heights: Res<TerrainHeights>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
+    (camera_target, transition): (Res<CameraTarget>, Res<ProjectionTransition>),
+    flythrough: Res<Flythrough>,
+    mut auto_walk: ResMut<AutoWalk>,
+    mut jump: Local<JumpTimer>,
     mut evr_motion: EventReader<MouseMotion>,
-    mut player: Query<&mut Transform, With<Player>>,
-    mut camera: Query<&mut Transform, (With<Camera>, Without<Player>)>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut player: Query<
+        (
+            &mut PlayerVelocity,
+            &KinematicCharacterControllerOutput,
+            &Transform,
+        ),
+        With<Player>,
+    >,
+    mut camera: Query<&mut Transform, (With<Camera3d>, Without<Player>)>,
 ) {
-    let mut player = player.get_single_mut().unwrap();
+    let (mut velocity, output, transform) = player.get_single_mut().unwrap();
     let mut camera = camera.get_single_mut().unwrap();
-    for ev in evr_motion.read() {
-        // rotate the camera relative to the x and y
-        camera.rotate_local(
-            Quat::from_rotation_x(-ev.delta.y / 1000.0)
-                * Quat::from_rotation_y(-ev.delta.x / 1000.0),
-        );
+    // the flythrough has the camera, stand still until it's done
+    if flythrough.playing {
+        velocity.0.x = 0.0;
+        velocity.0.z = 0.0;
+        evr_motion.clear();
+        return;
     }
-
-    if keyboard_input.pressed(KeyCode::KeyW) {
-        let forward = camera.forward();
-        player.translation += forward * 0.01;
+    let sensitivity = settings.mouse_sensitivity / 1000.0;
+    // the mouse keeps moving while another window has focus, don't look around then,
+    // or while the camera is turning towards a target or changing projection on its own
+    let focused = window.get_single().is_ok_and(|window| window.focused);
+    let looking = focused && !camera_target.is_active() && !transition.active;
+    let delta = camera::look_delta(
+        evr_motion.read().filter(|_| looking).map(|ev| ev.delta),
+        camera_config.max_look_delta,
+    );
+    // the roll keys roll the camera, the same speed at any frame rate
+    let mut roll = 0.0;
+    if keyboard_input.pressed(bindings.roll_right) && !transition.active {
+        roll += 1.0;
     }
-
-    if keyboard_input.pressed(KeyCode::KeyA) {
-        let left = camera.left();
-        player.translation += left * 0.01;
+    if keyboard_input.pressed(bindings.roll_left) && !transition.active {
+        roll -= 1.0;
     }
+    camera.rotation = camera::look_rotation(
+        camera.rotation,
+        delta * sensitivity,
+        roll * camera_config.roll_speed * time.delta_seconds(),
+        settings.roll_allowed(),
+        &camera_config,
+    );
 
-    if keyboard_input.pressed(KeyCode::KeyS) {
-        let back = camera.back();
-        player.translation += back * 0.01;
-    }
+    // only the horizontal part of the movement comes from the keys, gravity handles the rest
+    let mut input = Vec2::ZERO;
 
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        let right = camera.right();
-        player.translation += right * 0.01;
+    if keyboard_input.pressed(bindings.forward) {
+        input.y += 1.0;
     }
 
-    if keyboard_input.pressed(KeyCode::KeyE) {
-        let forward = camera.forward();
-        camera.rotate_axis(forward, 0.05);
+    if keyboard_input.pressed(bindings.left) {
+        input.x -= 1.0;
     }
 
-    if keyboard_input.pressed(KeyCode::KeyQ) {
-        let forward = camera.forward();
-        camera.rotate_axis(forward, -0.05);
+    if keyboard_input.pressed(bindings.back) {
+        input.y -= 1.0;
     }
 
-    if keyboard_input.pressed(KeyCode::Space) {
-        let up = camera.up();
-        player.translation += up * 0.02;
+    if keyboard_input.pressed(bindings.right) {
+        input.x += 1.0;
     }
-}
 
-// smoothly interpolates between some points using a special polynomial from this video's beginning:
-// https://www.youtube.com/watch?v=BFld4EBO2RE
-// interpolate_step is how many points there are between random points
-fn interpolate_random_points(
-    points: &[Vec<f32>],
-    xi: usize,
-    yi: usize,
-    interpolate_step: usize,
-) -> f32 {
-    let s_polynomial = |val: f32| 3.0 * (val * val * val) - 2.0 * val * val;
-    let rand_a = points[xi / interpolate_step][yi / interpolate_step];
-    let rand_b = points[xi / interpolate_step + 1][yi / interpolate_step];
-    let rand_c = points[xi / interpolate_step][yi / interpolate_step + 1];
-    let rand_d = points[xi / interpolate_step + 1][yi / interpolate_step + 1];
-
-    let rel_x =
-        ((xi - (interpolate_step * (xi / interpolate_step))) as f32) / (interpolate_step as f32);
-    let rel_y =
-        ((yi - (interpolate_step * (yi / interpolate_step))) as f32) / (interpolate_step as f32);
-    // interpolate smoothly between them
-    rand_a
-        + (rand_b - rand_a) * s_polynomial(rel_x)
-        + (rand_c - rand_a) * s_polynomial(rel_y)
-        + (rand_a - rand_b - rand_c + rand_d) * s_polynomial(rel_x) * s_polynomial(rel_y)
-}
-
-fn create_mountain_mesh() -> (Collider, Mesh) {
-    let mut random_positions: Vec<Vec<f32>> = vec![];
-    let mut rng = rand::thread_rng();
-    // add a ton of random positions so we never go out of bounds
-    for _i in 0..1000 {
-        let mut random_row = vec![];
-        for _j in 0..1000 {
-            random_row.push(rng.gen());
-        }
-        random_positions.push(random_row);
+    if keyboard_input.just_pressed(bindings.auto_walk) {
+        auto_walk.0 = !auto_walk.0;
     }
-
-    let mut vertex_positions = vec![];
-    let mut collision_heights = vec![];
-    let mut uv_positions = vec![];
-    let mut triangles = vec![];
-    let mut normals = vec![];
-
-    let x_max = 200;
-    let interpolate_step = 20;
-    let y_max = 200;
-    let last_index = (x_max * y_max) - 1;
-
-    for xi in 0..x_max {
-        for zi in 0..y_max {
-            let y = interpolate_random_points(&random_positions, xi, zi, interpolate_step)
-                + 0.5
-                    * interpolate_random_points(
-                        &random_positions,
-                        xi * 2,
-                        zi * 2,
-                        interpolate_step,
-                    );
-
-            collision_heights.push(y);
-
-            vertex_positions.push([
-                ((xi as f32) / (x_max as f32)) * 4.0 - 2.0,
-                y,
-                ((zi as f32) / (y_max as f32)) * 4.0 - 2.0,
-            ]);
-            uv_positions.push([(xi as f32) / (x_max as f32), (zi as f32) / (y_max as f32)]);
-            normals.push([0.0, 0.0, 1.0]);
-
-            // we make squares, so two triangles per index
-            let index = xi * y_max + zi;
-            let index_right = (xi + 1) * y_max + zi;
-            let index_down = xi * y_max + zi + 1;
-            let index_down_right = (xi + 1) * y_max + zi + 1;
-
-            if index_down_right <= last_index {
-                triangles.extend(vec![
-                    index_right as u32,
-                    index as u32,
-                    index_down_right as u32,
-                ]);
-                triangles.extend(vec![
-                    index_down_right as u32,
-                    index as u32,
-                    index_down as u32,
-                ]);
-            }
-        }
+    if auto_walk.0 && (input != Vec2::ZERO || movement::blocked(output)) {
+        auto_walk.0 = false;
+    }
+    if auto_walk.0 {
+        input.y = 1.0;
+    }
+    let movement = movement::walk_direction(&camera, input);
+
+    let speed = config.walk_speed
+        * movement::slope_speed_factor(
+            &slope_config,
+            &heights,
+            transform.translation.xz(),
+            movement.xz(),
+        );
+    let horizontal = movement::accelerate(
+        velocity.0.xz(),
+        movement.xz() * speed,
+        output.grounded,
+        time.delta_seconds(),
+        &config,
+    );
+    velocity.0.x = horizontal.x;
+    velocity.0.z = horizontal.y;
+
+    if jump.update(
+        keyboard_input.just_pressed(bindings.jump),
+        output.grounded,
+        time.delta_seconds(),
+        &config,
+    ) {
+        velocity.0.y = config.jump_speed;
     }
-
-    // Keep the mesh data accessible in future frames to be able to mutate it in toggle_texture.
-    (
-        Collider::heightfield(collision_heights, x_max, y_max, Vec3::new(4.0, 1.0, 4.0)),
-        Mesh::new(
-            PrimitiveTopology::TriangleList,
-            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-        )
-        .with_inserted_attribute(
-            Mesh::ATTRIBUTE_POSITION,
-            // Each array is an [x, y, z] coordinate in local space.
-            // The camera coordinate space is right-handed x-right, y-up, z-back. This means "forward" is -Z.
-            // Meshes always rotate around their local [0, 0, 0] when a rotation is applied to their Transform.
-            // By centering our mesh around the origin, rotating the mesh preserves its center of mass.
-            vertex_positions,
-        )
-        // make uv the same as vertex positions XD
-        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uv_positions)
-        //.with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
-        .with_inserted_indices(Indices::U32(triangles))
-        .with_computed_normals(),
-    )
 }
 
-// Function that changes the UV mapping of the mesh, to apply the other texture.
-fn toggle_texture(mesh_to_change: &mut Mesh) {}
-
 impl Material for MountainMaterial {
+    fn vertex_shader() -> ShaderRef {
+        MOUNTAIN_SHADER.into()
+    }
+
     fn fragment_shader() -> ShaderRef {
-        "animate_shader.wgsl".into()
+        MOUNTAIN_SHADER.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayoutRef,
+        key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.primitive.cull_mode = key.bind_group_data.cull_mode;
+        // the locations the terrain's vertex shader reads them from
+        let attributes: [VertexAttributeDescriptor; 4] = [
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            wetness::ATTRIBUTE_WETNESS.at_shader_location(8),
+            curvature::ATTRIBUTE_CURVATURE.at_shader_location(9),
+        ];
+        descriptor.vertex.buffers = vec![layout.0.get_layout(&attributes)?];
+        Ok(())
     }
 }
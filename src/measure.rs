@@ -0,0 +1,142 @@
+//! A tape measure for level design. In measure mode clicking two points on the
+//! terrain shows the straight line distance between them and the distance
+//! walking over the ground, and draws both lines. The brush is off while
+//! measuring so the clicks don't dig into the terrain.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::RapierContext;
+
+use crate::controls::KeyBindings;
+use crate::terrain::TerrainHeights;
+use crate::terrain_edit::terrain_under_crosshair;
+use crate::{Mountain, Player};
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct MeasureTool {
+    pub active: bool,
+    // the clicked points, a third click starts over
+    pub points: Vec<Vec3>,
+}
+
+#[derive(Component)]
+pub struct MeasureText {}
+
+// how far away points can be clicked
+const MEASURE_REACH: f32 = 500.0;
+// lift the lines a little so they don't z-fight with the ground
+const MEASURE_LIFT: f32 = 0.05;
+const STRAIGHT_COLOR: Color = Color::srgb(0.3, 0.8, 1.0);
+const SURFACE_COLOR: Color = Color::srgb(1.0, 0.5, 0.2);
+
+// Points on the terrain along the straight xz line from `a` to `b`, one per
+// `step` world units, skipping anything off the terrain.
+pub fn surface_path(heights: &TerrainHeights, a: Vec3, b: Vec3, step: f32) -> Vec<Vec3> {
+    let samples = ((a.xz().distance(b.xz()) / step).ceil() as usize).max(1);
+    (0..=samples)
+        .filter_map(|i| {
+            let point = a.xz().lerp(b.xz(), i as f32 / samples as f32);
+            heights
+                .height_at(point.x, point.y)
+                .map(|y| Vec3::new(point.x, y, point.y))
+        })
+        .collect()
+}
+
+fn path_length(path: &[Vec3]) -> f32 {
+    path.windows(2).map(|pair| pair[0].distance(pair[1])).sum()
+}
+
+// Turns measure mode on or off, leaving it clears the points.
+pub fn toggle_measure(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut tool: ResMut<MeasureTool>,
+    text: Query<Entity, With<MeasureText>>,
+) {
+    if !keys.just_pressed(bindings.measure) {
+        return;
+    }
+    tool.active = !tool.active;
+    tool.points.clear();
+    for entity in text.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if tool.active {
+        commands.spawn((
+            TextBundle::from_section("", TextStyle::default()).with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.0),
+                left: Val::Percent(40.0),
+                ..default()
+            }),
+            MeasureText {},
+        ));
+    }
+}
+
+pub fn measure_clicks(
+    mouse: Res<ButtonInput<MouseButton>>,
+    context: Res<RapierContext>,
+    mut tool: ResMut<MeasureTool>,
+    camera: Query<&Transform, With<Camera3d>>,
+    player: Query<Entity, With<Player>>,
+    mountain: Query<Entity, With<Mountain>>,
+) {
+    if !tool.active || !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let (Ok(camera), Ok(player), Ok(mountain)) = (
+        camera.get_single(),
+        player.get_single(),
+        mountain.get_single(),
+    ) else {
+        return;
+    };
+    let Some(hit) = terrain_under_crosshair(&context, camera, player, mountain, MEASURE_REACH)
+    else {
+        return;
+    };
+    if tool.points.len() >= 2 {
+        tool.points.clear();
+    }
+    tool.points.push(hit);
+}
+
+pub fn draw_measure(
+    mut gizmos: Gizmos,
+    tool: Res<MeasureTool>,
+    heights: Res<TerrainHeights>,
+    mut text: Query<&mut Text, With<MeasureText>>,
+) {
+    if !tool.active {
+        return;
+    }
+    for point in &tool.points {
+        gizmos.sphere(*point, Quat::IDENTITY, 0.3, SURFACE_COLOR);
+    }
+    let message = match tool.points[..] {
+        [a, b] => {
+            // half the vertex spacing follows every bump the mesh has
+            let path = surface_path(&heights, a, b, heights.spacing().x / 2.0);
+            gizmos.line(a, b, STRAIGHT_COLOR);
+            gizmos.linestrip(
+                path.iter().map(|point| *point + Vec3::Y * MEASURE_LIFT),
+                SURFACE_COLOR,
+            );
+            format!(
+                "Straight: {:.1} m\nOver the ground: {:.1} m\nHeight difference: {:.1} m",
+                a.distance(b),
+                path_length(&path),
+                b.y - a.y
+            )
+        }
+        [_] => "Click the second point".to_string(),
+        _ => "Measuring: click the first point".to_string(),
+    };
+    for mut text in text.iter_mut() {
+        if text.sections[0].value != message {
+            text.sections[0].value = message.clone();
+        }
+    }
+}
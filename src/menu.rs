@@ -0,0 +1,439 @@
+//! The options menu. It's shown once loading finishes and whenever the pause key
+//! is pressed while playing; the game is frozen and the cursor released while
+//! it's open.
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_rapier3d::prelude::RapierConfiguration;
+
+use crate::controls::{key_name, Action, KeyBindings};
+use crate::movement::MovementFeel;
+use crate::post_process::{self, TONEMAPPERS};
+use crate::settings::{self, Settings};
+use crate::window::grab_cursor;
+use crate::GameState;
+
+#[derive(Component)]
+pub struct Menu {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    Sensitivity,
+    Fov,
+    Msaa,
+    MaxFps,
+    Bloom,
+    Tonemapping,
+    ReduceMotion,
+    CameraRoll,
+    MovementFeel,
+    MasterVolume,
+    SfxVolume,
+    AmbientVolume,
+    MusicVolume,
+    Reverb,
+}
+
+impl Setting {
+    const ALL: [Setting; 14] = [
+        Setting::Sensitivity,
+        Setting::Fov,
+        Setting::Msaa,
+        Setting::MaxFps,
+        Setting::Bloom,
+        Setting::Tonemapping,
+        Setting::ReduceMotion,
+        Setting::CameraRoll,
+        Setting::MovementFeel,
+        Setting::MasterVolume,
+        Setting::SfxVolume,
+        Setting::AmbientVolume,
+        Setting::MusicVolume,
+        Setting::Reverb,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Setting::Sensitivity => "Mouse sensitivity",
+            Setting::Fov => "Field of view",
+            Setting::Msaa => "Anti-aliasing",
+            Setting::MaxFps => "Frame rate cap (no vsync)",
+            Setting::Bloom => "Bloom",
+            Setting::Tonemapping => "Tonemapping",
+            Setting::ReduceMotion => "Reduce motion",
+            Setting::CameraRoll => "Camera roll",
+            Setting::MovementFeel => "Movement",
+            Setting::MasterVolume => "Master volume",
+            Setting::SfxVolume => "Effects volume",
+            Setting::AmbientVolume => "Ambient volume",
+            Setting::MusicVolume => "Music volume",
+            Setting::Reverb => "Valley echo",
+        }
+    }
+
+    fn value(self, settings: &Settings) -> String {
+        match self {
+            Setting::Sensitivity => format!("{:.1}", settings.mouse_sensitivity),
+            Setting::Fov => format!("{:.0}", settings.fov),
+            Setting::Msaa if settings.msaa_samples <= 1 => "Off".to_string(),
+            Setting::Msaa => format!("{}x", settings.msaa_samples),
+            Setting::MaxFps if settings.max_fps == 0 => "Off".to_string(),
+            Setting::MaxFps => settings.max_fps.to_string(),
+            Setting::Bloom if settings.post_process.bloom_intensity <= 0.0 => "Off".to_string(),
+            Setting::Bloom => format!("{:.2}", settings.post_process.bloom_intensity),
+            Setting::Tonemapping => {
+                post_process::tonemapping_name(settings.post_process.tonemapping).to_string()
+            }
+            Setting::ReduceMotion if settings.reduce_motion => "On".to_string(),
+            Setting::ReduceMotion => "Off".to_string(),
+            // reduce motion turns it off whatever this says
+            Setting::CameraRoll if !settings.roll_allowed() => "Off".to_string(),
+            Setting::CameraRoll => "On".to_string(),
+            Setting::MovementFeel => settings.movement_feel.name().to_string(),
+            Setting::MasterVolume => format!("{:.0}%", settings.audio.master * 100.0),
+            Setting::SfxVolume => format!("{:.0}%", settings.audio.sfx * 100.0),
+            Setting::AmbientVolume => format!("{:.0}%", settings.audio.ambient * 100.0),
+            Setting::MusicVolume => format!("{:.0}%", settings.audio.music * 100.0),
+            Setting::Reverb if settings.audio.reverb => "On".to_string(),
+            Setting::Reverb => "Off".to_string(),
+        }
+    }
+
+    // Moves the setting one step up (1) or down (-1).
+    fn step(self, settings: &mut Settings, direction: f32) {
+        let step = |value: f32, (min, max, step): (f32, f32, f32)| {
+            // round so repeated steps don't drift away from the grid
+            (((value + step * direction) / step).round() * step).clamp(min, max)
+        };
+        match self {
+            Setting::Sensitivity => {
+                settings.mouse_sensitivity =
+                    step(settings.mouse_sensitivity, settings::SENSITIVITY_RANGE)
+            }
+            Setting::Fov => settings.fov = step(settings.fov, settings::FOV_RANGE),
+            Setting::Bloom => {
+                settings.post_process.bloom_intensity = step(
+                    settings.post_process.bloom_intensity,
+                    post_process::BLOOM_RANGE,
+                )
+            }
+            Setting::Tonemapping => {
+                let current = TONEMAPPERS
+                    .iter()
+                    .position(|(t, _)| *t == settings.post_process.tonemapping)
+                    .unwrap_or(0) as i32;
+                let next = (current + direction as i32).clamp(0, TONEMAPPERS.len() as i32 - 1);
+                settings.post_process.tonemapping = TONEMAPPERS[next as usize].0;
+            }
+            Setting::MasterVolume => {
+                settings.audio.master = step(settings.audio.master, settings::VOLUME_RANGE)
+            }
+            Setting::SfxVolume => {
+                settings.audio.sfx = step(settings.audio.sfx, settings::VOLUME_RANGE)
+            }
+            Setting::AmbientVolume => {
+                settings.audio.ambient = step(settings.audio.ambient, settings::VOLUME_RANGE)
+            }
+            Setting::MusicVolume => {
+                settings.audio.music = step(settings.audio.music, settings::VOLUME_RANGE)
+            }
+            // up turns it on, down turns it off
+            Setting::ReduceMotion => settings.reduce_motion = direction > 0.0,
+            Setting::CameraRoll => settings.camera_roll = direction > 0.0,
+            Setting::Reverb => settings.audio.reverb = direction > 0.0,
+            Setting::MovementFeel => {
+                let feels = &MovementFeel::ALL;
+                let current = feels
+                    .iter()
+                    .position(|&f| f == settings.movement_feel)
+                    .unwrap_or(0) as i32;
+                let next = (current + direction as i32).clamp(0, feels.len() as i32 - 1);
+                settings.movement_feel = feels[next as usize];
+            }
+            Setting::MaxFps => {
+                let options = &settings::MAX_FPS_OPTIONS;
+                let current = options
+                    .iter()
+                    .position(|&fps| fps == settings.max_fps)
+                    .unwrap_or(0) as i32;
+                let next = (current + direction as i32).clamp(0, options.len() as i32 - 1);
+                settings.max_fps = options[next as usize];
+            }
+            Setting::Msaa => {
+                let samples = &settings::MSAA_SAMPLES;
+                let current = samples
+                    .iter()
+                    .position(|&s| s == settings.msaa_samples)
+                    .unwrap_or(0) as i32;
+                let next = (current + direction as i32).clamp(0, samples.len() as i32 - 1);
+                settings.msaa_samples = samples[next as usize];
+            }
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy)]
+pub enum MenuButton {
+    Play,
+    Decrease(Setting),
+    Increase(Setting),
+    Rebind(Action),
+    ResetDefaults,
+}
+
+// Text showing the current value of a setting or key.
+#[derive(Component)]
+pub enum MenuValue {
+    Setting(Setting),
+    Binding(Action),
+}
+
+// The action waiting for a key press to rebind it, if any.
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<Action>);
+
+const BUTTON_COLOR: Color = Color::srgb(0.2, 0.2, 0.2);
+const BUTTON_HOVER_COLOR: Color = Color::srgb(0.35, 0.35, 0.35);
+
+fn button(parent: &mut ChildBuilder, kind: MenuButton, label: &str, width: f32) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(width),
+                    height: Val::Px(28.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            kind,
+        ))
+        .with_children(|parent| {
+            let mut text = parent.spawn(TextBundle::from_section(label, TextStyle::default()));
+            if let MenuButton::Rebind(action) = kind {
+                text.insert(MenuValue::Binding(action));
+            }
+        });
+}
+
+fn row(parent: &mut ChildBuilder, label: &str, contents: impl FnOnce(&mut ChildBuilder)) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(480.0),
+                justify_content: JustifyContent::SpaceBetween,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, TextStyle::default()));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(8.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(contents);
+        });
+}
+
+// The first time the menu opens is the start of the game, after that it's a pause menu.
+pub fn spawn_menu(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    bindings: Res<KeyBindings>,
+    mut started: Local<bool>,
+) {
+    let (title, play) = if *started {
+        ("Paused", "Resume")
+    } else {
+        ("Shader PLSE Game Jam", "Play")
+    };
+    *started = true;
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(8.0),
+                    ..default()
+                },
+                background_color: Color::srgba(0.0, 0.0, 0.0, 0.8).into(),
+                // draw above the controls text
+                z_index: ZIndex::Global(1),
+                ..default()
+            },
+            Menu {},
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 40.0,
+                    ..default()
+                },
+            ));
+            for setting in Setting::ALL {
+                row(parent, setting.name(), |parent| {
+                    button(parent, MenuButton::Decrease(setting), "-", 28.0);
+                    parent.spawn((
+                        TextBundle::from_section(setting.value(&settings), TextStyle::default())
+                            .with_style(Style {
+                                width: Val::Px(60.0),
+                                ..default()
+                            })
+                            .with_text_justify(JustifyText::Center),
+                        MenuValue::Setting(setting),
+                    ));
+                    button(parent, MenuButton::Increase(setting), "+", 28.0);
+                });
+            }
+            for action in Action::ALL {
+                row(parent, action.name(), |parent| {
+                    button(
+                        parent,
+                        MenuButton::Rebind(action),
+                        &key_name(bindings.key(action)),
+                        140.0,
+                    );
+                });
+            }
+            button(
+                parent,
+                MenuButton::ResetDefaults,
+                "Reset to defaults",
+                200.0,
+            );
+            button(parent, MenuButton::Play, play, 200.0);
+        });
+}
+
+pub fn despawn_menu(
+    mut commands: Commands,
+    mut rebinding: ResMut<Rebinding>,
+    menu: Query<Entity, With<Menu>>,
+) {
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    rebinding.0 = None;
+}
+
+#[allow(clippy::type_complexity)]
+pub fn menu_buttons(
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut buttons: Query<
+        (&Interaction, &MenuButton, &mut BackgroundColor),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        *color = match interaction {
+            Interaction::None => BUTTON_COLOR,
+            Interaction::Hovered | Interaction::Pressed => BUTTON_HOVER_COLOR,
+        }
+        .into();
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *button {
+            MenuButton::Play => next_state.set(GameState::Playing),
+            MenuButton::Decrease(setting) => setting.step(&mut settings, -1.0),
+            MenuButton::Increase(setting) => setting.step(&mut settings, 1.0),
+            MenuButton::Rebind(action) => rebinding.0 = Some(action),
+            MenuButton::ResetDefaults => *settings = Settings::default(),
+        }
+    }
+}
+
+// While an action is waiting to be rebound the next key press goes to it,
+// the pause key cancels. An action that already had the key swaps keys with it.
+// Otherwise the pause key closes the menu.
+pub fn menu_keys(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut settings: ResMut<Settings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(action) = rebinding.0 else {
+        if keys.just_pressed(bindings.pause) {
+            next_state.set(GameState::Playing);
+        }
+        return;
+    };
+    let Some(&key) = keys.get_just_pressed().next() else {
+        return;
+    };
+    if key != bindings.pause {
+        if let Some(other) = settings.key_bindings.rebind(action, key) {
+            info!(
+                "{} was on {}, it's on {} now",
+                other.name(),
+                key_name(key),
+                key_name(settings.key_bindings.key(other))
+            );
+        }
+    }
+    rebinding.0 = None;
+}
+
+pub fn update_menu_text(
+    settings: Res<Settings>,
+    rebinding: Res<Rebinding>,
+    mut text: Query<(&mut Text, &MenuValue)>,
+) {
+    if !settings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (mut text, value) in text.iter_mut() {
+        text.sections[0].value = match *value {
+            MenuValue::Setting(setting) => setting.value(&settings),
+            MenuValue::Binding(action) if rebinding.0 == Some(action) => "Press a key".to_string(),
+            MenuValue::Binding(action) => key_name(settings.key_bindings.key(action)),
+        };
+    }
+}
+
+pub fn open_menu(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.just_pressed(bindings.pause) {
+        next_state.set(GameState::Menu);
+    }
+}
+
+// Locks the cursor for mouse look and lets physics run.
+pub fn start_playing(
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    rapier_config.physics_pipeline_active = true;
+    grab_cursor(&mut window.single_mut(), true);
+}
+
+// Frees the cursor for the menu and freezes physics.
+pub fn stop_playing(
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    rapier_config.physics_pipeline_active = false;
+    grab_cursor(&mut window.single_mut(), false);
+}
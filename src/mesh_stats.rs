@@ -0,0 +1,150 @@
+//! Logging a summary of the terrain mesh, for bug reports about odd looking
+//! terrain, and a histogram of its slopes for picking the slope cutoffs between
+//! surfaces. Nothing here changes the mesh.
+
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+
+use crate::controls::KeyBindings;
+use crate::terrain::{TerrainConfig, TerrainSeed};
+use crate::Mountain;
+
+#[derive(Resource, Debug, Clone)]
+pub struct MeshStatsConfig {
+    // width of each slope histogram bin, in degrees
+    pub slope_bin_degrees: f32,
+    // also write the slope histogram here as CSV when set
+    pub slope_csv_path: Option<String>,
+}
+
+impl Default for MeshStatsConfig {
+    fn default() -> Self {
+        MeshStatsConfig {
+            slope_bin_degrees: 5.0,
+            slope_csv_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshStats {
+    pub vertices: usize,
+    pub triangles: usize,
+    // corners of the bounding box, in the mesh's local space
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+pub fn mesh_stats(mesh: &Mesh) -> Option<MeshStats> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
+    };
+    let (min, max) = positions.iter().map(|&p| Vec3::from(p)).fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), p| (min.min(p), max.max(p)),
+    );
+    let triangles = match mesh.indices() {
+        Some(indices) => indices.len() / 3,
+        None => positions.len() / 3,
+    };
+    Some(MeshStats {
+        vertices: positions.len(),
+        triangles,
+        min,
+        max,
+    })
+}
+
+// How many vertex normals fall in each slope bin, from flat up to vertical. A
+// normal's slope is its angle away from straight up.
+pub fn slope_histogram(mesh: &Mesh, bin_degrees: f32) -> Option<Vec<usize>> {
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return None;
+    };
+    let bins = (90.0 / bin_degrees).ceil().max(1.0) as usize;
+    let mut counts = vec![0; bins];
+    for &normal in normals {
+        let slope = Vec3::from(normal).angle_between(Vec3::Y).to_degrees();
+        // normals facing down count as vertical, and NaNs from zero normals land in bin 0
+        let bin = ((slope.min(90.0) / bin_degrees) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    Some(counts)
+}
+
+fn log_slope_histogram(counts: &[usize], bin_degrees: f32, csv_path: Option<&str>) {
+    let total = counts.iter().sum::<usize>().max(1);
+    let mut csv = String::from("min_degrees,max_degrees,vertices,fraction\n");
+    let mut cumulative = 0;
+    info!("terrain slopes, by vertex:");
+    for (i, &count) in counts.iter().enumerate() {
+        let (low, high) = (
+            i as f32 * bin_degrees,
+            ((i + 1) as f32 * bin_degrees).min(90.0),
+        );
+        let fraction = count as f32 / total as f32;
+        cumulative += count;
+        info!(
+            "{:>4.0} to {:>3.0} degrees: {:>7} {:>5.1}% ({:>5.1}% at most this steep) {}",
+            low,
+            high,
+            count,
+            fraction * 100.0,
+            cumulative as f32 / total as f32 * 100.0,
+            "#".repeat((fraction * 50.0).round() as usize),
+        );
+        csv += &format!("{},{},{},{}\n", low, high, count, fraction);
+    }
+    if let Some(path) = csv_path {
+        match std::fs::write(path, csv) {
+            Ok(()) => info!("wrote the slope histogram to {}", path),
+            Err(err) => warn!("couldn't write {}: {}", path, err),
+        }
+    }
+}
+
+pub fn log_mesh_stats(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    stats_config: Res<MeshStatsConfig>,
+    seed: Res<TerrainSeed>,
+    config: Res<TerrainConfig>,
+    meshes: Res<Assets<Mesh>>,
+    mountain: Query<(&Handle<Mesh>, &GlobalTransform), With<Mountain>>,
+) {
+    if !keys.just_pressed(bindings.dump_mesh_stats) {
+        return;
+    }
+    info!("terrain seed {} with {:?}", seed.0, config);
+    for (handle, transform) in mountain.iter() {
+        let Some(mesh) = meshes.get(handle) else {
+            info!("the terrain mesh isn't loaded");
+            continue;
+        };
+        let Some(stats) = mesh_stats(mesh) else {
+            info!("the terrain mesh has no positions");
+            continue;
+        };
+        let offset = transform.translation();
+        info!(
+            "terrain mesh: {} vertices, {} triangles, bounds {} to {}, world heights {:.2} to {:.2}",
+            stats.vertices,
+            stats.triangles,
+            stats.min + offset,
+            stats.max + offset,
+            stats.min.y + offset.y,
+            stats.max.y + offset.y,
+        );
+        match slope_histogram(mesh, stats_config.slope_bin_degrees) {
+            Some(counts) => log_slope_histogram(
+                &counts,
+                stats_config.slope_bin_degrees,
+                stats_config.slope_csv_path.as_deref(),
+            ),
+            None => info!("the terrain mesh has no normals"),
+        }
+    }
+}
@@ -0,0 +1,725 @@
+//! Player movement: input sets a desired velocity that the player speeds up or
+//! slows down towards, gravity (`PhysicsConfig::player_gravity`) pulls it down,
+//! and the result is clamped and
+//! handed to the `KinematicCharacterController`.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{
+    CharacterAutostep, CharacterLength, Collider, KinematicCharacterController,
+    KinematicCharacterControllerOutput, MoveShapeOptions, QueryFilter, RapierContext,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::physics::PhysicsConfig;
+use crate::terrain::TerrainHeights;
+use crate::Player;
+
+#[derive(Resource, Debug, Clone)]
+pub struct MovementConfig {
+    // world units per second
+    pub walk_speed: f32,
+    pub jump_speed: f32,
+    // seconds a jump pressed before landing is remembered for, and done on landing
+    pub jump_buffer: f32,
+    // seconds after leaving the ground that jumping still works
+    pub coyote_time: f32,
+    // cap on the xz part of the velocity
+    pub max_horizontal_speed: f32,
+    // cap on falling speed, keeps a long fall from moving further than the
+    // player's collider in a single frame and tunneling through the terrain
+    pub terminal_velocity: f32,
+    pub player_radius: f32,
+    pub player_shape: PlayerShape,
+    // the longest move handed to the character controller at once, as a fraction of
+    // the player's radius. Longer moves (fast movement, frame hitches) are split up.
+    pub substep_fraction: f32,
+    // gap the character controller keeps between the player and the ground, in
+    // world units. Too small and the player snags on the bumps of the heightfield
+    // and jitters against it, too large and they visibly float above it.
+    pub skin_width: f32,
+    // how quickly walking speeds up towards the keys' direction, world units per second squared
+    pub acceleration: f32,
+    // how quickly walking slows down with no keys held, world units per second squared
+    pub friction: f32,
+    // fraction of the acceleration and friction left while in the air
+    pub air_control: f32,
+    // tallest ledge the player walks up without jumping, in world units
+    pub max_step_height: f32,
+    // steepest ground the player can walk up, in degrees
+    pub max_climb_angle: f32,
+    // how far past the front of the player the ground is checked for walls, in
+    // world units. Ground that rises more than a step in this distance, and
+    // steeper than `max_climb_angle`, stops the player like a wall.
+    pub wall_lookahead: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        MovementConfig {
+            // a jog
+            walk_speed: 4.0,
+            jump_speed: 4.0,
+            jump_buffer: 0.1,
+            coyote_time: 0.1,
+            max_horizontal_speed: 8.0,
+            terminal_velocity: 50.0,
+            player_radius: 0.5,
+            // about as tall as a person
+            player_shape: PlayerShape::Capsule { height: 1.8 },
+            substep_fraction: 0.5,
+            // a couple of centimeters, well under what the eye notices at ground level
+            skin_width: 0.02,
+            // the snappy preset
+            acceleration: 200.0,
+            friction: 200.0,
+            air_control: 1.0,
+            // a tall stair
+            max_step_height: 0.35,
+            max_climb_angle: 45.0,
+            wall_lookahead: 0.5,
+        }
+    }
+}
+
+// The player's collider. Both are `player_radius` wide, and the player's
+// translation is the middle of the shape.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PlayerShape {
+    // rolls and perches on bumps, but never snags on anything
+    Ball,
+    // stands upright on slopes like a person would. `height` is from the bottom
+    // of the capsule to the top, and is at least twice the radius.
+    Capsule { height: f32 },
+}
+
+// how far up the player the camera sits, as a fraction of their height
+const EYE_HEIGHT: f32 = 0.9;
+
+// Presets for how movement responds to the keys, picked in the options menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MovementFeel {
+    // up to speed and stopped again almost instantly, full control in the air
+    Snappy,
+    // gets going and slows down gradually, little steering in the air
+    Floaty,
+}
+
+impl MovementFeel {
+    pub const ALL: [MovementFeel; 2] = [MovementFeel::Snappy, MovementFeel::Floaty];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MovementFeel::Snappy => "Snappy",
+            MovementFeel::Floaty => "Floaty",
+        }
+    }
+}
+
+impl MovementConfig {
+    pub fn player_collider(&self) -> Collider {
+        match self.player_shape {
+            PlayerShape::Ball => Collider::ball(self.player_radius),
+            PlayerShape::Capsule { .. } => Collider::capsule_y(
+                self.player_half_height() - self.player_radius,
+                self.player_radius,
+            ),
+        }
+    }
+
+    // distance from the player's translation down to their feet
+    pub fn player_half_height(&self) -> f32 {
+        match self.player_shape {
+            PlayerShape::Ball => self.player_radius,
+            PlayerShape::Capsule { height } => (height / 2.0).max(self.player_radius),
+        }
+    }
+
+    // where the camera sits, relative to the player's translation. A ball has no
+    // head so the camera stays in its middle.
+    pub fn eye_offset(&self) -> Vec3 {
+        match self.player_shape {
+            PlayerShape::Ball => Vec3::ZERO,
+            PlayerShape::Capsule { .. } => {
+                let half = self.player_half_height();
+                Vec3::Y * (2.0 * half * EYE_HEIGHT - half)
+            }
+        }
+    }
+
+    pub fn with_feel(mut self, feel: MovementFeel) -> MovementConfig {
+        (self.acceleration, self.friction, self.air_control) = match feel {
+            // full walking speed in a fiftieth of a second
+            MovementFeel::Snappy => (200.0, 200.0, 1.0),
+            MovementFeel::Floaty => (12.0, 6.0, 0.3),
+        };
+        self
+    }
+}
+
+// Moves the horizontal velocity `current` towards `target` by one frame of
+// acceleration, or friction when there's nowhere to go.
+pub fn accelerate(
+    current: Vec2,
+    target: Vec2,
+    grounded: bool,
+    dt: f32,
+    config: &MovementConfig,
+) -> Vec2 {
+    let mut rate = if target == Vec2::ZERO {
+        config.friction
+    } else {
+        config.acceleration
+    };
+    if !grounded {
+        rate *= config.air_control;
+    }
+    let difference = target - current;
+    current + difference.clamp_length_max(rate * dt)
+}
+
+// Scales walking speed by how steep the ground is in the direction of travel:
+// slower going uphill, a little faster going down.
+#[derive(Resource, Debug, Clone)]
+pub struct SlopeSpeedConfig {
+    pub enabled: bool,
+    // speed is divided by (1 + uphill_penalty * slope), slope being rise over run
+    pub uphill_penalty: f32,
+    // speed is multiplied by (1 + downhill_bonus * slope) going down
+    pub downhill_bonus: f32,
+    // slowest crawl up a steep slope, as a fraction of walk speed
+    pub min_factor: f32,
+    // fastest downhill speed, as a fraction of walk speed
+    pub max_factor: f32,
+}
+
+impl Default for SlopeSpeedConfig {
+    fn default() -> Self {
+        SlopeSpeedConfig {
+            enabled: true,
+            uphill_penalty: 2.0,
+            downhill_bonus: 0.3,
+            min_factor: 0.1,
+            max_factor: 1.3,
+        }
+    }
+}
+
+// distance either side of the player used to measure the slope
+const SLOPE_SAMPLE_DISTANCE: f32 = 0.5;
+
+// How much to scale walking speed at `position` when heading in `direction` (xz).
+pub fn slope_speed_factor(
+    config: &SlopeSpeedConfig,
+    heights: &TerrainHeights,
+    position: Vec2,
+    direction: Vec2,
+) -> f32 {
+    let Some(direction) = direction.try_normalize() else {
+        return 1.0;
+    };
+    if !config.enabled {
+        return 1.0;
+    }
+    let ahead = position + direction * SLOPE_SAMPLE_DISTANCE;
+    let behind = position - direction * SLOPE_SAMPLE_DISTANCE;
+    let (Some(ahead), Some(behind)) = (
+        heights.height_at(ahead.x, ahead.y),
+        heights.height_at(behind.x, behind.y),
+    ) else {
+        return 1.0;
+    };
+
+    let slope = (ahead - behind) / (2.0 * SLOPE_SAMPLE_DISTANCE);
+    let factor = if slope > 0.0 {
+        1.0 / (1.0 + config.uphill_penalty * slope)
+    } else {
+        1.0 + config.downhill_bonus * -slope
+    };
+    factor.clamp(config.min_factor, config.max_factor)
+}
+
+// Direction to walk in on the ground for the movement keys held, `input` being
+// (right, forward) with each between -1 and 1. Only the camera's heading matters,
+// not its pitch or roll, and the result is unit length so holding two keys isn't
+// faster than one. Zero when nothing, or two opposite keys, are held.
+pub fn walk_direction(camera: &Transform, input: Vec2) -> Vec3 {
+    // the right vector stays level however far up or down the camera looks
+    let right = camera.right().with_y(0.0).normalize_or_zero();
+    let forward = Vec3::Y.cross(right);
+    (right * input.x + forward * input.y).normalize_or_zero()
+}
+
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct PlayerVelocity(pub Vec3);
+
+// Remembers how long ago jump was pressed and the player was on the ground, so a
+// jump pressed just before landing or just after running off an edge still
+// happens.
+#[derive(Debug, Clone, Copy)]
+pub struct JumpTimer {
+    since_pressed: f32,
+    since_grounded: f32,
+}
+
+impl Default for JumpTimer {
+    fn default() -> Self {
+        JumpTimer {
+            since_pressed: f32::INFINITY,
+            since_grounded: f32::INFINITY,
+        }
+    }
+}
+
+impl JumpTimer {
+    // Whether to jump this frame. `just_pressed` is only true on the frame the
+    // jump key goes down, so holding it doesn't jump again on landing.
+    pub fn update(
+        &mut self,
+        just_pressed: bool,
+        grounded: bool,
+        dt: f32,
+        config: &MovementConfig,
+    ) -> bool {
+        self.since_pressed = if just_pressed {
+            0.0
+        } else {
+            self.since_pressed + dt
+        };
+        self.since_grounded = if grounded {
+            0.0
+        } else {
+            self.since_grounded + dt
+        };
+        let jump =
+            self.since_pressed <= config.jump_buffer && self.since_grounded <= config.coyote_time;
+        if jump {
+            // one press is one jump, and no jumping again off the same ground
+            *self = JumpTimer::default();
+        }
+        jump
+    }
+}
+
+// Walks the player forward on their own while on. Any movement key turns it
+// off, and so does walking into something.
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct AutoWalk(pub bool);
+
+// fraction of the last frame's horizontal move that has to get through for
+// auto-walk to keep going
+const AUTO_WALK_MIN_PROGRESS: f32 = 0.25;
+
+// Whether the last move ran into something, most of it got stopped by the
+// character controller. Walking up slopes still gets through.
+pub fn blocked(output: &KinematicCharacterControllerOutput) -> bool {
+    let desired = output.desired_translation.xz().length();
+    let effective = output.effective_translation.xz().length();
+    desired > f32::EPSILON && effective < desired * AUTO_WALK_MIN_PROGRESS
+}
+
+// Limits the horizontal speed and the falling speed, leaving the direction alone.
+pub fn clamp_velocity(velocity: Vec3, config: &MovementConfig) -> Vec3 {
+    let horizontal =
+        Vec2::new(velocity.x, velocity.z).clamp_length_max(config.max_horizontal_speed);
+    Vec3::new(
+        horizontal.x,
+        velocity.y.max(-config.terminal_velocity),
+        horizontal.y,
+    )
+}
+
+// upper bound so a huge hitch can't stall the frame with controller moves
+const MAX_SUBSTEPS: usize = 32;
+
+// How many controller moves a displacement of length `distance` is split into so
+// that none of them is longer than `max_step`.
+pub fn substep_count(distance: f32, max_step: f32) -> usize {
+    ((distance / max_step).ceil() as usize).clamp(1, MAX_SUBSTEPS)
+}
+
+// Advances the velocity by one frame of `gravity` and clamps it.
+pub fn step_velocity(
+    mut velocity: Vec3,
+    grounded: bool,
+    dt: f32,
+    gravity: f32,
+    config: &MovementConfig,
+) -> Vec3 {
+    // standing on the ground shouldn't build up falling speed
+    if grounded && velocity.y < 0.0 {
+        velocity.y = 0.0;
+    }
+    velocity.y -= gravity * dt;
+    clamp_velocity(velocity, config)
+}
+
+// Takes away the part of the velocity heading into a wall. Rapier's autostep
+// lifts the player over anything up to a step high, which done every frame would
+// carry them up a cliff one step at a time, so walls are found in the heights
+// instead: ground just past the front of the player that rises more than a step
+// within `wall_lookahead`, at steeper than `max_climb_angle`.
+pub fn stop_at_walls(
+    velocity: Vec3,
+    position: Vec3,
+    heights: &TerrainHeights,
+    config: &MovementConfig,
+) -> Vec3 {
+    let Some(direction) = velocity.xz().try_normalize() else {
+        return velocity;
+    };
+    let front = position.xz() + direction * config.player_radius;
+    let beyond = front + direction * config.wall_lookahead;
+    let (Some(front), Some(beyond)) = (
+        heights.height_at(front.x, front.y),
+        heights.height_at(beyond.x, beyond.y),
+    ) else {
+        return velocity;
+    };
+    let rise = beyond - front;
+    let too_steep = rise > config.wall_lookahead * config.max_climb_angle.to_radians().tan();
+    if rise <= config.max_step_height || !too_steep {
+        return velocity;
+    }
+    // sliding along the wall is fine, only heading into it stops
+    let into_wall = velocity.xz().dot(direction).max(0.0);
+    let remaining = velocity.xz() - direction * into_wall;
+    Vec3::new(remaining.x, velocity.y, remaining.y)
+}
+
+// The controller's move options, with the step height, slope limit and skin
+// width from the config rather than the controller, so they can be tuned live.
+pub fn controller_options(
+    controller: &KinematicCharacterController,
+    config: &MovementConfig,
+) -> MoveShapeOptions {
+    MoveShapeOptions {
+        up: controller.up,
+        offset: CharacterLength::Absolute(config.skin_width),
+        slide: controller.slide,
+        autostep: Some(CharacterAutostep {
+            max_height: CharacterLength::Absolute(config.max_step_height),
+            // the top of a step has to fit half the player
+            min_width: CharacterLength::Absolute(config.player_radius),
+            include_dynamic_bodies: false,
+        }),
+        max_slope_climb_angle: config.max_climb_angle.to_radians(),
+        min_slope_slide_angle: controller.min_slope_slide_angle,
+        apply_impulse_to_dynamic_bodies: controller.apply_impulse_to_dynamic_bodies,
+        snap_to_ground: controller.snap_to_ground,
+        normal_nudge_factor: controller.normal_nudge_factor,
+    }
+}
+
+// Moves a character shape by `desired` with the controller, split into steps no
+// longer than a fraction of the player's radius, and returns what happened.
+#[allow(clippy::too_many_arguments)]
+pub fn move_character(
+    context: &mut RapierContext,
+    collider: &Collider,
+    transform: &mut Transform,
+    desired: Vec3,
+    mass: f32,
+    options: &MoveShapeOptions,
+    filter: QueryFilter,
+    config: &MovementConfig,
+) -> KinematicCharacterControllerOutput {
+    let substeps = substep_count(
+        desired.length(),
+        config.player_radius * config.substep_fraction,
+    );
+    let step = desired / substeps as f32;
+
+    let mut result = KinematicCharacterControllerOutput {
+        desired_translation: desired,
+        ..default()
+    };
+    for _ in 0..substeps {
+        let moved = context.move_shape(
+            step,
+            collider,
+            transform.translation,
+            transform.rotation,
+            mass,
+            options,
+            filter,
+            |collision| result.collisions.push(collision),
+        );
+        transform.translation += moved.effective_translation;
+        result.effective_translation += moved.effective_translation;
+        result.grounded = moved.grounded;
+        result.is_sliding_down_slope = moved.is_sliding_down_slope;
+    }
+    result
+}
+
+#[allow(clippy::type_complexity)]
+pub fn apply_velocity(
+    time: Res<Time>,
+    config: Res<MovementConfig>,
+    physics: Res<PhysicsConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    mut context: ResMut<RapierContext>,
+    mut player: Query<
+        (
+            Entity,
+            &mut PlayerVelocity,
+            &mut Transform,
+            &Collider,
+            &KinematicCharacterController,
+            &mut KinematicCharacterControllerOutput,
+        ),
+        With<Player>,
+    >,
+) {
+    let (entity, mut velocity, mut transform, collider, controller, mut output) =
+        player.single_mut();
+    let dt = time.delta_seconds();
+
+    velocity.0 = step_velocity(
+        velocity.0,
+        output.grounded,
+        dt,
+        physics.player_gravity,
+        &config,
+    );
+    if let Some(heights) = &heights {
+        velocity.0 = stop_at_walls(velocity.0, transform.translation, heights, &config);
+    }
+
+    // We run the controller ourselves instead of setting `controller.translation`,
+    // since rapier only does a single move per physics step.
+    let options = controller_options(controller, &config);
+    let filter = QueryFilter {
+        flags: controller.filter_flags,
+        groups: controller.filter_groups,
+        ..default()
+    }
+    .exclude_collider(entity);
+
+    *output = move_character(
+        &mut context,
+        collider,
+        &mut transform,
+        velocity.0 * dt,
+        controller.custom_mass.unwrap_or(0.0),
+        &options,
+        filter,
+        &config,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_fall_is_clamped_to_terminal_velocity() {
+        let config = MovementConfig::default();
+        let gravity = PhysicsConfig::default().player_gravity;
+        let mut velocity = Vec3::ZERO;
+        // a minute of free fall at 60fps
+        for _ in 0..3600 {
+            velocity = step_velocity(velocity, false, 1.0 / 60.0, gravity, &config);
+        }
+        assert_eq!(velocity.y, -config.terminal_velocity);
+    }
+
+    #[test]
+    fn horizontal_speed_is_capped() {
+        let config = MovementConfig::default();
+        let velocity = clamp_velocity(Vec3::new(30.0, 0.0, 40.0), &config);
+        let horizontal = Vec2::new(velocity.x, velocity.z);
+        assert!((horizontal.length() - config.max_horizontal_speed).abs() < 1e-5);
+        // direction is preserved
+        assert!((velocity.x / velocity.z - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diagonal_walking_is_not_faster() {
+        let camera =
+            Transform::from_xyz(0.0, 1.0, 0.0).looking_to(Vec3::new(1.0, -0.5, 2.0), Vec3::Y);
+        let straight = walk_direction(&camera, Vec2::new(0.0, 1.0));
+        let diagonal = walk_direction(&camera, Vec2::new(1.0, 1.0));
+        assert!((straight.length() - 1.0).abs() < 1e-5);
+        assert!((diagonal.length() - straight.length()).abs() < 1e-5);
+        // level even though the camera looks down
+        assert_eq!(straight.y, 0.0);
+        assert_eq!(diagonal.y, 0.0);
+        // no keys held means standing still, not NaN
+        assert_eq!(walk_direction(&camera, Vec2::ZERO), Vec3::ZERO);
+    }
+
+    #[test]
+    fn jumps_are_buffered_and_allowed_just_after_leaving_the_ground() {
+        let config = MovementConfig::default();
+        let dt = 1.0 / 60.0;
+        // pressed a little before landing, the jump happens on landing
+        let mut timer = JumpTimer::default();
+        assert!(!timer.update(true, false, dt, &config));
+        assert!(!timer.update(false, false, config.jump_buffer / 2.0, &config));
+        assert!(timer.update(false, true, dt, &config));
+        // but not if it was pressed too long before
+        let mut timer = JumpTimer::default();
+        assert!(!timer.update(true, false, dt, &config));
+        assert!(!timer.update(false, false, config.jump_buffer * 2.0, &config));
+        assert!(!timer.update(false, true, dt, &config));
+        // pressed a little after walking off an edge still jumps
+        let mut timer = JumpTimer::default();
+        assert!(!timer.update(false, true, dt, &config));
+        assert!(timer.update(true, false, config.coyote_time / 2.0, &config));
+        // and no more than once, without landing in between
+        assert!(!timer.update(true, false, dt, &config));
+        // too long after leaving the ground is too late
+        let mut timer = JumpTimer::default();
+        assert!(!timer.update(false, true, dt, &config));
+        assert!(!timer.update(true, false, config.coyote_time * 2.0, &config));
+    }
+
+    #[test]
+    fn holding_jump_does_not_jump_again_on_landing() {
+        let config = MovementConfig::default();
+        let dt = 1.0 / 60.0;
+        let mut timer = JumpTimer::default();
+        assert!(timer.update(true, true, dt, &config));
+        // the key stays down through the jump and the landing, which only
+        // `just_pressed` on the first frame reports
+        for _ in 0..30 {
+            assert!(!timer.update(false, false, dt, &config));
+        }
+        for _ in 0..30 {
+            assert!(!timer.update(false, true, dt, &config));
+        }
+    }
+
+    #[test]
+    fn large_move_is_split_into_substeps() {
+        let config = MovementConfig::default();
+        let max_step = config.player_radius * config.substep_fraction;
+        // a 2 unit jump in one frame with 0.25 unit steps
+        assert_eq!(substep_count(2.0, max_step), 8);
+        // small moves still get one step, and zero moves don't divide by zero
+        assert_eq!(substep_count(0.1, max_step), 1);
+        assert_eq!(substep_count(0.0, max_step), 1);
+    }
+
+    // Walks the player along +x for two seconds over ground shaped by `height`,
+    // through the same steps as `apply_velocity`, and returns where they end up.
+    fn walk_over(config: &MovementConfig, height: impl Fn(f32) -> f32) -> Vec3 {
+        use crate::terrain::{self, TerrainConfig};
+        use bevy_rapier3d::rapier::geometry::ColliderBuilder;
+
+        let gravity = PhysicsConfig::default().player_gravity;
+        let (x_max, y_max) = (81, 9);
+        let extent = Vec2::new(20.0, 2.0);
+        let spacing = extent.x / (x_max - 1) as f32;
+        let heights = TerrainHeights {
+            x_max,
+            y_max,
+            extent,
+            heights: (0..x_max)
+                .flat_map(|xi| {
+                    let x = xi as f32 * spacing - extent.x / 2.0;
+                    std::iter::repeat_n(height(x), y_max)
+                })
+                .collect(),
+        };
+        let terrain = terrain::terrain_collider(
+            &TerrainConfig::default(),
+            &heights,
+            &terrain::terrain_mesh(&TerrainConfig::default(), &heights),
+        );
+        // the collider has the ground where it's meant to be, across the step or
+        // the wall and either side of them, checked just past each vertex as rays
+        // right down a cell's edge can slip through
+        for xi in 28..=52 {
+            let x = (xi as f32 + 1e-3) * spacing - extent.x / 2.0;
+            let top = 100.0;
+            let hit = terrain
+                .cast_local_ray(Vec3::new(x, top, 0.3), Vec3::NEG_Y, 2.0 * top, true)
+                .unwrap_or_else(|| panic!("no ground under {x}"));
+            let ground = top - hit;
+            assert!(
+                (ground - height(x)).abs() < 1e-3,
+                "the collider has the ground at {ground} instead of {} at {x}",
+                height(x)
+            );
+        }
+        let mut context = RapierContext::default();
+        context.colliders.insert(
+            ColliderBuilder::new(terrain.raw.clone())
+                .translation([0.0, crate::TERRAIN_BASE_HEIGHT, 0.0].into())
+                // collisions are reported with the entity the collider is on
+                .user_data(Entity::from_raw(1).to_bits() as u128)
+                .build(),
+        );
+        context.query_pipeline.update(&context.colliders);
+
+        let collider = config.player_collider();
+        let options = controller_options(&KinematicCharacterController::default(), config);
+        let start = heights.height_at(-3.0, 0.0).unwrap() + config.player_half_height();
+        let mut transform = Transform::from_xyz(-3.0, start + config.skin_width, 0.0);
+        let mut velocity = Vec3::ZERO;
+        let mut grounded = false;
+        let dt = 1.0 / 60.0;
+        for _ in 0..120 {
+            velocity.x = config.walk_speed;
+            velocity = step_velocity(velocity, grounded, dt, gravity, config);
+            velocity = stop_at_walls(velocity, transform.translation, &heights, config);
+            let output = move_character(
+                &mut context,
+                &collider,
+                &mut transform,
+                velocity * dt,
+                0.0,
+                &options,
+                QueryFilter::default(),
+                config,
+            );
+            grounded = output.grounded;
+        }
+        transform.translation
+    }
+
+    // Walks over a step and into a wall, checking the player gets up the one and
+    // not the other.
+    fn climb_step_but_not_wall(config: &MovementConfig) {
+        // a ledge a little lower than the tallest step, its face steeper than
+        // anything walkable
+        let step = config.max_step_height * 0.8;
+        let on_step = walk_over(config, |x| if x < 0.0 { 0.0 } else { step });
+        assert!(on_step.x > 2.0, "stopped at the step, at {}", on_step);
+        // a cliff, no steeper than the step's face but much taller
+        let at_wall = walk_over(config, |x| (x * 2.0).clamp(0.0, 4.0));
+        assert!(at_wall.x < 0.5, "climbed the wall to {}", at_wall);
+        assert!(
+            at_wall.y < crate::TERRAIN_BASE_HEIGHT + 1.0 + config.player_half_height(),
+            "climbed the wall to {}",
+            at_wall
+        );
+    }
+
+    #[test]
+    fn steps_are_climbed_and_walls_are_not() {
+        climb_step_but_not_wall(&MovementConfig::default());
+    }
+
+    #[test]
+    fn ball_climbs_steps_and_not_walls() {
+        let config = MovementConfig {
+            player_shape: PlayerShape::Ball,
+            ..default()
+        };
+        climb_step_but_not_wall(&config);
+        // it rolls along the flat on its bottom, not sunk into the ground
+        let flat = walk_over(&config, |_| 0.0);
+        assert!(flat.x > 2.0, "the ball didn't get anywhere, at {}", flat);
+        let resting = crate::TERRAIN_BASE_HEIGHT + config.player_radius;
+        assert!(
+            (flat.y - resting).abs() < 0.1,
+            "the ball is at {} instead of {}",
+            flat.y,
+            resting
+        );
+    }
+}
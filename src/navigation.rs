@@ -0,0 +1,146 @@
+//! Where on the terrain things can walk. The terrain is split into one cell per
+//! quad of the height grid, and a cell is walkable if it isn't too steep and is
+//! above the water. It's built from `TerrainHeights`, so the same seed always
+//! gives the same grid, and it's patched up wherever the terrain is edited.
+
+use bevy::prelude::*;
+
+use crate::scatter::RescatterRegion;
+use crate::terrain::TerrainHeights;
+use crate::TERRAIN_BASE_HEIGHT;
+
+#[derive(Resource, Debug, Clone)]
+pub struct NavigationConfig {
+    // steepest walkable slope, in degrees from flat
+    pub max_slope: f32,
+    // world space height of the water surface, cells with any corner below it aren't
+    // walkable. None while there's no water.
+    pub water_level: Option<f32>,
+}
+
+impl Default for NavigationConfig {
+    fn default() -> Self {
+        NavigationConfig {
+            max_slope: 35.0,
+            water_level: None,
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct WalkabilityGrid {
+    // cells along x and z, one less than the vertices in each direction
+    pub x_cells: usize,
+    pub z_cells: usize,
+    // world units the cells span along x and z, the terrain's extent
+    pub extent: Vec2,
+    walkable: Vec<bool>,
+}
+
+impl WalkabilityGrid {
+    pub fn new(config: &NavigationConfig, heights: &TerrainHeights) -> WalkabilityGrid {
+        let mut grid = WalkabilityGrid {
+            x_cells: heights.x_max - 1,
+            z_cells: heights.y_max - 1,
+            extent: heights.extent,
+            walkable: vec![false; (heights.x_max - 1) * (heights.y_max - 1)],
+        };
+        for xi in 0..grid.x_cells {
+            for zi in 0..grid.z_cells {
+                grid.update_cell(config, heights, xi, zi);
+            }
+        }
+        grid
+    }
+
+    fn cell_size(&self) -> Vec2 {
+        // the cells span the whole terrain, see TerrainHeights::vertex_xz
+        self.extent / Vec2::new(self.x_cells as f32, self.z_cells as f32)
+    }
+
+    // the xz rectangle the cells cover
+    pub fn bounds(&self) -> Rect {
+        Rect::from_center_size(Vec2::ZERO, self.extent)
+    }
+
+    // The cell containing world (x, z), if it's over the terrain.
+    pub fn cell_at(&self, x: f32, z: f32) -> Option<(usize, usize)> {
+        let cell = ((Vec2::new(x, z) + self.extent / 2.0) / self.cell_size()).floor();
+        let in_bounds = (0.0..self.x_cells as f32).contains(&cell.x)
+            && (0.0..self.z_cells as f32).contains(&cell.y);
+        in_bounds.then_some((cell.x as usize, cell.y as usize))
+    }
+
+    pub fn is_walkable(&self, x: f32, z: f32) -> bool {
+        self.cell_at(x, z)
+            .is_some_and(|(xi, zi)| self.walkable[xi * self.z_cells + zi])
+    }
+
+    fn update_cell(
+        &mut self,
+        config: &NavigationConfig,
+        heights: &TerrainHeights,
+        xi: usize,
+        zi: usize,
+    ) {
+        let corner = |dx, dz| heights.heights[heights.index(xi + dx, zi + dz)];
+        let (h00, h10, h01, h11) = (corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1));
+        let size = self.cell_size();
+        // steepest rise along either axis of the quad
+        let slope = ((h10 - h00).abs().max((h11 - h01).abs()) / size.x)
+            .max((h01 - h00).abs().max((h11 - h10).abs()) / size.y);
+        let flat_enough = slope.atan().to_degrees() <= config.max_slope;
+        let lowest = h00.min(h10).min(h01).min(h11) + TERRAIN_BASE_HEIGHT;
+        let dry = config.water_level.is_none_or(|water| lowest > water);
+        self.walkable[xi * self.z_cells + zi] = flat_enough && dry;
+    }
+
+    // Recomputes the cells overlapping a world space xz rectangle.
+    pub fn update_region(
+        &mut self,
+        config: &NavigationConfig,
+        heights: &TerrainHeights,
+        region: Rect,
+    ) {
+        let size = self.cell_size();
+        let to_cell = |p: Vec2| ((p + self.extent / 2.0) / size).floor();
+        let min = to_cell(region.min).max(Vec2::ZERO);
+        let max = to_cell(region.max).min(Vec2::new(
+            self.x_cells as f32 - 1.0,
+            self.z_cells as f32 - 1.0,
+        ));
+        if min.x > max.x || min.y > max.y {
+            return;
+        }
+        for xi in min.x as usize..=max.x as usize {
+            for zi in min.y as usize..=max.y as usize {
+                self.update_cell(config, heights, xi, zi);
+            }
+        }
+    }
+}
+
+// `RescatterRegion` is sent when the terrain first appears and wherever it's
+// been edited, which is exactly when the grid needs rebuilding too.
+pub fn update_walkability(
+    mut commands: Commands,
+    config: Res<NavigationConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    grid: Option<ResMut<WalkabilityGrid>>,
+    mut changed: EventReader<RescatterRegion>,
+) {
+    let Some(heights) = heights else {
+        return;
+    };
+    match grid {
+        Some(mut grid) => {
+            for RescatterRegion(region) in changed.read() {
+                grid.update_region(&config, &heights, *region);
+            }
+        }
+        None => {
+            changed.clear();
+            commands.insert_resource(WalkabilityGrid::new(&config, &heights));
+        }
+    }
+}
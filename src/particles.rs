@@ -0,0 +1,194 @@
+//! Short lived particles: small unlit spheres that get thrown out, slow down
+//! in the air and shrink away. Footsteps and landings kick them up in the color
+//! of the ground. There's a cap on how many can be alive at once, and none are
+//! made at all with the reduce motion setting on.
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::footprints::Footstep;
+use crate::freeze::FreezeTime;
+use crate::player::PlayerLanded;
+use crate::settings::Settings;
+use crate::surface::{Surface, SurfaceConfig};
+use crate::terrain::TerrainHeights;
+
+#[derive(Resource, Debug, Clone)]
+pub struct ParticleConfig {
+    // bursts are cut short once this many particles are alive
+    pub max_particles: usize,
+    // landings slower than this, in units per second, don't kick anything up
+    pub landing_min_speed: f32,
+    // particles per unit per second of landing speed above the minimum
+    pub landing_particles_per_speed: f32,
+    pub max_landing_particles: u32,
+}
+
+impl Default for ParticleConfig {
+    fn default() -> Self {
+        ParticleConfig {
+            max_particles: 300,
+            landing_min_speed: 3.0,
+            landing_particles_per_speed: 2.0,
+            max_landing_particles: 40,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec3,
+    age: f32,
+    lifetime: f32,
+}
+
+const PARTICLE_RADIUS: f32 = 0.04;
+// fraction of its speed a particle loses each second to the air
+const DRAG: f32 = 3.0;
+
+#[derive(Resource)]
+pub struct ParticleAssets {
+    mesh: Handle<Mesh>,
+}
+
+impl FromWorld for ParticleAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Sphere::new(PARTICLE_RADIUS));
+        ParticleAssets { mesh }
+    }
+}
+
+// Throws up to `count` particles out of `position`, `strength` scales how fast
+// they go. Returns how many were actually spawned.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_burst(
+    commands: &mut Commands,
+    assets: &ParticleAssets,
+    materials: &mut Assets<StandardMaterial>,
+    room: usize,
+    color: Color,
+    count: u32,
+    strength: f32,
+    position: Vec3,
+) -> usize {
+    let count = (count as usize).min(room);
+    if count == 0 {
+        return 0;
+    }
+    // every particle of a burst shares one material, which goes away with them
+    let material = materials.add(StandardMaterial {
+        base_color: color,
+        unlit: true,
+        ..default()
+    });
+    let mut rng = rand::thread_rng();
+    for _ in 0..count {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let speed = rng.gen_range(0.3..0.8) * strength;
+        let velocity = Vec3::new(
+            angle.cos() * speed,
+            rng.gen_range(0.4..1.0) * strength.sqrt(),
+            angle.sin() * speed,
+        );
+        commands.spawn((
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            Particle {
+                velocity,
+                age: 0.0,
+                lifetime: rng.gen_range(0.4..0.8),
+            },
+        ));
+    }
+    count
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn emit_particles(
+    mut commands: Commands,
+    assets: Res<ParticleAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    config: Res<ParticleConfig>,
+    settings: Res<Settings>,
+    surfaces: Res<SurfaceConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    mut footsteps: EventReader<Footstep>,
+    mut landings: EventReader<PlayerLanded>,
+    particles: Query<(), With<Particle>>,
+) {
+    if settings.reduce_motion {
+        footsteps.clear();
+        landings.clear();
+        return;
+    }
+    let mut room = config.max_particles.saturating_sub(particles.iter().len());
+
+    for footstep in footsteps.read() {
+        let Some(surface) = footstep.surface else {
+            continue;
+        };
+        let effect = surfaces.effect(surface);
+        room -= spawn_burst(
+            &mut commands,
+            &assets,
+            &mut materials,
+            room,
+            effect.dust,
+            effect.footstep_particles,
+            1.0,
+            footstep.position,
+        );
+    }
+
+    for landing in landings.read() {
+        let over = landing.speed - config.landing_min_speed;
+        if over <= 0.0 {
+            continue;
+        }
+        let position = landing.position;
+        let surface = heights
+            .as_ref()
+            .and_then(|heights| surfaces.surface_at(heights, position.x, position.z))
+            .unwrap_or(Surface::Grass);
+        let count =
+            ((over * config.landing_particles_per_speed) as u32).min(config.max_landing_particles);
+        room -= spawn_burst(
+            &mut commands,
+            &assets,
+            &mut materials,
+            room,
+            surfaces.effect(surface).dust,
+            count,
+            landing.speed / config.landing_min_speed,
+            position,
+        );
+    }
+}
+
+pub fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    freeze: Res<FreezeTime>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform)>,
+) {
+    if freeze.0 {
+        return;
+    }
+    let dt = time.delta_seconds();
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        particle.age += dt;
+        if particle.age >= particle.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        particle.velocity *= (1.0 - DRAG * dt).max(0.0);
+        transform.translation += particle.velocity * dt;
+        transform.scale = Vec3::splat(1.0 - particle.age / particle.lifetime);
+    }
+}
@@ -0,0 +1,189 @@
+//! Paths over the terrain for camera moves, patrols and guided tours. In path
+//! mode left clicks on the terrain add waypoints and right clicks take the last
+//! one back off; a Catmull-Rom spline through them is drawn following the
+//! ground. The path is saved when leaving path mode and loaded back at startup.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::RapierContext;
+use serde::{Deserialize, Serialize};
+
+use crate::controls::KeyBindings;
+use crate::terrain::TerrainHeights;
+use crate::terrain_edit::terrain_under_crosshair;
+use crate::{Mountain, Player};
+
+const PATH_SAVE_PATH: &str = "path.json";
+// how far away waypoints can be clicked
+const PATH_REACH: f32 = 500.0;
+// lift the line a little so it doesn't z-fight with the ground
+const PATH_LIFT: f32 = 0.1;
+// world units between the points the drawn line is made of
+const DRAW_STEP: f32 = 1.0;
+const WAYPOINT_COLOR: Color = Color::srgb(1.0, 0.9, 0.2);
+const PATH_COLOR: Color = Color::srgb(0.9, 0.3, 0.9);
+
+// A smooth curve through waypoints. It passes through every waypoint, and
+// `sample` goes from the first at 0.0 to the last at 1.0, spending the same
+// amount of t between each pair however far apart they are.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TerrainSpline {
+    pub waypoints: Vec<Vec3>,
+}
+
+impl TerrainSpline {
+    // The point on the curve at `t`, clamped to 0.0 to 1.0. Between waypoints the
+    // curve can cut through or float over the ground, see `sample_on_terrain`.
+    pub fn sample(&self, t: f32) -> Vec3 {
+        let points = &self.waypoints;
+        match points.len() {
+            0 => return Vec3::ZERO,
+            1 => return points[0],
+            _ => {}
+        }
+        let segments = points.len() - 1;
+        let position = t.clamp(0.0, 1.0) * segments as f32;
+        let i = (position.floor() as usize).min(segments - 1);
+        let local = position - i as f32;
+        // the ends repeat their waypoint so the curve still reaches them
+        let p0 = points[i.saturating_sub(1)];
+        let p1 = points[i];
+        let p2 = points[i + 1];
+        let p3 = points[(i + 2).min(segments)];
+        catmull_rom(p0, p1, p2, p3, local)
+    }
+
+    // Like `sample`, but standing on the ground wherever the curve is over the terrain.
+    pub fn sample_on_terrain(&self, heights: &TerrainHeights, t: f32) -> Vec3 {
+        let point = self.sample(t);
+        match heights.height_at(point.x, point.z) {
+            Some(y) => point.with_y(y),
+            None => point,
+        }
+    }
+
+    // Rough length of the path over the ground, in world units.
+    pub fn length(&self, heights: &TerrainHeights) -> f32 {
+        let samples = self.waypoints.len().saturating_sub(1) * 16;
+        (0..samples)
+            .map(|i| {
+                let a = self.sample_on_terrain(heights, i as f32 / samples as f32);
+                let b = self.sample_on_terrain(heights, (i + 1) as f32 / samples as f32);
+                a.distance(b)
+            })
+            .sum()
+    }
+
+    fn load(path: &str) -> Option<TerrainSpline> {
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        let text = std::fs::read_to_string(path).ok()?;
+        match serde_json::from_str(&text) {
+            Ok(spline) => Some(spline),
+            Err(err) => {
+                warn!("couldn't load the path from {}: {}", path, err);
+                None
+            }
+        }
+    }
+
+    fn save(&self, path: &str) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let result = serde_json::to_string(self)
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(path, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("couldn't save the path to {}: {}", path, err);
+        }
+    }
+}
+
+// Uniform Catmull-Rom between `p1` and `p2`.
+pub fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PathTool {
+    pub active: bool,
+    pub spline: TerrainSpline,
+}
+
+pub fn load_path(mut tool: ResMut<PathTool>) {
+    if let Some(spline) = TerrainSpline::load(PATH_SAVE_PATH) {
+        tool.spline = spline;
+    }
+}
+
+// Turns path mode on or off, saving the path when leaving it.
+pub fn toggle_path_tool(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut tool: ResMut<PathTool>,
+) {
+    if !keys.just_pressed(bindings.path_tool) {
+        return;
+    }
+    tool.active = !tool.active;
+    if !tool.active {
+        tool.spline.save(PATH_SAVE_PATH);
+    }
+}
+
+pub fn path_clicks(
+    mouse: Res<ButtonInput<MouseButton>>,
+    context: Res<RapierContext>,
+    mut tool: ResMut<PathTool>,
+    camera: Query<&Transform, With<Camera3d>>,
+    player: Query<Entity, With<Player>>,
+    mountain: Query<Entity, With<Mountain>>,
+) {
+    if !tool.active {
+        return;
+    }
+    if mouse.just_pressed(MouseButton::Right) {
+        tool.spline.waypoints.pop();
+        return;
+    }
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let (Ok(camera), Ok(player), Ok(mountain)) = (
+        camera.get_single(),
+        player.get_single(),
+        mountain.get_single(),
+    ) else {
+        return;
+    };
+    if let Some(hit) = terrain_under_crosshair(&context, camera, player, mountain, PATH_REACH) {
+        tool.spline.waypoints.push(hit);
+    }
+}
+
+// The path is only drawn in path mode, but can be sampled any time.
+pub fn draw_path(mut gizmos: Gizmos, tool: Res<PathTool>, heights: Res<TerrainHeights>) {
+    if !tool.active {
+        return;
+    }
+    let spline = &tool.spline;
+    for waypoint in &spline.waypoints {
+        gizmos.sphere(*waypoint, Quat::IDENTITY, 0.4, WAYPOINT_COLOR);
+    }
+    if spline.waypoints.len() < 2 {
+        return;
+    }
+    let samples = ((spline.length(&heights) / DRAW_STEP).ceil() as usize).max(1);
+    gizmos.linestrip(
+        (0..=samples).map(|i| {
+            spline.sample_on_terrain(&heights, i as f32 / samples as f32) + Vec3::Y * PATH_LIFT
+        }),
+        PATH_COLOR,
+    );
+}
@@ -0,0 +1,55 @@
+//! Gravity, in one place for everything that falls. Rapier pulls on the dynamic
+//! bodies like thrown cubes, while the player is kinematic and falls by hand in
+//! `movement::step_velocity`, so the two can be tuned apart: a floaty moon, or
+//! a heavy world where only the props drop like stones.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{GravityScale, RapierConfiguration};
+
+// world units per second squared, a meter per unit, and Rapier's own default
+pub const EARTH_GRAVITY: f32 = 9.81;
+
+#[derive(Resource, Debug, Clone)]
+pub struct PhysicsConfig {
+    // world units per second squared, what Rapier pulls dynamic bodies down with
+    pub gravity: f32,
+    // world units per second squared, what the player falls with
+    pub player_gravity: f32,
+    // multiplier on `gravity` for the dynamic props we spawn
+    pub prop_gravity_scale: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            gravity: EARTH_GRAVITY,
+            player_gravity: EARTH_GRAVITY,
+            prop_gravity_scale: 1.0,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    pub fn rapier_gravity(&self) -> Vec3 {
+        Vec3::NEG_Y * self.gravity
+    }
+
+    pub fn prop_gravity(&self) -> GravityScale {
+        GravityScale(self.prop_gravity_scale)
+    }
+}
+
+// Passes config changes on to Rapier and the props already out there.
+pub fn apply_physics_config(
+    config: Res<PhysicsConfig>,
+    mut rapier: ResMut<RapierConfiguration>,
+    mut props: Query<&mut GravityScale>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    rapier.gravity = config.rapier_gravity();
+    for mut scale in props.iter_mut() {
+        *scale = config.prop_gravity();
+    }
+}
@@ -0,0 +1,146 @@
+//! A visible body for the player with a few placeholder "animations".
+//! The body is a child of the player so it follows it around, but it has no
+//! collider of its own, so physics only ever sees the player's ball.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::KinematicCharacterControllerOutput;
+
+use crate::movement::{MovementConfig, PlayerShape, PlayerVelocity};
+use crate::Player;
+
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerState {
+    #[default]
+    Idle,
+    Walking,
+    Jumping,
+    Falling,
+}
+
+// below this horizontal speed a grounded player counts as standing still
+const WALK_THRESHOLD: f32 = 0.1;
+
+impl PlayerState {
+    pub fn from_motion(velocity: Vec3, grounded: bool) -> PlayerState {
+        if grounded {
+            if Vec2::new(velocity.x, velocity.z).length() < WALK_THRESHOLD {
+                PlayerState::Idle
+            } else {
+                PlayerState::Walking
+            }
+        } else if velocity.y > 0.0 {
+            PlayerState::Jumping
+        } else {
+            PlayerState::Falling
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            PlayerState::Idle => Color::srgb(0.8, 0.8, 0.8),
+            PlayerState::Walking => Color::srgb(0.3, 0.8, 0.4),
+            PlayerState::Jumping => Color::srgb(0.3, 0.5, 0.9),
+            PlayerState::Falling => Color::srgb(0.9, 0.4, 0.3),
+        }
+    }
+
+    // stretch while going up, squash a little while coming down
+    fn scale(self) -> Vec3 {
+        match self {
+            PlayerState::Idle | PlayerState::Walking => Vec3::ONE,
+            PlayerState::Jumping => Vec3::new(0.85, 1.2, 0.85),
+            PlayerState::Falling => Vec3::new(1.1, 0.9, 1.1),
+        }
+    }
+}
+
+// Sent when the player comes down on the ground after being in the air.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerLanded {
+    // the player's feet
+    pub position: Vec3,
+    // how fast they were falling, in units per second
+    pub speed: f32,
+}
+
+// Marker for the mesh that draws the player.
+#[derive(Component)]
+pub struct PlayerBody {}
+
+pub fn spawn_player_body(
+    mut commands: Commands,
+    config: Res<MovementConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    player: Query<Entity, With<Player>>,
+) {
+    let radius = config.player_radius;
+    let mesh = match config.player_shape {
+        // the capsule is as tall as the ball is wide, plus a bit
+        PlayerShape::Ball => Capsule3d::new(radius * 0.6, radius),
+        PlayerShape::Capsule { .. } => {
+            Capsule3d::new(radius, 2.0 * (config.player_half_height() - radius))
+        }
+    };
+    let body = commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(mesh),
+                material: materials.add(StandardMaterial {
+                    base_color: PlayerState::Idle.color(),
+                    ..default()
+                }),
+                ..default()
+            },
+            PlayerBody {},
+        ))
+        .id();
+    commands
+        .entity(player.single())
+        .insert(PlayerState::default())
+        .add_child(body);
+}
+
+pub fn update_player_state(
+    config: Res<MovementConfig>,
+    mut landed: EventWriter<PlayerLanded>,
+    mut player: Query<
+        (
+            &Transform,
+            &PlayerVelocity,
+            &KinematicCharacterControllerOutput,
+            &mut PlayerState,
+        ),
+        With<Player>,
+    >,
+) {
+    let (transform, velocity, output, mut state) = player.single_mut();
+    let new_state = PlayerState::from_motion(velocity.0, output.grounded);
+    // the velocity still has the fall in it, it's only zeroed next frame
+    if output.grounded && matches!(*state, PlayerState::Jumping | PlayerState::Falling) {
+        landed.send(PlayerLanded {
+            position: transform.translation - Vec3::Y * config.player_half_height(),
+            speed: -velocity.0.y.min(0.0),
+        });
+    }
+    // only touch the component when it changes so `Changed<PlayerState>` is meaningful
+    if *state != new_state {
+        *state = new_state;
+    }
+}
+
+pub fn animate_player_body(
+    player: Query<&PlayerState, (With<Player>, Changed<PlayerState>)>,
+    mut body: Query<(&mut Transform, &Handle<StandardMaterial>), With<PlayerBody>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Ok(state) = player.get_single() else {
+        return;
+    };
+    for (mut transform, material) in body.iter_mut() {
+        transform.scale = state.scale();
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color = state.color();
+        }
+    }
+}
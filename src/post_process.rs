@@ -0,0 +1,90 @@
+//! Bloom and tonemapping on the camera. The camera renders in HDR so bright
+//! snow and the sun can bloom. The defaults leave bloom off and keep Bevy's own
+//! tonemapper, so the scene looks the same as it did without them.
+
+use bevy::core_pipeline::bloom::BloomSettings;
+use bevy::core_pipeline::tonemapping::Tonemapping;
+use bevy::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// range the options menu steps bloom through
+pub const BLOOM_RANGE: (f32, f32, f32) = (0.0, 0.5, 0.05);
+
+// The tonemappers the options menu cycles through, and the names they're saved under.
+pub const TONEMAPPERS: [(Tonemapping, &str); 7] = [
+    (Tonemapping::TonyMcMapface, "Tony McMapface"),
+    (Tonemapping::AgX, "AgX"),
+    (Tonemapping::AcesFitted, "ACES"),
+    (Tonemapping::BlenderFilmic, "Blender Filmic"),
+    (Tonemapping::Reinhard, "Reinhard"),
+    (Tonemapping::SomewhatBoringDisplayTransform, "Boring"),
+    (Tonemapping::None, "None"),
+];
+
+pub fn tonemapping_name(tonemapping: Tonemapping) -> &'static str {
+    TONEMAPPERS
+        .iter()
+        .find(|(t, _)| *t == tonemapping)
+        .map_or("Other", |(_, name)| name)
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostProcessSettings {
+    // 0.0 turns bloom off
+    pub bloom_intensity: f32,
+    #[serde(with = "tonemapping_by_name")]
+    pub tonemapping: Tonemapping,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        PostProcessSettings {
+            bloom_intensity: 0.0,
+            // Bevy's default
+            tonemapping: Tonemapping::TonyMcMapface,
+        }
+    }
+}
+
+// Tonemapping isn't serializable, so it's saved by its name in `TONEMAPPERS`.
+// Unknown names load as the default.
+mod tonemapping_by_name {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(tonemapping: &Tonemapping, s: S) -> Result<S::Ok, S::Error> {
+        tonemapping_name(*tonemapping).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Tonemapping, D::Error> {
+        let name = String::deserialize(d)?;
+        Ok(TONEMAPPERS
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map_or(PostProcessSettings::default().tonemapping, |(t, _)| *t))
+    }
+}
+
+// Puts the settings on the camera whenever they change, or a camera appears.
+// Only the 3D camera though, the one stretching its scaled down render over the
+// window (see render_scale.rs) would tonemap the picture a second time.
+pub fn apply_post_process(
+    mut commands: Commands,
+    settings: Res<PostProcessSettings>,
+    mut cameras: Query<(Entity, Ref<Camera>, &mut Tonemapping), With<Camera3d>>,
+) {
+    for (entity, camera, mut tonemapping) in cameras.iter_mut() {
+        if !settings.is_changed() && !camera.is_added() {
+            continue;
+        }
+        *tonemapping = settings.tonemapping;
+        if settings.bloom_intensity > 0.0 {
+            commands.entity(entity).insert(BloomSettings {
+                intensity: settings.bloom_intensity,
+                ..BloomSettings::NATURAL
+            });
+        } else {
+            commands.entity(entity).remove::<BloomSettings>();
+        }
+    }
+}
@@ -0,0 +1,380 @@
+//! Terrain presets: a RON file read at startup with the seed, the terrain
+//! generation settings and the terrain colors, so different worlds can be shipped
+//! and shared without recompiling. `terrain.ron` in the working directory is used
+//! unless another file is given with `--terrain <path>`. Anything left out of the
+//! file keeps its default. The seed is `Random` unless it's set to `Fixed(1234)`
+//! or `Daily`, which everyone playing on the same (UTC) day gets the same terrain
+//! from. A random seed can be replaced with a fixed one from the
+//! `SHADER_GAME_SEED` environment variable, for reproducible captures.
+//!
+//! The optional name is shown in the window title along with the seed. A key
+//! saves the terrain being played as a preset in the same format, with its seed
+//! fixed, so live tweaks can be loaded again with `--terrain` or shipped.
+//!
+//! There are also a few built in presets for showing off, which a key cycles
+//! through while playing. Each one regenerates the terrain with the same seed.
+//! They only change the shape and colors, everything else stays as the startup
+//! preset had it.
+//!
+//! ```ron
+//! (
+//!     name: Some("Snowy wraparound"),
+//!     seed: Fixed(1234),
+//!     terrain: (interpolate_step: 10, height_scale: 60.0, boundary: Wrap),
+//!     height_gradient: (stops: [
+//!         (0.0, Srgba((red: 0.8, green: 0.7, blue: 0.5, alpha: 1.0))),
+//!         (1.0, Srgba((red: 1.0, green: 1.0, blue: 1.0, alpha: 1.0))),
+//!     ]),
+//! )
+//! ```
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::controls::KeyBindings;
+use crate::creatures::Creature;
+use crate::height_gradient::HeightGradient;
+use crate::loading::{AfterLoading, TerrainTask};
+use crate::navigation::WalkabilityGrid;
+use crate::rocks::Rock;
+use crate::scatter::Scattered;
+use crate::settings::Settings;
+use crate::terrain::{
+    IslandMask, NoiseLayer, SeedMode, TerrainConfig, TerrainHeights, TerrainSeed,
+};
+use crate::terrain_edit::TerrainEdits;
+use crate::{GameState, Mountain};
+
+const DEFAULT_PRESET_PATH: &str = "terrain.ron";
+const PRESET_FLAG: &str = "--terrain";
+// where the save key writes the current terrain to
+const SAVED_PRESET_PATH: &str = "terrain_saved.ron";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainPreset {
+    pub name: Option<String>,
+    pub seed: SeedMode,
+    pub terrain: TerrainConfig,
+    pub height_gradient: HeightGradient,
+}
+
+impl TerrainPreset {
+    // The preset path from the command line, or the default one.
+    fn path_from_args() -> (String, bool) {
+        let mut args = std::env::args()
+            .skip_while(|arg| arg != PRESET_FLAG)
+            .skip(1);
+        match args.next() {
+            Some(path) => (path, true),
+            None => (DEFAULT_PRESET_PATH.to_string(), false),
+        }
+    }
+
+    // Reads the preset, falling back to the defaults if it can't be used. Logging
+    // isn't set up yet when this runs, so the reason comes back to be logged later.
+    pub fn load() -> (TerrainPreset, Option<String>) {
+        if cfg!(target_arch = "wasm32") {
+            return (TerrainPreset::default(), None);
+        }
+        let (path, explicit) = TerrainPreset::path_from_args();
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            // no preset is fine unless one was asked for
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !explicit => {
+                return (TerrainPreset::default(), None);
+            }
+            Err(err) => {
+                let error = format!("couldn't read {}, using the default terrain: {}", path, err);
+                return (TerrainPreset::default(), Some(error));
+            }
+        };
+        let preset = match ron::from_str::<TerrainPreset>(&text) {
+            Ok(preset) => preset,
+            Err(err) => {
+                let error = format!("{} is malformed, using the default terrain: {}", path, err);
+                return (TerrainPreset::default(), Some(error));
+            }
+        };
+        if let Err(err) = preset.terrain.validate() {
+            let error = format!("{} can't be used, using the default terrain: {}", path, err);
+            return (TerrainPreset::default(), Some(error));
+        }
+        (preset, None)
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+    }
+}
+
+// One of the presets to cycle through.
+pub struct BuiltinPreset {
+    pub name: &'static str,
+    pub terrain: TerrainConfig,
+    pub height_gradient: HeightGradient,
+}
+
+// The built in presets, on top of `base` so whatever they don't pick, like the
+// terrain's size, collider and boundary, stays the same.
+pub fn builtin_presets(base: &TerrainConfig) -> Vec<BuiltinPreset> {
+    let layer = |frequency_mult, amplitude| NoiseLayer {
+        frequency_mult,
+        amplitude,
+    };
+    vec![
+        BuiltinPreset {
+            name: "Rolling hills",
+            terrain: TerrainConfig {
+                interpolate_step: 40,
+                height_scale: 40.0,
+                layers: vec![layer(1, 1.0), layer(2, 0.3)],
+                ..base.clone()
+            },
+            height_gradient: HeightGradient {
+                stops: vec![
+                    (0.0, Color::srgb(0.2, 0.4, 0.15)),
+                    (0.5, Color::srgb(0.4, 0.6, 0.25)),
+                    (1.0, Color::srgb(0.6, 0.7, 0.35)),
+                ],
+            },
+        },
+        BuiltinPreset {
+            name: "Jagged peaks",
+            terrain: TerrainConfig {
+                interpolate_step: 20,
+                height_scale: 140.0,
+                layers: vec![layer(1, 1.0), layer(2, 0.6), layer(4, 0.3), layer(10, 0.1)],
+                ..base.clone()
+            },
+            height_gradient: HeightGradient {
+                stops: vec![
+                    (0.0, Color::srgb(0.25, 0.3, 0.2)),
+                    (0.3, Color::srgb(0.4, 0.38, 0.35)),
+                    (0.6, Color::srgb(0.5, 0.5, 0.52)),
+                    (0.75, Color::srgb(0.95, 0.95, 1.0)),
+                ],
+            },
+        },
+        // there's no terracing, so the tops are broad and smooth rather than
+        // flat, with bands of rock color down the sides
+        BuiltinPreset {
+            name: "Plateau",
+            terrain: TerrainConfig {
+                interpolate_step: 50,
+                height_scale: 70.0,
+                layers: vec![layer(1, 1.0), layer(5, 0.08)],
+                ..base.clone()
+            },
+            height_gradient: HeightGradient {
+                stops: vec![
+                    (0.0, Color::srgb(0.6, 0.45, 0.3)),
+                    (0.4, Color::srgb(0.7, 0.4, 0.25)),
+                    (0.55, Color::srgb(0.8, 0.6, 0.4)),
+                    (0.7, Color::srgb(0.55, 0.5, 0.3)),
+                    (1.0, Color::srgb(0.5, 0.55, 0.3)),
+                ],
+            },
+        },
+        // there's no water yet, the lowlands are just colored like it
+        BuiltinPreset {
+            name: "Islands",
+            terrain: TerrainConfig {
+                interpolate_step: 25,
+                height_scale: 60.0,
+                layers: vec![layer(1, 1.0), layer(3, 0.25)],
+                island: IslandMask {
+                    enabled: true,
+                    ..default()
+                },
+                ..base.clone()
+            },
+            height_gradient: HeightGradient {
+                stops: vec![
+                    (0.0, Color::srgb(0.05, 0.15, 0.35)),
+                    (0.35, Color::srgb(0.1, 0.35, 0.55)),
+                    (0.4, Color::srgb(0.85, 0.8, 0.6)),
+                    (0.48, Color::srgb(0.3, 0.55, 0.25)),
+                    (1.0, Color::srgb(0.2, 0.4, 0.2)),
+                ],
+            },
+        },
+    ]
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct PresetCycle {
+    // the startup terrain settings the built in presets go on top of
+    base: TerrainConfig,
+    // what the startup preset calls itself, if anything
+    startup_name: Option<String>,
+    // index into `builtin_presets`, None while the startup preset is in use
+    pub current: Option<usize>,
+}
+
+impl PresetCycle {
+    pub fn new(base: TerrainConfig, startup_name: Option<String>) -> PresetCycle {
+        PresetCycle {
+            base,
+            startup_name,
+            current: None,
+        }
+    }
+
+    // None for a startup preset without a name
+    pub fn name(&self) -> Option<&str> {
+        match self.current {
+            Some(index) => Some(builtin_presets(&self.base)[index].name),
+            None => self.startup_name.as_deref(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!("Terrain: {}", self.name().unwrap_or("startup preset"))
+    }
+}
+
+// Switches to the next built in preset and regenerates the terrain. The old
+// terrain goes once loading starts, see `clear_old_terrain`.
+#[allow(clippy::type_complexity)]
+pub fn cycle_presets(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    seed: Res<TerrainSeed>,
+    mut cycle: ResMut<PresetCycle>,
+    (mut terrain_config, mut gradient): (ResMut<TerrainConfig>, ResMut<HeightGradient>),
+    (mut after_loading, mut next_state): (ResMut<AfterLoading>, ResMut<NextState<GameState>>),
+) {
+    if !keys.just_pressed(bindings.next_preset) {
+        return;
+    }
+    let presets = builtin_presets(&cycle.base);
+    let index = cycle.current.map_or(0, |index| (index + 1) % presets.len());
+    cycle.current = Some(index);
+    let preset = &presets[index];
+    let (config, budget_warning) = preset.terrain.fit_budget();
+    if let Some(warning) = budget_warning {
+        warn!("{}", warning);
+    }
+    info!("switching to the {} terrain preset", preset.name);
+
+    // edits still in flight were for the old terrain
+    commands.insert_resource(TerrainEdits::default());
+    commands.insert_resource(TerrainTask::start(&config, *seed));
+    *terrain_config = config;
+    *gradient = preset.height_gradient.clone();
+    after_loading.0 = GameState::Playing;
+    next_state.set(GameState::Loading);
+}
+
+// Everything that stands on the terrain goes with it, and comes back once the new
+// terrain is spawned. This waits for the loading state, as the systems that
+// read the heights while playing expect them to be there.
+#[allow(clippy::type_complexity)]
+pub fn clear_old_terrain(
+    mut commands: Commands,
+    terrain: Query<Entity, Or<(With<Mountain>, With<Rock>, With<Scattered>, With<Creature>)>>,
+) {
+    for entity in terrain.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    // both come back with the new terrain, the creatures along with the grid
+    commands.remove_resource::<TerrainHeights>();
+    commands.remove_resource::<WalkabilityGrid>();
+}
+
+// Writes the terrain being played to a preset file `TerrainPreset::load` can
+// read, and the settings with the controls to theirs.
+pub fn save_preset(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    seed: Res<TerrainSeed>,
+    cycle: Res<PresetCycle>,
+    (terrain_config, gradient): (Res<TerrainConfig>, Res<HeightGradient>),
+    settings: Res<Settings>,
+) {
+    if !keys.just_pressed(bindings.save_preset) || cfg!(target_arch = "wasm32") {
+        return;
+    }
+    let preset = TerrainPreset {
+        name: cycle.name().map(str::to_string),
+        seed: SeedMode::Fixed(seed.0),
+        terrain: terrain_config.clone(),
+        height_gradient: gradient.clone(),
+    };
+    let text = match preset.to_ron() {
+        Ok(text) => text,
+        Err(err) => {
+            warn!("couldn't serialize the terrain preset: {}", err);
+            return;
+        }
+    };
+    match std::fs::write(SAVED_PRESET_PATH, text) {
+        Ok(()) => info!(
+            "saved the terrain to {}, load it with {} {}",
+            SAVED_PRESET_PATH, PRESET_FLAG, SAVED_PRESET_PATH
+        ),
+        Err(err) => warn!("couldn't write {}: {}", SAVED_PRESET_PATH, err),
+    }
+    settings.save();
+}
+
+// Marker for the label saying which preset the terrain is from.
+#[derive(Component)]
+pub struct PresetLabel {}
+
+pub fn spawn_preset_label(mut commands: Commands, cycle: Res<PresetCycle>) {
+    commands.spawn((
+        TextBundle::from_section(cycle.label(), TextStyle::default()).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(12.0),
+            left: Val::Px(12.0),
+            ..default()
+        }),
+        PresetLabel {},
+    ));
+}
+
+pub fn update_preset_label(
+    cycle: Res<PresetCycle>,
+    mut label: Query<&mut Text, With<PresetLabel>>,
+) {
+    if !cycle.is_changed() {
+        return;
+    }
+    for mut text in label.iter_mut() {
+        text.sections[0].value = cycle.label();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_preset_loads_back_the_same() {
+        let base = TerrainConfig::default();
+        let builtin = builtin_presets(&base).swap_remove(3);
+        let preset = TerrainPreset {
+            name: Some(builtin.name.to_string()),
+            seed: SeedMode::Fixed(1234),
+            terrain: builtin.terrain,
+            height_gradient: builtin.height_gradient,
+        };
+        let text = preset.to_ron().unwrap();
+        let loaded: TerrainPreset = ron::from_str(&text).unwrap();
+        assert_eq!(loaded.name, preset.name);
+        assert_eq!(loaded.seed, preset.seed);
+        // neither config has PartialEq, so they're compared as they'd be saved
+        assert_eq!(loaded.to_ron().unwrap(), text);
+    }
+
+    #[test]
+    fn builtin_presets_are_valid() {
+        for preset in builtin_presets(&TerrainConfig::default()) {
+            let (config, _) = preset.terrain.fit_budget();
+            assert_eq!(config.validate(), Ok(()), "{}", preset.name);
+        }
+    }
+}
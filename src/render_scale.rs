@@ -0,0 +1,231 @@
+//! Adaptive resolution for weak GPUs. When it's on, the 3D camera renders into
+//! an image a fraction of the window's size, which a second camera stretches
+//! over the window. The UI is drawn by that second camera, so it stays sharp at
+//! the window's own resolution. Every so often the fraction steps down if frames
+//! take longer than `target_fps` allows and back up if there's plenty of time to
+//! spare, with a gap in between so it doesn't flip back and forth. Frames are
+//! timed without the sleep the frame rate cap adds, so a cap under `target_fps`
+//! isn't mistaken for a slow GPU. Waiting on vsync can't be told apart from
+//! rendering, so the scale holds still while vsync is on.
+
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::view::RenderLayers;
+use bevy::window::PrimaryWindow;
+
+use crate::window::{FrameWork, WindowConfig};
+
+#[derive(Resource, Debug, Clone)]
+pub struct RenderScaleConfig {
+    // off renders straight to the window, as without any of this
+    pub enabled: bool,
+    pub target_fps: f32,
+    // fractions of the window's resolution the scale stays between
+    pub min_scale: f32,
+    pub max_scale: f32,
+    // how much the scale changes at a time
+    pub step: f32,
+    // the scale only goes up when frames take less than this fraction of the
+    // target frame time, so raising it doesn't immediately miss the target
+    pub headroom: f32,
+    // seconds of frame time averaged before each change
+    pub interval: f32,
+}
+
+impl Default for RenderScaleConfig {
+    fn default() -> Self {
+        RenderScaleConfig {
+            enabled: false,
+            target_fps: 50.0,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            step: 0.1,
+            headroom: 0.75,
+            interval: 1.0,
+        }
+    }
+}
+
+impl RenderScaleConfig {
+    // The scale after a stretch of frames averaging `frame_time` seconds.
+    pub fn next_scale(&self, scale: f32, frame_time: f32) -> f32 {
+        let target = 1.0 / self.target_fps.max(1.0);
+        let next = if frame_time > target {
+            scale - self.step
+        } else if frame_time < target * self.headroom {
+            scale + self.step
+        } else {
+            scale
+        };
+        next.clamp(self.min_scale, self.max_scale.max(self.min_scale))
+    }
+}
+
+// The image the 3D camera renders into, and how big it is next to the window.
+#[derive(Resource, Debug)]
+pub struct RenderScale {
+    pub scale: f32,
+    image: Handle<Image>,
+    // frames and seconds since the scale last changed
+    frames: u32,
+    elapsed: f32,
+}
+
+// Marker for the sprite showing the scaled down render.
+#[derive(Component)]
+pub struct ScaledView {}
+
+// layer the scaled view is on, so only the camera stretching it sees it
+const SCALED_VIEW_LAYER: usize = 31;
+
+fn render_size(window: &Window, scale: f32) -> Extent3d {
+    Extent3d {
+        width: ((window.physical_width() as f32 * scale) as u32).max(1),
+        height: ((window.physical_height() as f32 * scale) as u32).max(1),
+        depth_or_array_layers: 1,
+    }
+}
+
+// Points the 3D camera at an image and puts a camera in front of the window
+// showing it.
+pub fn setup_render_scale(
+    mut commands: Commands,
+    config: Res<RenderScaleConfig>,
+    mut images: ResMut<Assets<Image>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut camera: Query<&mut Camera, With<Camera3d>>,
+) {
+    if !config.enabled {
+        return;
+    }
+    let (Ok(window), Ok(mut camera)) = (window.get_single(), camera.get_single_mut()) else {
+        return;
+    };
+    let scale = config.max_scale;
+    let size = render_size(window, scale);
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("scaled view"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        },
+        ..default()
+    };
+    image.resize(size);
+    let image = images.add(image);
+    camera.target = RenderTarget::Image(image.clone());
+
+    commands.spawn((
+        Camera2dBundle {
+            // after the 3D camera, so its render is done by then
+            camera: Camera {
+                order: 1,
+                ..default()
+            },
+            ..default()
+        },
+        RenderLayers::layer(SCALED_VIEW_LAYER),
+        IsDefaultUiCamera,
+    ));
+    commands.spawn((
+        SpriteBundle {
+            texture: image.clone(),
+            sprite: Sprite {
+                custom_size: Some(window.size()),
+                ..default()
+            },
+            ..default()
+        },
+        RenderLayers::layer(SCALED_VIEW_LAYER),
+        ScaledView {},
+    ));
+    commands.insert_resource(RenderScale {
+        scale,
+        image,
+        frames: 0,
+        elapsed: 0.0,
+    });
+}
+
+// Steps the scale up or down with the frame rate, and keeps the image and the
+// sprite showing it the size of the window.
+pub fn adapt_render_scale(
+    work: Res<FrameWork>,
+    config: Res<RenderScaleConfig>,
+    window_config: Res<WindowConfig>,
+    render_scale: Option<ResMut<RenderScale>>,
+    mut images: ResMut<Assets<Image>>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut view: Query<&mut Sprite, With<ScaledView>>,
+) {
+    let (Some(mut render_scale), Ok(window)) = (render_scale, window.get_single()) else {
+        return;
+    };
+    if window_config.vsync {
+        // frames wait for the monitor, so they'd read as slower than they are
+        render_scale.frames = 0;
+        render_scale.elapsed = 0.0;
+    } else {
+        render_scale.frames += 1;
+        render_scale.elapsed += work.seconds;
+    }
+    if render_scale.frames > 0 && render_scale.elapsed >= config.interval {
+        let frame_time = render_scale.elapsed / render_scale.frames as f32;
+        let scale = config.next_scale(render_scale.scale, frame_time);
+        if scale != render_scale.scale {
+            info!(
+                "rendering at {:.0}% of the window's resolution, frames took {:.1}ms",
+                scale * 100.0,
+                frame_time * 1000.0
+            );
+            render_scale.scale = scale;
+        }
+        render_scale.frames = 0;
+        render_scale.elapsed = 0.0;
+    }
+
+    let size = render_size(window, render_scale.scale);
+    // only a changed size touches the image, which has the camera reallocate it
+    if images
+        .get(&render_scale.image)
+        .is_some_and(|image| image.texture_descriptor.size != size)
+    {
+        if let Some(image) = images.get_mut(&render_scale.image) {
+            image.resize(size);
+        }
+    }
+    for mut sprite in view.iter_mut() {
+        if sprite.custom_size != Some(window.size()) {
+            sprite.custom_size = Some(window.size());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_holds_steady_between_the_thresholds() {
+        let config = RenderScaleConfig::default();
+        let target = 1.0 / config.target_fps;
+        // too slow steps down, lots of headroom steps up
+        assert!(config.next_scale(0.8, target * 1.2) < 0.8);
+        assert!(config.next_scale(0.8, target * 0.5) > 0.8);
+        // just under the target isn't enough to go back up
+        assert_eq!(config.next_scale(0.8, target * 0.9), 0.8);
+        // and it never leaves the configured range
+        assert_eq!(config.next_scale(config.min_scale, 1.0), config.min_scale);
+        assert_eq!(config.next_scale(config.max_scale, 0.0), config.max_scale);
+    }
+}
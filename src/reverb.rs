@@ -0,0 +1,109 @@
+//! How enclosed the player is by the terrain, for sound. The ground is sampled in
+//! rings around the player, and the more of it rises above them the more
+//! enclosed they are: 0.0 on a peak, 1.0 at the bottom of a steep valley.
+//! Footsteps echo more the more enclosed the player is, and the wind dies down.
+//! Playing sounds can't have an echo added, so the wind only gets quieter.
+
+use bevy::audio::{AudioSink, AudioSinkPlayback};
+use bevy::prelude::*;
+
+use crate::audio::{AudioChannel, AudioSettings, ChannelVolume, WindSound};
+use crate::terrain::TerrainHeights;
+use crate::Player;
+
+#[derive(Resource, Debug, Clone)]
+pub struct ReverbConfig {
+    // how much footsteps echo when fully enclosed, 0.0 to 1.0
+    pub strength: f32,
+    // fraction the wind is quieted by when fully enclosed, 0.0 to 1.0
+    pub wind_shelter: f32,
+    // distances from the player the ground is sampled at, in world units
+    pub sample_distances: [f32; 2],
+    // slope from the player up to the surrounding ground that counts as fully
+    // enclosed, rise over run
+    pub full_enclosure_slope: f32,
+    // how quickly the effect follows the player, higher is faster
+    pub smoothing: f32,
+}
+
+impl Default for ReverbConfig {
+    fn default() -> Self {
+        ReverbConfig {
+            strength: 0.5,
+            wind_shelter: 0.6,
+            sample_distances: [15.0, 40.0],
+            full_enclosure_slope: 0.6,
+            smoothing: 2.0,
+        }
+    }
+}
+
+// directions sampled around the player at each distance
+const SAMPLE_DIRECTIONS: usize = 8;
+
+// 0.0 to 1.0, how enclosed the player is right now, eased over time.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct Enclosure(pub f32);
+
+// How enclosed a point on the xz plane is, 0.0 to 1.0. Samples off the terrain
+// count as open.
+pub fn enclosure_at(config: &ReverbConfig, heights: &TerrainHeights, position: Vec2) -> f32 {
+    let Some(ground) = heights.height_at(position.x, position.y) else {
+        return 0.0;
+    };
+    let full = config.full_enclosure_slope.max(f32::EPSILON);
+    let mut total = 0.0;
+    let mut samples = 0;
+    for &distance in &config.sample_distances {
+        for i in 0..SAMPLE_DIRECTIONS {
+            let angle = i as f32 / SAMPLE_DIRECTIONS as f32 * std::f32::consts::TAU;
+            let sample = position + Vec2::from_angle(angle) * distance;
+            let rise = heights
+                .height_at(sample.x, sample.y)
+                .map_or(0.0, |height| height - ground);
+            total += (rise / distance / full).clamp(0.0, 1.0);
+            samples += 1;
+        }
+    }
+    total / samples.max(1) as f32
+}
+
+pub fn update_enclosure(
+    time: Res<Time>,
+    config: Res<ReverbConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    mut enclosure: ResMut<Enclosure>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let (Some(heights), Ok(player)) = (heights, player.get_single()) else {
+        return;
+    };
+    let wanted = enclosure_at(&config, &heights, player.translation.xz());
+    // frame rate independent easing
+    let t = 1.0 - (-config.smoothing * time.delta_seconds()).exp();
+    enclosure.0 += (wanted - enclosure.0) * t;
+}
+
+// Quiets the wind in sheltered places, on top of its channel volume.
+pub fn shelter_wind(
+    settings: Res<AudioSettings>,
+    config: Res<ReverbConfig>,
+    enclosure: Res<Enclosure>,
+    mut wind: Query<(&mut ChannelVolume, Option<&AudioSink>), With<WindSound>>,
+) {
+    let shelter = if settings.reverb {
+        config.wind_shelter.clamp(0.0, 1.0) * enclosure.0
+    } else {
+        0.0
+    };
+    for (mut channel, sink) in wind.iter_mut() {
+        let volume = 1.0 - shelter;
+        if channel.volume == volume {
+            continue;
+        }
+        channel.volume = volume;
+        if let Some(sink) = sink {
+            sink.set_volume(settings.channel(AudioChannel::Ambient) * volume);
+        }
+    }
+}
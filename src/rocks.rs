@@ -0,0 +1,215 @@
+//! Rocks lying around on the terrain that the player bumps into. They're placed
+//! per cell like the scatter, from the seed and the cell alone, but only where
+//! the ground is neither flat nor a cliff, and lie flat on the terrain's normal
+//! averaged over their footprint. Every rock shares one mesh and material, so
+//! Bevy draws them all instanced. Only rocks near the player have colliders, the
+//! rest are just for looks until the player gets close.
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{Collider, CollisionGroups, Group};
+use rand::prelude::*;
+
+use crate::scatter::{cell_rng, cells_in, RescatterRegion};
+use crate::terrain::{TerrainHeights, TerrainSeed};
+use crate::{Player, PROPS_GROUP};
+
+#[derive(Resource, Debug, Clone)]
+pub struct RockConfig {
+    // world units per side of a rock cell
+    pub cell_size: f32,
+    // each cell gets between 0 and this many rocks, before the slope check
+    pub max_per_cell: u32,
+    // rocks only go where the slope is between these, in degrees
+    pub min_slope: f32,
+    pub max_slope: f32,
+    // rocks within this distance of the player have colliders
+    pub collision_radius: f32,
+    // range of rock sizes, in world units across
+    pub min_size: f32,
+    pub max_size: f32,
+}
+
+impl Default for RockConfig {
+    fn default() -> Self {
+        RockConfig {
+            cell_size: 20.0,
+            max_per_cell: 2,
+            min_slope: 8.0,
+            max_slope: 35.0,
+            collision_radius: 40.0,
+            min_size: 0.6,
+            max_size: 2.5,
+        }
+    }
+}
+
+// how squashed rocks are compared to a ball, along x, y and z
+const ROCK_SHAPE: Vec3 = Vec3::new(1.0, 0.6, 0.8);
+// the collider box is a bit inside the rock so its corners don't stick out
+const COLLIDER_FIT: f32 = 0.75;
+// colliders are only removed this much past `collision_radius`, so walking back
+// and forth over the edge doesn't keep adding and removing them
+const COLLIDER_HYSTERESIS: f32 = 5.0;
+// keeps rocks from landing on the same spots as the scatter's objects
+const ROCK_SEED_SALT: u64 = 0x0052_4f43_4b53;
+
+#[derive(Component)]
+pub struct Rock {
+    pub cell: IVec2,
+}
+
+#[derive(Resource)]
+pub struct RockAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for RockAssets {
+    fn from_world(world: &mut World) -> Self {
+        // a coarse sphere looks faceted enough to pass for a rock
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Sphere::new(0.5).mesh().ico(1).unwrap());
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::srgb(0.45, 0.43, 0.4),
+                perceptual_roughness: 0.9,
+                ..default()
+            });
+        RockAssets { mesh, material }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RockPlacement {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    // world units across along each axis, before the rotation
+    pub size: Vec3,
+}
+
+// The terrain's normal averaged over a square `size` across, so a rock lies on
+// the ground under all of it and not just the point in the middle.
+fn footprint_normal(heights: &TerrainHeights, center: Vec2, size: f32) -> Option<Vec3> {
+    let half = size / 2.0;
+    let sum: Vec3 = [
+        Vec2::ZERO,
+        Vec2::new(-half, -half),
+        Vec2::new(half, -half),
+        Vec2::new(-half, half),
+        Vec2::new(half, half),
+    ]
+    .iter()
+    .filter_map(|offset| heights.normal_at(center.x + offset.x, center.y + offset.y))
+    .sum();
+    sum.try_normalize()
+}
+
+// Where the rocks in a cell go. Only depends on the seed, the cell and the
+// terrain heights.
+pub fn place_rocks(
+    seed: TerrainSeed,
+    cell: IVec2,
+    config: &RockConfig,
+    heights: &TerrainHeights,
+) -> Vec<RockPlacement> {
+    let mut rng = cell_rng(TerrainSeed(seed.0 ^ ROCK_SEED_SALT), cell);
+    let count = rng.gen_range(0..=config.max_per_cell);
+    let corner = cell.as_vec2() * config.cell_size;
+    let (min_size, max_size) = (config.min_size, config.max_size.max(config.min_size));
+    (0..count)
+        .filter_map(|_| {
+            // always draw every number so a skipped rock doesn't shift the rest
+            let offset = Vec2::new(rng.gen(), rng.gen()) * config.cell_size;
+            let yaw = rng.gen_range(0.0..std::f32::consts::TAU);
+            let size = min_size + (max_size - min_size) * rng.gen::<f32>();
+            let position = corner + offset;
+            let y = heights.height_at(position.x, position.y)?;
+            let normal = footprint_normal(heights, position, size)?;
+            let slope = normal.angle_between(Vec3::Y).to_degrees();
+            if slope < config.min_slope || slope > config.max_slope {
+                return None;
+            }
+            let size = ROCK_SHAPE * size;
+            Some(RockPlacement {
+                // sunk a little so the downhill side doesn't float
+                translation: Vec3::new(position.x, y, position.y) + normal * size.y * 0.25,
+                rotation: Quat::from_rotation_arc(Vec3::Y, normal) * Quat::from_rotation_y(yaw),
+                size,
+            })
+        })
+        .collect()
+}
+
+// The collider is scaled with the rock's transform, so it's made for a rock 1.0 across.
+fn rock_collider() -> (Collider, CollisionGroups) {
+    let half = 0.5 * COLLIDER_FIT;
+    (
+        Collider::cuboid(half, half, half),
+        CollisionGroups::new(PROPS_GROUP, Group::ALL),
+    )
+}
+
+// Places rocks again wherever the scatter is redone, so they follow terrain edits.
+pub fn handle_rock_rescatter(
+    mut commands: Commands,
+    mut events: EventReader<RescatterRegion>,
+    seed: Res<TerrainSeed>,
+    config: Res<RockConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    assets: Res<RockAssets>,
+    rocks: Query<(Entity, &Rock)>,
+) {
+    let Some(heights) = heights else {
+        return;
+    };
+    for RescatterRegion(bounds) in events.read() {
+        let cells: Vec<IVec2> = cells_in(*bounds, heights.bounds(), config.cell_size).collect();
+        for (entity, rock) in rocks.iter() {
+            if cells.contains(&rock.cell) {
+                commands.entity(entity).despawn();
+            }
+        }
+        for cell in cells {
+            for placement in place_rocks(*seed, cell, &config, &heights) {
+                // the mesh is 1.0 across, scaling it makes it the rock's size.
+                // Colliders come later, see `update_rock_colliders`.
+                commands.spawn((
+                    PbrBundle {
+                        mesh: assets.mesh.clone(),
+                        material: assets.material.clone(),
+                        transform: Transform::from_translation(placement.translation)
+                            .with_rotation(placement.rotation)
+                            .with_scale(placement.size),
+                        ..default()
+                    },
+                    Rock { cell },
+                ));
+            }
+        }
+    }
+}
+
+// Gives rocks near the player colliders and takes them away from far ones.
+#[allow(clippy::type_complexity)]
+pub fn update_rock_colliders(
+    mut commands: Commands,
+    config: Res<RockConfig>,
+    player: Query<&Transform, With<Player>>,
+    rocks: Query<(Entity, &Transform, Has<Collider>), (With<Rock>, Without<Player>)>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    for (entity, transform, has_collider) in rocks.iter() {
+        let distance = transform.translation.distance(player.translation);
+        if !has_collider && distance < config.collision_radius {
+            commands.entity(entity).insert(rock_collider());
+        } else if has_collider && distance > config.collision_radius + COLLIDER_HYSTERESIS {
+            commands
+                .entity(entity)
+                .remove::<(Collider, CollisionGroups)>();
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Throwaway physics objects for checking the terrain's collider: a key throws a
+//! cube from the player that tumbles down the slopes. If the collider didn't
+//! match the mesh the cubes would float above or sink into the ground. The
+//! oldest cube goes away once there are too many.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{Collider, CollisionGroups, Group, RigidBody, Velocity};
+
+use crate::controls::KeyBindings;
+use crate::movement::MovementConfig;
+use crate::physics::PhysicsConfig;
+use crate::PROPS_GROUP;
+
+#[derive(Resource, Debug, Clone)]
+pub struct SandboxConfig {
+    pub max_cubes: usize,
+    // length of a cube's side in world units
+    pub cube_size: f32,
+    // how fast cubes are thrown along the view
+    pub throw_speed: f32,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig {
+            max_cubes: 30,
+            cube_size: 0.5,
+            throw_speed: 6.0,
+        }
+    }
+}
+
+// The cubes that are around, oldest first.
+#[derive(Resource, Default)]
+pub struct SandboxCubes(VecDeque<Entity>);
+
+impl SandboxCubes {
+    // Forgets a cube that was despawned some other way, like falling out of the
+    // world.
+    pub fn remove(&mut self, cube: Entity) {
+        self.0.retain(|&other| other != cube);
+    }
+}
+
+#[derive(Component)]
+pub struct SandboxCube {}
+
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_cubes(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    config: Res<SandboxConfig>,
+    movement: Res<MovementConfig>,
+    physics: Res<PhysicsConfig>,
+    mut cubes: ResMut<SandboxCubes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    camera: Query<&Transform, With<Camera3d>>,
+) {
+    if !keys.just_pressed(bindings.spawn_cube) {
+        return;
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    while cubes.0.len() >= config.max_cubes.max(1) {
+        if let Some(oldest) = cubes
+            .0
+            .pop_front()
+            .and_then(|oldest| commands.get_entity(oldest))
+        {
+            oldest.despawn_recursive();
+        }
+    }
+
+    let forward = camera.forward().as_vec3();
+    let size = config.cube_size;
+    // just outside the player's ball so it doesn't start out stuck in it
+    let position = camera.translation + forward * (movement.player_radius + size);
+    let cube = commands
+        .spawn((
+            PbrBundle {
+                mesh: meshes.add(Cuboid::from_length(size)),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::srgb(0.8, 0.3, 0.2),
+                    ..default()
+                }),
+                transform: Transform::from_translation(position).with_rotation(camera.rotation),
+                ..default()
+            },
+            RigidBody::Dynamic,
+            physics.prop_gravity(),
+            Collider::cuboid(size / 2.0, size / 2.0, size / 2.0),
+            CollisionGroups::new(PROPS_GROUP, Group::ALL),
+            Velocity::linear(forward * config.throw_speed),
+            SandboxCube {},
+        ))
+        .id();
+    cubes.0.push_back(cube);
+}
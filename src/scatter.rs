@@ -0,0 +1,277 @@
+//! Small objects scattered over the terrain. Placement is split into cells and
+//! each cell's random numbers come only from the seed and the cell's position,
+//! so any region can be scattered again on its own and land exactly where it
+//! did before, as long as the terrain underneath hasn't changed. Objects thin out
+//! with distance from the player and are hidden past a cull distance, and lean
+//! partway into the slope they stand on.
+
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::terrain::{TerrainHeights, TerrainSeed};
+use crate::Player;
+
+#[derive(Resource, Debug, Clone)]
+pub struct ScatterConfig {
+    // world units per side of a scatter cell
+    pub cell_size: f32,
+    // each cell gets between 0 and this many objects
+    pub max_per_cell: u32,
+    // fraction of the objects shown near the player, 0.0 to 1.0
+    pub near_density: f32,
+    // distance from the player where objects start thinning out
+    pub far_distance: f32,
+    // distance from the player past which none are shown
+    pub cull_distance: f32,
+    // how far objects lean from straight up towards the terrain normal, 0.0 to 1.0.
+    // Leaning all the way looks odd on cliffs.
+    pub normal_alignment: f32,
+}
+
+impl Default for ScatterConfig {
+    fn default() -> Self {
+        ScatterConfig {
+            cell_size: 25.0,
+            max_per_cell: 3,
+            near_density: 1.0,
+            far_distance: 80.0,
+            cull_distance: 200.0,
+            normal_alignment: 0.4,
+        }
+    }
+}
+
+// Asks for every scatter cell overlapping this world space xz rect to be redone.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RescatterRegion(pub Rect);
+
+#[derive(Component)]
+pub struct Scattered {
+    pub cell: IVec2,
+    // shown while the density where it stands is above this, see `Placement::keep`
+    pub keep: f32,
+}
+
+const OBJECT_HEIGHT: f32 = 8.0;
+
+#[derive(Resource)]
+pub struct ScatterAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for ScatterAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(Cone {
+            radius: 2.0,
+            height: OBJECT_HEIGHT,
+        });
+        let material = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::srgb(0.25, 0.55, 0.2),
+                ..default()
+            });
+        ScatterAssets { mesh, material }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub translation: Vec3,
+    // the object's up, between world up and the terrain normal
+    pub up: Vec3,
+    pub yaw: f32,
+    pub scale: f32,
+    // a random 0.0 to 1.0, objects whose value is under the density at their
+    // distance from the player are shown, so the same ones always thin out first
+    pub keep: f32,
+}
+
+pub fn cell_rng(seed: TerrainSeed, cell: IVec2) -> ChaCha8Rng {
+    // mix the cell coordinates into the seed so neighbouring cells don't
+    // end up with related numbers
+    let x = (cell.x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let z = (cell.y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    ChaCha8Rng::seed_from_u64(seed.0 ^ x ^ z.rotate_left(32))
+}
+
+// Where the objects in a cell go. Only depends on the seed, the cell and the
+// terrain heights.
+pub fn scatter_cell(
+    seed: TerrainSeed,
+    cell: IVec2,
+    config: &ScatterConfig,
+    heights: &TerrainHeights,
+) -> Vec<Placement> {
+    let mut rng = cell_rng(seed, cell);
+    let count = rng.gen_range(0..=config.max_per_cell);
+    let corner = cell.as_vec2() * config.cell_size;
+    (0..count)
+        .filter_map(|_| {
+            // always draw every number so a point off the terrain doesn't shift the rest
+            let offset = Vec2::new(rng.gen(), rng.gen()) * config.cell_size;
+            let yaw = rng.gen_range(0.0..std::f32::consts::TAU);
+            let scale = rng.gen_range(0.6..1.4);
+            let keep = rng.gen();
+            let position = corner + offset;
+            let y = heights.height_at(position.x, position.y)?;
+            let normal = heights.normal_at(position.x, position.y)?;
+            Some(Placement {
+                translation: Vec3::new(position.x, y, position.y),
+                up: Vec3::Y.lerp(normal, config.normal_alignment).normalize(),
+                yaw,
+                scale,
+                keep,
+            })
+        })
+        .collect()
+}
+
+// The cells `cell_size` across overlapping a world space xz rect, limited to the
+// terrain's bounds, see `TerrainHeights::bounds`.
+pub fn cells_in(bounds: Rect, terrain: Rect, cell_size: f32) -> impl Iterator<Item = IVec2> {
+    let bounds = bounds.intersect(terrain);
+    let min = (bounds.min / cell_size).floor().as_ivec2();
+    let max = (bounds.max / cell_size).ceil().as_ivec2();
+    (min.x..max.x).flat_map(move |x| (min.y..max.y).map(move |z| IVec2::new(x, z)))
+}
+
+// Removes the objects in every cell overlapping `bounds` and places them again
+// from the current terrain. Used when the terrain first appears and after edits.
+#[allow(clippy::too_many_arguments)]
+pub fn rescatter_region(
+    commands: &mut Commands,
+    bounds: Rect,
+    seed: TerrainSeed,
+    config: &ScatterConfig,
+    heights: &TerrainHeights,
+    assets: &ScatterAssets,
+    scattered: &Query<(Entity, &Scattered)>,
+) {
+    let cells: Vec<IVec2> = cells_in(bounds, heights.bounds(), config.cell_size).collect();
+    for (entity, object) in scattered.iter() {
+        if cells.contains(&object.cell) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for cell in cells {
+        for placement in scatter_cell(seed, cell, config, heights) {
+            // lean towards the slope, then spin around the leaned up
+            let rotation = Quat::from_rotation_arc(Vec3::Y, placement.up)
+                * Quat::from_rotation_y(placement.yaw);
+            commands.spawn((
+                PbrBundle {
+                    mesh: assets.mesh.clone(),
+                    material: assets.material.clone(),
+                    // the cone's origin is its middle, stand it on the ground
+                    transform: Transform::from_translation(
+                        placement.translation
+                            + placement.up * OBJECT_HEIGHT / 2.0 * placement.scale,
+                    )
+                    .with_rotation(rotation)
+                    .with_scale(Vec3::splat(placement.scale)),
+                    ..default()
+                },
+                Scattered {
+                    cell,
+                    keep: placement.keep,
+                },
+            ));
+        }
+    }
+}
+
+impl ScatterConfig {
+    // Fraction of objects shown at `distance` from the player.
+    pub fn density_at(&self, distance: f32) -> f32 {
+        let falloff = (self.cull_distance - self.far_distance).max(f32::EPSILON);
+        let fade = ((self.cull_distance - distance) / falloff).clamp(0.0, 1.0);
+        self.near_density * fade
+    }
+}
+
+// Hides objects as they get far from the player and shows them again on the way back.
+pub fn update_scatter_density(
+    config: Res<ScatterConfig>,
+    player: Query<&Transform, With<Player>>,
+    mut scattered: Query<(&Scattered, &Transform, &mut Visibility), Without<Player>>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let center = player.translation.xz();
+    for (object, transform, mut visibility) in scattered.iter_mut() {
+        let density = config.density_at(transform.translation.xz().distance(center));
+        let wanted = if object.keep < density {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+}
+
+pub fn handle_rescatter_events(
+    mut commands: Commands,
+    mut events: EventReader<RescatterRegion>,
+    seed: Res<TerrainSeed>,
+    config: Res<ScatterConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    assets: Res<ScatterAssets>,
+    scattered: Query<(Entity, &Scattered)>,
+) {
+    let Some(heights) = heights else {
+        return;
+    };
+    for RescatterRegion(bounds) in events.read() {
+        rescatter_region(
+            &mut commands,
+            *bounds,
+            *seed,
+            &config,
+            &heights,
+            &assets,
+            &scattered,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bumpy_terrain() -> TerrainHeights {
+        let (x_max, y_max) = (50, 50);
+        let heights = (0..x_max * y_max)
+            .map(|i| ((i % 7) as f32 * 0.1).sin())
+            .collect();
+        TerrainHeights {
+            x_max,
+            y_max,
+            extent: Vec2::splat(100.0),
+            heights,
+        }
+    }
+
+    #[test]
+    fn same_seed_scatters_identically() {
+        let config = ScatterConfig::default();
+        let heights = bumpy_terrain();
+        let everything = Rect::new(-10.0, -10.0, 10.0, 10.0);
+        let scatter = |seed| {
+            cells_in(everything, heights.bounds(), config.cell_size)
+                .flat_map(|cell| scatter_cell(TerrainSeed(seed), cell, &config, &heights))
+                .collect::<Vec<_>>()
+        };
+
+        let first = scatter(42);
+        assert!(!first.is_empty());
+        assert_eq!(first, scatter(42));
+        assert_ne!(first, scatter(43));
+    }
+}
@@ -0,0 +1,214 @@
+//! Player settings that persist between runs. They're saved as JSON in the
+//! working directory whenever they change, and anything missing or malformed in
+//! the file falls back to its default.
+
+use bevy::prelude::*;
+use bevy::render::camera::PerspectiveProjection;
+use serde::{Deserialize, Serialize};
+
+use crate::audio::AudioSettings;
+use crate::controls::KeyBindings;
+use crate::movement::{MovementConfig, MovementFeel, PlayerShape};
+use crate::post_process::{PostProcessSettings, BLOOM_RANGE};
+
+const SETTINGS_PATH: &str = "settings.json";
+
+// ranges the options menu steps through, loaded values are clamped to them too
+pub const SENSITIVITY_RANGE: (f32, f32, f32) = (0.1, 5.0, 0.1);
+pub const FOV_RANGE: (f32, f32, f32) = (40.0, 110.0, 5.0);
+pub const VOLUME_RANGE: (f32, f32, f32) = (0.0, 1.0, 0.1);
+pub const MSAA_SAMPLES: [u32; 4] = [1, 2, 4, 8];
+// 0 is no cap
+pub const MAX_FPS_OPTIONS: [u32; 6] = [0, 30, 60, 120, 144, 240];
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // multiplier on how fast the mouse turns the camera
+    pub mouse_sensitivity: f32,
+    // vertical field of view in degrees
+    pub fov: f32,
+    // 1 turns anti-aliasing off
+    pub msaa_samples: u32,
+    // frames per second the game is held to while vsync is off, 0 for no cap
+    pub max_fps: u32,
+    // cuts down on things moving around that don't need to, like particles
+    pub reduce_motion: bool,
+    // whether Q and E roll the camera, it's always level with reduce motion on
+    pub camera_roll: bool,
+    pub movement_feel: MovementFeel,
+    // the player's collider, only read at startup since the player is spawned
+    // with it
+    pub player_shape: PlayerShape,
+    pub post_process: PostProcessSettings,
+    pub audio: AudioSettings,
+    pub key_bindings: KeyBindings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            mouse_sensitivity: 1.0,
+            // Bevy's default projection
+            fov: 45.0,
+            msaa_samples: 4,
+            max_fps: 0,
+            reduce_motion: false,
+            camera_roll: true,
+            movement_feel: MovementFeel::Snappy,
+            player_shape: MovementConfig::default().player_shape,
+            post_process: PostProcessSettings::default(),
+            audio: AudioSettings::default(),
+            key_bindings: KeyBindings::default(),
+        }
+    }
+}
+
+impl Settings {
+    // Reads the settings file, using defaults for anything that can't be read.
+    pub fn load() -> Settings {
+        if cfg!(target_arch = "wasm32") {
+            return Settings::default();
+        }
+        let text = match std::fs::read_to_string(SETTINGS_PATH) {
+            Ok(text) => text,
+            Err(err) => {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    warn!(
+                        "couldn't read {}, using default settings: {}",
+                        SETTINGS_PATH, err
+                    );
+                }
+                return Settings::default();
+            }
+        };
+        match serde_json::from_str::<Settings>(&text) {
+            Ok(settings) => settings.sanitized(),
+            Err(err) => {
+                warn!(
+                    "{} is malformed, using default settings: {}",
+                    SETTINGS_PATH, err
+                );
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let text = match serde_json::to_string_pretty(self) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("couldn't serialize settings: {}", err);
+                return;
+            }
+        };
+        if let Err(err) = std::fs::write(SETTINGS_PATH, text) {
+            warn!("couldn't write {}: {}", SETTINGS_PATH, err);
+        }
+    }
+
+    // Pulls hand edited values back into the ranges the game supports.
+    fn sanitized(mut self) -> Settings {
+        let defaults = Settings::default();
+        self.mouse_sensitivity = clamp_or(
+            self.mouse_sensitivity,
+            SENSITIVITY_RANGE,
+            defaults.mouse_sensitivity,
+        );
+        self.fov = clamp_or(self.fov, FOV_RANGE, defaults.fov);
+        self.post_process.bloom_intensity = clamp_or(
+            self.post_process.bloom_intensity,
+            BLOOM_RANGE,
+            defaults.post_process.bloom_intensity,
+        );
+        for (volume, default) in [
+            (&mut self.audio.master, defaults.audio.master),
+            (&mut self.audio.sfx, defaults.audio.sfx),
+            (&mut self.audio.ambient, defaults.audio.ambient),
+            (&mut self.audio.music, defaults.audio.music),
+        ] {
+            *volume = clamp_or(*volume, VOLUME_RANGE, default);
+        }
+        if !MSAA_SAMPLES.contains(&self.msaa_samples) {
+            self.msaa_samples = defaults.msaa_samples;
+        }
+        if !MAX_FPS_OPTIONS.contains(&self.max_fps) {
+            self.max_fps = defaults.max_fps;
+        }
+        if let PlayerShape::Capsule { height } = self.player_shape {
+            if !height.is_finite() || height <= 0.0 {
+                self.player_shape = defaults.player_shape;
+            }
+        }
+        self
+    }
+
+    // The movement config the player starts out with.
+    pub fn movement_config(&self) -> MovementConfig {
+        MovementConfig {
+            player_shape: self.player_shape,
+            ..default()
+        }
+        .with_feel(self.movement_feel)
+    }
+
+    pub fn roll_allowed(&self) -> bool {
+        self.camera_roll && !self.reduce_motion
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        match self.msaa_samples {
+            2 => Msaa::Sample2,
+            4 => Msaa::Sample4,
+            8 => Msaa::Sample8,
+            _ => Msaa::Off,
+        }
+    }
+}
+
+fn clamp_or(value: f32, (min, max, _step): (f32, f32, f32), default: f32) -> f32 {
+    if value.is_finite() {
+        value.clamp(min, max)
+    } else {
+        default
+    }
+}
+
+// Pushes the settings out to the resources and camera that use them, and saves
+// them whenever they change after startup.
+pub fn apply_settings(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut bindings: ResMut<KeyBindings>,
+    mut audio: ResMut<AudioSettings>,
+    mut post_process: ResMut<PostProcessSettings>,
+    mut movement: ResMut<MovementConfig>,
+    mut camera: Query<&mut Projection, With<Camera3d>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    if !settings.is_added() {
+        settings.save();
+    }
+
+    if *bindings != settings.key_bindings {
+        *bindings = settings.key_bindings.clone();
+    }
+    commands.insert_resource(settings.msaa());
+    if *audio != settings.audio {
+        *audio = settings.audio.clone();
+    }
+    if *post_process != settings.post_process {
+        *post_process = settings.post_process.clone();
+    }
+    *movement = movement.clone().with_feel(settings.movement_feel);
+    for mut projection in camera.iter_mut() {
+        if let Projection::Perspective(PerspectiveProjection { fov, .. }) = &mut *projection {
+            *fov = settings.fov.to_radians();
+        }
+    }
+}
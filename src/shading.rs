@@ -0,0 +1,67 @@
+//! Switching the terrain between smooth shading, where triangles share their
+//! vertices and the normals are averaged across them, and low poly faceted
+//! shading, where every triangle has its own vertices and its own flat normal.
+//! Switching rebuilds the mesh from the same heights, so only the shading
+//! changes. Collisions and everything else that reads the terrain go by the
+//! heights rather than the mesh's vertices, and edits move every copy of a
+//! vertex along with the triangles they're on.
+
+use bevy::prelude::*;
+
+use crate::controls::KeyBindings;
+use crate::curvature::{self, CurvatureConfig};
+use crate::terrain::{self, TerrainConfig, TerrainHeights};
+use crate::wetness::{self, WetnessConfig};
+use crate::Mountain;
+
+#[derive(Resource, Debug, Clone, Default)]
+pub struct TerrainShading {
+    // flat normals per triangle instead of smooth ones per vertex
+    pub faceted: bool,
+}
+
+// The terrain's mesh for its heights with the current shading, along with the
+// per vertex attributes the terrain shader reads.
+pub fn shaded_terrain_mesh(
+    config: &TerrainConfig,
+    heights: &TerrainHeights,
+    (wetness, curvature): (&WetnessConfig, &CurvatureConfig),
+    shading: &TerrainShading,
+) -> Mesh {
+    let mut mesh = terrain::terrain_mesh(config, heights);
+    wetness::write_wetness(&mut mesh, wetness);
+    curvature::write_curvature(&mut mesh, heights, curvature);
+    if shading.faceted {
+        terrain::facet_mesh(&mut mesh);
+    }
+    mesh
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn toggle_faceted_shading(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut shading: ResMut<TerrainShading>,
+    config: Res<TerrainConfig>,
+    heights: Option<Res<TerrainHeights>>,
+    (wetness, curvature): (Res<WetnessConfig>, Res<CurvatureConfig>),
+    mut meshes: ResMut<Assets<Mesh>>,
+    mountain: Query<&Handle<Mesh>, With<Mountain>>,
+) {
+    if !keys.just_pressed(bindings.toggle_faceted) {
+        return;
+    }
+    shading.faceted = !shading.faceted;
+    let Some(heights) = heights else {
+        return;
+    };
+    for mesh in mountain.iter() {
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            *mesh = shaded_terrain_mesh(&config, &heights, (&wetness, &curvature), &shading);
+        }
+    }
+    info!(
+        "terrain shading is now {}",
+        if shading.faceted { "faceted" } else { "smooth" }
+    );
+}
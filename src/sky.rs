@@ -0,0 +1,484 @@
+//! The sky and the day-night cycle. The sun, moon and stars all hang off one
+//! rotation driven by `DayNight::time_of_day`, so they always stay in sync: the
+//! moon is opposite the sun, and the stars turn with them and fade in as the sun
+//! goes down. For setting up shots the sun can be locked to shine the way the
+//! camera is looking.
+//!
+//! Behind them all is a dome with a gradient from the horizon to the zenith and
+//! a sun disc, colored by `SkyGradient` for the time of day, so there's a sky
+//! without any art. Swapping it for a cubemap means putting a `Skybox` on the
+//! camera and not spawning the dome.
+
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey, NotShadowCaster};
+use bevy::prelude::*;
+use bevy::render::{
+    mesh::MeshVertexBufferLayoutRef,
+    render_asset::RenderAssetUsages,
+    render_resource::{
+        AsBindGroup, Extent3d, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+        TextureDimension, TextureFormat,
+    },
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f32::consts::TAU;
+
+use crate::controls::KeyBindings;
+use crate::freeze::FreezeTime;
+
+#[derive(Resource, Debug, Clone)]
+pub struct DayNight {
+    // 0.0 is midnight, 0.25 sunrise, 0.5 noon, 0.75 sunset
+    pub time_of_day: f32,
+    // real seconds for a full day
+    pub day_length: f32,
+    pub paused: bool,
+    // set by the sun lock key, the sun stays this way (towards it) until released
+    pub sun_lock: Option<Vec3>,
+}
+
+impl Default for DayNight {
+    fn default() -> Self {
+        DayNight {
+            time_of_day: 0.3,
+            day_length: 240.0,
+            paused: false,
+            sun_lock: None,
+        }
+    }
+}
+
+// how far the sun's path is tilted away from passing straight overhead, radians
+const SUN_TILT: f32 = 0.4;
+// sky objects are drawn this far from the camera, inside the camera's far plane
+const STAR_DISTANCE: f32 = 900.0;
+const MOON_DISTANCE: f32 = 800.0;
+// behind the stars, which are added on top of it
+const DOME_DISTANCE: f32 = 950.0;
+const MOON_RADIUS: f32 = 30.0;
+const SUN_ILLUMINANCE: f32 = 3000.0;
+const MOON_ILLUMINANCE: f32 = 60.0;
+
+const MOON_COLOR: Color = Color::srgb(0.85, 0.88, 0.95);
+
+impl DayNight {
+    // Orientation of the whole sky, the sun is along its +x.
+    pub fn sky_rotation(&self) -> Quat {
+        // at 0.25 the sun is on the horizon and rising
+        let angle = (self.time_of_day - 0.25) * TAU;
+        Quat::from_rotation_x(SUN_TILT) * Quat::from_rotation_z(angle)
+    }
+
+    // Unit vector pointing towards the sun.
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_lock
+            .unwrap_or_else(|| self.sky_rotation() * Vec3::X)
+    }
+
+    // 0.0 in daylight, 1.0 at night, smooth while the sun crosses the horizon.
+    pub fn night_factor(&self) -> f32 {
+        let elevation = self.sun_direction().y;
+        let t = ((0.15 - elevation) / 0.3).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    // How close the sun is to the horizon, 1.0 right on it.
+    fn dusk_factor(&self) -> f32 {
+        (1.0 - self.sun_direction().y.abs() / 0.25).max(0.0)
+    }
+}
+
+// Colors of the sky dome. Each time of day has a color at the horizon and one
+// straight up, and the sky blends between them as the sun moves.
+#[derive(Resource, Debug, Clone)]
+pub struct SkyGradient {
+    pub day_horizon: Color,
+    pub day_zenith: Color,
+    // mixed in while the sun is near the horizon
+    pub dusk_horizon: Color,
+    pub dusk_zenith: Color,
+    pub night_horizon: Color,
+    pub night_zenith: Color,
+    pub sun_color: Color,
+    // multiplier on the sun disc's color, above 1.0 it can bloom
+    pub sun_brightness: f32,
+    // angular radius of the sun disc in degrees, 0.0 for no disc
+    pub sun_radius: f32,
+}
+
+impl Default for SkyGradient {
+    fn default() -> Self {
+        SkyGradient {
+            day_horizon: Color::srgb(0.7, 0.8, 0.95),
+            day_zenith: Color::srgb(0.3, 0.5, 0.85),
+            dusk_horizon: Color::srgb(0.95, 0.5, 0.3),
+            dusk_zenith: Color::srgb(0.4, 0.35, 0.55),
+            night_horizon: Color::srgb(0.02, 0.02, 0.05),
+            night_zenith: Color::srgb(0.005, 0.005, 0.015),
+            sun_color: Color::srgb(1.0, 0.95, 0.8),
+            sun_brightness: 4.0,
+            // a bit bigger than the real one, which looks tiny on screen
+            sun_radius: 1.5,
+        }
+    }
+}
+
+impl SkyGradient {
+    // The (horizon, zenith) colors for the time of day.
+    pub fn colors(&self, day_night: &DayNight) -> (Color, Color) {
+        let night = day_night.night_factor();
+        let dusk = day_night.dusk_factor() * 0.6;
+        let color = |day: Color, dusk_color: Color, night_color: Color| {
+            day.mix(&night_color, night).mix(&dusk_color, dusk)
+        };
+        (
+            color(self.day_horizon, self.dusk_horizon, self.night_horizon),
+            color(self.day_zenith, self.dusk_zenith, self.night_zenith),
+        )
+    }
+
+    fn uniform(&self, day_night: &DayNight) -> SkyUniform {
+        let linear = |color: Color| {
+            let color = color.to_linear();
+            Vec4::new(color.red, color.green, color.blue, color.alpha)
+        };
+        let (horizon, zenith) = self.colors(day_night);
+        let sun = linear(self.sun_color) * self.sun_brightness;
+        // the disc sets with the sun
+        let visible = if self.sun_radius > 0.0 {
+            1.0 - day_night.night_factor()
+        } else {
+            0.0
+        };
+        SkyUniform {
+            horizon: linear(horizon),
+            zenith: linear(zenith),
+            sun_color: sun.truncate().extend(visible),
+            sun_direction: day_night
+                .sun_direction()
+                .extend(self.sun_radius.to_radians().cos()),
+        }
+    }
+}
+
+pub use uniform::SkyUniform;
+
+// ShaderType's generated size checks count as dead code in test builds, see
+// height_gradient.rs
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    // see sky.wgsl
+    #[derive(ShaderType, Debug, Clone, Copy, PartialEq)]
+    pub struct SkyUniform {
+        pub horizon: Vec4,
+        pub zenith: Vec4,
+        pub sun_color: Vec4,
+        pub sun_direction: Vec4,
+    }
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+pub struct SkyMaterial {
+    #[uniform(0)]
+    sky: SkyUniform,
+}
+
+const SKY_SHADER: &str = "sky.wgsl";
+
+impl Material for SkyMaterial {
+    fn fragment_shader() -> ShaderRef {
+        SKY_SHADER.into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // the dome is seen from the inside
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+// The things in the sky that move with the time of day.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkyPart {
+    Sun,
+    // the faint blue light coming from the moon
+    MoonLight,
+    Moon,
+    Stars,
+    Dome,
+}
+
+// A random star field laid out for a uv sphere.
+fn star_texture() -> Image {
+    let (width, height) = (1024, 512);
+    let mut data = vec![0u8; width * height * 4];
+    // the same sky every run
+    let mut rng = ChaCha8Rng::seed_from_u64(0x57a25);
+    for _ in 0..1500 {
+        let x = rng.gen_range(0..width);
+        let y = rng.gen_range(0..height);
+        let brightness: f32 = rng.gen::<f32>().powi(3);
+        // a slight blue or yellow tint
+        let tint = rng.gen_range(-0.15..0.15);
+        let texel = &mut data[(y * width + x) * 4..][..4];
+        texel[0] = ((brightness * (1.0 + tint)).min(1.0) * 255.0) as u8;
+        texel[1] = (brightness * 255.0) as u8;
+        texel[2] = ((brightness * (1.0 - tint)).min(1.0) * 255.0) as u8;
+        texel[3] = 255;
+    }
+    Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+pub fn spawn_sky(
+    mut commands: Commands,
+    gradient: Res<SkyGradient>,
+    day_night: Res<DayNight>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut sky_materials: ResMut<Assets<SkyMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    commands.spawn((
+        MaterialMeshBundle {
+            mesh: meshes.add(Sphere::new(DOME_DISTANCE).mesh().uv(64, 32)),
+            material: sky_materials.add(SkyMaterial {
+                sky: gradient.uniform(&day_night),
+            }),
+            ..default()
+        },
+        NotShadowCaster,
+        SkyPart::Dome,
+    ));
+
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                illuminance: SUN_ILLUMINANCE,
+                ..default()
+            },
+            ..default()
+        },
+        SkyPart::Sun,
+    ));
+    commands.spawn((
+        DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                color: Color::srgb(0.6, 0.7, 1.0),
+                illuminance: 0.0,
+                ..default()
+            },
+            ..default()
+        },
+        SkyPart::MoonLight,
+    ));
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Sphere::new(STAR_DISTANCE).mesh().uv(64, 32)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::BLACK,
+                base_color_texture: Some(images.add(star_texture())),
+                unlit: true,
+                fog_enabled: false,
+                // added on top of the sky color, black means invisible
+                alpha_mode: AlphaMode::Add,
+                // seen from the inside
+                cull_mode: None,
+                ..default()
+            }),
+            ..default()
+        },
+        SkyPart::Stars,
+    ));
+
+    commands.spawn((
+        PbrBundle {
+            mesh: meshes.add(Circle::new(MOON_RADIUS)),
+            material: materials.add(StandardMaterial {
+                base_color: MOON_COLOR,
+                unlit: true,
+                fog_enabled: false,
+                alpha_mode: AlphaMode::Blend,
+                cull_mode: None,
+                ..default()
+            }),
+            ..default()
+        },
+        SkyPart::Moon,
+    ));
+}
+
+pub fn advance_day_night(
+    time: Res<Time>,
+    freeze: Res<FreezeTime>,
+    mut day_night: ResMut<DayNight>,
+) {
+    if day_night.paused || freeze.0 {
+        return;
+    }
+    day_night.time_of_day =
+        (day_night.time_of_day + time.delta_seconds() / day_night.day_length).fract();
+}
+
+// Moves the sun, moon and stars to match the time of day, and keeps the sky
+// centered on the camera so it always looks infinitely far away.
+#[allow(clippy::type_complexity)]
+pub fn update_sky(
+    day_night: Res<DayNight>,
+    gradient: Res<SkyGradient>,
+    mut clear_color: ResMut<ClearColor>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut sky_materials: ResMut<Assets<SkyMaterial>>,
+    camera: Query<&Transform, With<Camera3d>>,
+    mut parts: Query<
+        (
+            &SkyPart,
+            &mut Transform,
+            Option<&mut DirectionalLight>,
+            Option<&Handle<StandardMaterial>>,
+            Option<&Handle<SkyMaterial>>,
+        ),
+        Without<Camera3d>,
+    >,
+) {
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let sun_direction = day_night.sun_direction();
+    let night = day_night.night_factor();
+
+    // shows wherever the dome doesn't cover
+    let (horizon, _) = gradient.colors(&day_night);
+    if clear_color.0 != horizon {
+        clear_color.0 = horizon;
+    }
+    let sky = gradient.uniform(&day_night);
+
+    for (part, mut transform, light, material, sky_material) in parts.iter_mut() {
+        let (new_transform, illuminance, color) = match part {
+            SkyPart::Sun => (
+                Transform::default().looking_to(-sun_direction, Vec3::Y),
+                SUN_ILLUMINANCE * (1.0 - night),
+                None,
+            ),
+            SkyPart::MoonLight => (
+                Transform::default().looking_to(sun_direction, Vec3::Y),
+                MOON_ILLUMINANCE * night,
+                None,
+            ),
+            SkyPart::Moon => (
+                Transform::from_translation(camera.translation - sun_direction * MOON_DISTANCE)
+                    .looking_at(camera.translation, Vec3::Y),
+                0.0,
+                // still faintly visible during the day
+                Some(MOON_COLOR.with_alpha(0.2 + 0.8 * night)),
+            ),
+            SkyPart::Stars => (
+                Transform::from_translation(camera.translation)
+                    .with_rotation(day_night.sky_rotation()),
+                0.0,
+                Some(Color::BLACK.mix(&Color::WHITE, night)),
+            ),
+            SkyPart::Dome => (Transform::from_translation(camera.translation), 0.0, None),
+        };
+        *transform = new_transform;
+        if let Some(mut light) = light {
+            light.illuminance = illuminance;
+        }
+        // only touch the material when it changes, every change re-uploads it
+        if let (Some(material), Some(color)) = (material, color) {
+            if materials
+                .get(material)
+                .is_some_and(|m| m.base_color != color)
+            {
+                materials.get_mut(material).unwrap().base_color = color;
+            }
+        }
+        if let Some(material) = sky_material {
+            if sky_materials.get(material).is_some_and(|m| m.sky != sky) {
+                sky_materials.get_mut(material).unwrap().sky = sky;
+            }
+        }
+    }
+}
+
+// Shows where the locked sun is.
+#[derive(Component)]
+pub struct SunLockText {}
+
+// Degrees clockwise from -z, the way the camera starts out facing, and degrees
+// above the horizon.
+fn azimuth_elevation(direction: Vec3) -> (f32, f32) {
+    let azimuth = direction
+        .x
+        .atan2(-direction.z)
+        .to_degrees()
+        .rem_euclid(360.0);
+    let elevation = direction.y.clamp(-1.0, 1.0).asin().to_degrees();
+    (azimuth, elevation)
+}
+
+// Points the sun's light along the camera's view, or gives the sun back to the
+// day-night cycle if it's already locked.
+pub fn toggle_sun_lock(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut day_night: ResMut<DayNight>,
+    camera: Query<&Transform, With<Camera3d>>,
+    text: Query<Entity, With<SunLockText>>,
+) {
+    if !keys.just_pressed(bindings.sun_lock) {
+        return;
+    }
+    for entity in text.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if day_night.sun_lock.take().is_some() {
+        return;
+    }
+    let Ok(camera) = camera.get_single() else {
+        return;
+    };
+    let sun_direction = -camera.forward().as_vec3();
+    day_night.sun_lock = Some(sun_direction);
+    let (azimuth, elevation) = azimuth_elevation(sun_direction);
+    info!(
+        "sun locked at azimuth {:.1}, elevation {:.1}",
+        azimuth, elevation
+    );
+    commands.spawn((
+        TextBundle::from_section(
+            format!(
+                "Sun locked\nazimuth {:.1}\nelevation {:.1}",
+                azimuth, elevation
+            ),
+            TextStyle::default(),
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(12.0),
+            right: Val::Px(12.0),
+            ..default()
+        }),
+        SunLockText {},
+    ));
+}
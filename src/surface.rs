@@ -0,0 +1,124 @@
+//! What the ground is made of. The terrain has no painted layers, so the surface
+//! under a point is worked out the same way the height gradient colors it: from
+//! how high it is between the lowest and highest point of the terrain, with steep
+//! slopes always counting as rock. Each surface has its own footstep sound and
+//! color of dust that steps and landings kick up.
+
+use bevy::prelude::*;
+
+use crate::audio::FootstepSound;
+use crate::height_gradient::height_range;
+use crate::terrain::TerrainHeights;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Surface {
+    Sand,
+    Grass,
+    Rock,
+    Snow,
+}
+
+// What stepping on a surface sounds and looks like.
+#[derive(Debug, Clone)]
+pub struct SurfaceEffect {
+    pub footstep: FootstepSound,
+    // volume of the footstep on the sfx channel, 0.0 to 1.0
+    pub volume: f32,
+    // color of the dust kicked up by steps and landings
+    pub dust: Color,
+    // particles kicked up by every step
+    pub footstep_particles: u32,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct SurfaceConfig {
+    // normalized heights, 0.0 is the lowest point of the terrain and 1.0 the highest
+    pub sand_below: f32,
+    pub rock_above: f32,
+    pub snow_above: f32,
+    // anything steeper than this, in degrees, is rock
+    pub rock_slope: f32,
+    pub sand: SurfaceEffect,
+    pub grass: SurfaceEffect,
+    pub rock: SurfaceEffect,
+    pub snow: SurfaceEffect,
+}
+
+impl Default for SurfaceConfig {
+    fn default() -> Self {
+        SurfaceConfig {
+            sand_below: 0.05,
+            rock_above: 0.7,
+            snow_above: 0.88,
+            rock_slope: 40.0,
+            sand: SurfaceEffect {
+                footstep: FootstepSound {
+                    cutoff: 0.35,
+                    decay: 35.0,
+                },
+                volume: 0.6,
+                dust: Color::srgb(0.8, 0.7, 0.5),
+                footstep_particles: 6,
+            },
+            grass: SurfaceEffect {
+                footstep: FootstepSound {
+                    cutoff: 0.15,
+                    decay: 50.0,
+                },
+                volume: 0.8,
+                dust: Color::srgb(0.45, 0.4, 0.3),
+                footstep_particles: 0,
+            },
+            rock: SurfaceEffect {
+                footstep: FootstepSound {
+                    cutoff: 0.5,
+                    decay: 90.0,
+                },
+                volume: 0.7,
+                dust: Color::srgb(0.55, 0.53, 0.5),
+                footstep_particles: 3,
+            },
+            snow: SurfaceEffect {
+                footstep: FootstepSound {
+                    cutoff: 0.06,
+                    decay: 25.0,
+                },
+                volume: 0.9,
+                dust: Color::srgb(0.95, 0.95, 1.0),
+                footstep_particles: 8,
+            },
+        }
+    }
+}
+
+impl SurfaceConfig {
+    pub fn effect(&self, surface: Surface) -> &SurfaceEffect {
+        match surface {
+            Surface::Sand => &self.sand,
+            Surface::Grass => &self.grass,
+            Surface::Rock => &self.rock,
+            Surface::Snow => &self.snow,
+        }
+    }
+
+    // The surface at world (x, z), None if it isn't over the terrain.
+    pub fn surface_at(&self, heights: &TerrainHeights, x: f32, z: f32) -> Option<Surface> {
+        let height = heights.height_at(x, z)?;
+        let normal = heights.normal_at(x, z)?;
+        if normal.angle_between(Vec3::Y).to_degrees() > self.rock_slope {
+            return Some(Surface::Rock);
+        }
+
+        let (min, max) = height_range(heights);
+        let normalized = (height - min) / (max - min).max(f32::EPSILON);
+        Some(if normalized >= self.snow_above {
+            Surface::Snow
+        } else if normalized >= self.rock_above {
+            Surface::Rock
+        } else if normalized < self.sand_below {
+            Surface::Sand
+        } else {
+            Surface::Grass
+        })
+    }
+}
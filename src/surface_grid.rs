@@ -0,0 +1,91 @@
+//! Grid lines drawn by the terrain shader, for a blueprint look or lining things
+//! up. Unlike the gizmo grid in debug_draw.rs they lie exactly on the surface,
+//! cover the whole terrain and cost next to nothing. They're anti-aliased with
+//! screen space derivatives, so they stay `line_width` pixels wide at any distance.
+
+use bevy::prelude::*;
+
+use crate::controls::KeyBindings;
+use crate::{Mountain, MountainMaterial};
+
+#[derive(Resource, Debug, Clone)]
+pub struct SurfaceGridConfig {
+    pub enabled: bool,
+    // world units between lines, along x and z
+    pub spacing: f32,
+    // in pixels on screen
+    pub line_width: f32,
+    // the alpha is how strongly the lines cover the ground
+    pub color: Color,
+}
+
+impl Default for SurfaceGridConfig {
+    fn default() -> Self {
+        SurfaceGridConfig {
+            enabled: false,
+            spacing: 10.0,
+            line_width: 1.5,
+            color: Color::srgba(0.2, 0.5, 1.0, 0.8),
+        }
+    }
+}
+
+pub use uniform::SurfaceGridUniform;
+
+// ShaderType's generated size checks count as dead code in test builds, see
+// height_gradient.rs
+#[allow(dead_code)]
+mod uniform {
+    use bevy::prelude::*;
+    use bevy::render::render_resource::ShaderType;
+
+    // The config as the shader sees it.
+    #[derive(ShaderType, Debug, Clone, Copy, Default, PartialEq)]
+    pub struct SurfaceGridUniform {
+        // linear rgb, and the alpha is 0.0 while the grid is off
+        pub color: Vec4,
+        pub spacing: f32,
+        pub line_width: f32,
+    }
+}
+
+impl SurfaceGridConfig {
+    pub fn uniform(&self) -> SurfaceGridUniform {
+        let mut color = self.color.to_linear().to_vec4();
+        if !self.enabled {
+            color.w = 0.0;
+        }
+        SurfaceGridUniform {
+            color,
+            spacing: self.spacing.max(f32::EPSILON),
+            line_width: self.line_width.max(0.0),
+        }
+    }
+}
+
+pub fn toggle_surface_grid(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut config: ResMut<SurfaceGridConfig>,
+) {
+    if keys.just_pressed(bindings.toggle_surface_grid) {
+        config.enabled = !config.enabled;
+    }
+}
+
+// Passes config changes on to the terrain.
+pub fn apply_surface_grid(
+    config: Res<SurfaceGridConfig>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<&Handle<MountainMaterial>, With<Mountain>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let uniform = config.uniform();
+    for handle in mountain.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.surface_grid = uniform;
+        }
+    }
+}
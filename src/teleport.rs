@@ -0,0 +1,134 @@
+//! A debug prompt for jumping to exact coordinates. The teleport key opens it,
+//! typing "x, z" and pressing enter moves the player there, standing on the
+//! ground. Together with the seed this makes it easy to get back to a spot from a
+//! bug report. Escape, or enter with nothing typed, closes it.
+
+use bevy::input::keyboard::{Key, KeyboardInput};
+use bevy::prelude::*;
+
+use crate::controls::KeyBindings;
+use crate::movement::{MovementConfig, PlayerVelocity};
+use crate::terrain::TerrainHeights;
+use crate::Player;
+
+// What's been typed so far, None while the prompt is closed.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct TeleportPrompt(pub Option<String>);
+
+#[derive(Component)]
+pub struct TeleportText {}
+
+const PROMPT: &str = "Teleport to x, z: ";
+
+// "x, z" or "x z" into a point.
+fn parse_coordinates(text: &str) -> Option<Vec2> {
+    let mut numbers = text
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f32>().ok().filter(|n| n.is_finite()));
+    match (numbers.next(), numbers.next(), numbers.next()) {
+        (Some(Some(x)), Some(Some(z)), None) => Some(Vec2::new(x, z)),
+        _ => None,
+    }
+}
+
+// Run condition for things the prompt's keys shouldn't also trigger.
+pub fn prompt_closed(prompt: Res<TeleportPrompt>) -> bool {
+    prompt.0.is_none()
+}
+
+pub fn open_teleport_prompt(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut prompt: ResMut<TeleportPrompt>,
+) {
+    if prompt.0.is_some() || !keys.just_pressed(bindings.teleport) {
+        return;
+    }
+    prompt.0 = Some(String::new());
+    commands.spawn((
+        TextBundle::from_section(PROMPT, TextStyle::default()).with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(12.0),
+            left: Val::Px(12.0),
+            ..default()
+        }),
+        TeleportText {},
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn teleport_prompt(
+    mut commands: Commands,
+    mut prompt: ResMut<TeleportPrompt>,
+    mut typed: EventReader<KeyboardInput>,
+    movement: Res<MovementConfig>,
+    heights: Res<TerrainHeights>,
+    mut player: Query<(&mut Transform, &mut PlayerVelocity), With<Player>>,
+    mut text: Query<(Entity, &mut Text), With<TeleportText>>,
+) {
+    let Some(input) = &mut prompt.0 else {
+        typed.clear();
+        return;
+    };
+    let mut close = false;
+    let mut submitted = None;
+    for event in typed.read().filter(|event| event.state.is_pressed()) {
+        match &event.logical_key {
+            Key::Character(c) => input.extend(
+                c.chars()
+                    .filter(|c| c.is_ascii_digit() || matches!(c, '-' | '.' | ',' | ' ')),
+            ),
+            Key::Space => input.push(' '),
+            Key::Backspace => {
+                input.pop();
+            }
+            Key::Escape => close = true,
+            Key::Enter => {
+                close = true;
+                submitted = Some(input.clone());
+            }
+            _ => {}
+        }
+    }
+
+    for (entity, mut text) in text.iter_mut() {
+        if close {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            text.sections[0].value = format!("{}{}", PROMPT, input);
+        }
+    }
+    if close {
+        prompt.0 = None;
+    }
+
+    let Some(submitted) = submitted.filter(|text| !text.trim().is_empty()) else {
+        return;
+    };
+    let Some(target) = parse_coordinates(&submitted) else {
+        warn!("can't teleport to \"{}\", expected \"x, z\"", submitted);
+        return;
+    };
+    let terrain = heights.bounds();
+    let clamped = target.clamp(terrain.min, terrain.max);
+    if clamped != target {
+        warn!(
+            "{} is off the terrain, which spans {} to {}, teleporting to {} instead",
+            target, terrain.min, terrain.max, clamped
+        );
+    }
+    let Some(height) = heights.height_at(clamped.x, clamped.y) else {
+        return;
+    };
+    for (mut transform, mut velocity) in player.iter_mut() {
+        transform.translation =
+            Vec3::new(clamped.x, height + movement.player_half_height(), clamped.y);
+        velocity.0 = Vec3::ZERO;
+    }
+    info!(
+        "teleported to x {:.2}, z {:.2}, ground height {:.2}",
+        clamped.x, clamped.y, height
+    );
+}
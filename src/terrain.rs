@@ -0,0 +1,1486 @@
+//! Terrain generation: random points smoothly interpolated into a height grid,
+//! turned into a mesh and a matching collider.
+
+use bevy::prelude::*;
+use bevy::render::{
+    mesh::{Indices, VertexAttributeValues},
+    render_asset::RenderAssetUsages,
+    render_resource::PrimitiveTopology,
+};
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier3d::prelude::Collider;
+use bevy_rapier3d::rapier::na::DMatrix;
+use bevy_rapier3d::rapier::parry::shape::{HeightField, HeightFieldCellStatus, SharedShape};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::terrain_cache;
+use crate::{Player, TERRAIN_BASE_HEIGHT, TERRAIN_SIZE};
+
+// Which shape the terrain collides with, picked in the terrain preset and
+// switched with the trimesh key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColliderKind {
+    // cheap, but can only represent one height per xz position
+    Heightfield,
+    // built from the mesh triangles, so it matches any geometry the mesh has
+    Trimesh,
+}
+
+// Everything random about the world is derived from this, so the same seed
+// always gives the same terrain and the same scattered objects. The numbers come
+// from ChaCha8Rng, whose output is pinned down across rand releases and
+// platforms, unlike StdRng's, so a seed means the same world everywhere.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerrainSeed(pub u64);
+
+// Where the seed comes from, picked in the terrain preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SeedMode {
+    Fixed(u64),
+    #[default]
+    Random,
+    // the same for everyone on the same day, from the UTC date
+    Daily,
+}
+
+// How the seed is shown to the player, like "seed 1234".
+#[derive(Resource, Debug, Clone)]
+pub struct SeedLabel(pub String);
+
+// environment variable that picks the seed when the preset doesn't
+const SEED_VARIABLE: &str = "SHADER_GAME_SEED";
+
+impl SeedMode {
+    // The preset's seed mode if it picks one, otherwise a fixed seed from
+    // SHADER_GAME_SEED if that's set to a number, otherwise random. Also says
+    // where the seed came from, to be logged once logging is up.
+    pub fn with_env_override(self) -> (SeedMode, String) {
+        if self != SeedMode::Random {
+            return (self, "the seed is from the terrain preset".to_string());
+        }
+        match std::env::var(SEED_VARIABLE) {
+            Ok(value) => match value.trim().parse::<u64>() {
+                Ok(seed) => (
+                    SeedMode::Fixed(seed),
+                    format!("the seed is from {}", SEED_VARIABLE),
+                ),
+                Err(_) => (
+                    SeedMode::Random,
+                    format!(
+                        "{} is \"{}\", which isn't a seed, the seed is random",
+                        SEED_VARIABLE, value
+                    ),
+                ),
+            },
+            Err(_) => (SeedMode::Random, "the seed is random".to_string()),
+        }
+    }
+
+    pub fn resolve(self) -> (TerrainSeed, SeedLabel) {
+        match self {
+            SeedMode::Fixed(seed) => (TerrainSeed(seed), SeedLabel(format!("seed {}", seed))),
+            SeedMode::Random => {
+                let seed = rand::random();
+                (TerrainSeed(seed), SeedLabel(format!("seed {}", seed)))
+            }
+            SeedMode::Daily => {
+                let since_epoch = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let (year, month, day) = utc_date(since_epoch.as_secs() / 86400);
+                let seed = daily_seed(year, month, day);
+                let label = format!("daily seed {} for {}-{:02}-{:02}", seed, year, month, day);
+                (TerrainSeed(seed), SeedLabel(label))
+            }
+        }
+    }
+}
+
+// Year, month and day of a day counted from 1970-01-01, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn utc_date(days_since_epoch: u64) -> (u64, u64, u64) {
+    let days = days_since_epoch + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+    (year, month, day)
+}
+
+// Scrambles the date into a seed with splitmix64, which unlike std's hashers is
+// guaranteed to give the same answer on every platform and Rust version.
+pub fn daily_seed(year: u64, month: u64, day: u64) -> u64 {
+    let mut z = (year * 10_000 + month * 100 + day).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainConfig {
+    // number of vertices along x and z
+    pub x_max: usize,
+    pub y_max: usize,
+    // world units the terrain spans along x and z, it's centered on the origin.
+    // The vertices are spread evenly over it, so a long narrow terrain wants
+    // proportionally more vertices along its long side.
+    pub extent: Vec2,
+    // how many vertices there are between random points
+    pub interpolate_step: usize,
+    // world units per unit of generated height, the raw heights are between 0 and
+    // the sum of the layers' amplitudes
+    pub height_scale: f32,
+    // noise summed to make the heights, see `NoiseLayer`
+    pub layers: Vec<NoiseLayer>,
+    // sinks the edges of the terrain to make an island, see `IslandMask`
+    pub island: IslandMask,
+    // most vertices the terrain can have, generation time grows with the vertex
+    // count. Bigger terrains are made coarser to fit, see `fit_budget`. None for
+    // no limit.
+    pub max_vertices: Option<usize>,
+    pub collider: ColliderKind,
+    // what happens at the edges of the terrain, see `BoundaryMode`
+    pub boundary: BoundaryMode,
+    // where generated heights are cached between runs, see terrain_cache.rs.
+    // None turns the cache off.
+    pub cache_dir: Option<String>,
+    // how many times each vertex normal is averaged with its neighbors after
+    // they're computed, evens out the lighting on rough terrain. Independent of
+    // the heights, so the mesh shape and collider stay the same.
+    pub normal_smoothing: u32,
+    // world space height below which things have fallen out of the world, see
+    // kill_plane.rs. None puts it a safe distance under the lowest the terrain can go.
+    pub kill_plane_y: Option<f32>,
+}
+
+// What stops the player at the edges of the terrain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    // nothing, whatever walks off the edge falls forever. There's no kill plane
+    // either, so holes in the collider aren't caught.
+    None,
+    // invisible walls around the terrain keep the player in
+    Walls,
+    // opposite edges of the terrain match and the player is teleported across
+    // when they walk off one, so the world feels endless. Seamless only when
+    // `x_max - 1` and `y_max - 1` are multiples of `interpolate_step`.
+    Wrap,
+    // walking off the edge falls to the kill plane and respawns, see kill_plane.rs
+    KillPlane,
+}
+
+// Multiplies the heights by a falloff from the center of the terrain, so they
+// go down to nothing towards the edges and the terrain ends up an island in
+// whatever water surrounds it. Smooth all the way, there's no ring where the
+// falloff starts or ends.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IslandMask {
+    pub enabled: bool,
+    // world units from the center the heights are left alone within
+    pub radius: f32,
+    // world units past `radius` the heights take to sink to nothing
+    pub falloff: f32,
+}
+
+impl Default for IslandMask {
+    fn default() -> Self {
+        IslandMask {
+            enabled: false,
+            // sunk by the middle of the default terrain's edges
+            radius: 80.0,
+            falloff: 110.0,
+        }
+    }
+}
+
+impl IslandMask {
+    // What heights at a point on the xz plane are multiplied by, 0.0 to 1.0.
+    pub fn factor(&self, position: Vec2) -> f32 {
+        if !self.enabled {
+            return 1.0;
+        }
+        let t =
+            ((position.length() - self.radius) / self.falloff.max(f32::EPSILON)).clamp(0.0, 1.0);
+        1.0 - t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// One layer of interpolated random points. A layer with a `frequency_mult` of n
+// has random points every `interpolate_step / n` vertices, so it reads n times as
+// far into the random points as a layer of 1 does. The random points only go so
+// far, which caps how big `frequency_mult` can be for a terrain's size, see
+// `TerrainConfig::validate`. Each layer reads the same random points, just
+// stretched differently.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NoiseLayer {
+    pub frequency_mult: usize,
+    // generated height of this layer is between 0 and this
+    pub amplitude: f32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        TerrainConfig {
+            // 200 quads across, a multiple of interpolate_step so wrapping works
+            x_max: 201,
+            y_max: 201,
+            extent: Vec2::splat(TERRAIN_SIZE),
+            interpolate_step: 20,
+            // keeps the same proportions as the terrain's width, peaks up to about 150m
+            height_scale: 100.0,
+            // the base shape, plus detail at twice the frequency and half the height
+            layers: vec![
+                NoiseLayer {
+                    frequency_mult: 1,
+                    amplitude: 1.0,
+                },
+                NoiseLayer {
+                    frequency_mult: 2,
+                    amplitude: 0.5,
+                },
+            ],
+            island: IslandMask::default(),
+            // about 1000 by 1000, a few seconds on a slow machine
+            max_vertices: Some(1_000_000),
+            collider: ColliderKind::Heightfield,
+            boundary: BoundaryMode::KillPlane,
+            cache_dir: Some("terrain_cache".to_string()),
+            normal_smoothing: 0,
+            kill_plane_y: None,
+        }
+    }
+}
+
+impl TerrainConfig {
+    pub fn wraps(&self) -> bool {
+        self.boundary == BoundaryMode::Wrap
+    }
+
+    // position of vertex (xi, zi) on the xz plane, in the mountain's local space,
+    // the same as `TerrainHeights::vertex_xz`
+    pub fn vertex_xz(&self, xi: usize, zi: usize) -> Vec2 {
+        let fraction = Vec2::new(
+            xi as f32 / (self.x_max - 1) as f32,
+            zi as f32 / (self.y_max - 1) as f32,
+        );
+        fraction * self.extent - self.extent / 2.0
+    }
+
+    // The highest generated height can go, in the mountain's local space.
+    pub fn max_height(&self) -> f32 {
+        let raw: f32 = self
+            .layers
+            .iter()
+            .map(|layer| layer.amplitude.max(0.0))
+            .sum();
+        raw * self.height_scale.abs()
+    }
+
+    // Colliders standing around the edges of the terrain, in the mountain's local
+    // space. They reach from the kill plane to well above the highest peak, so
+    // brush edits can't dig or build past them.
+    pub fn boundary_walls(&self) -> [(Transform, Collider); 4] {
+        const THICKNESS: f32 = 1.0;
+        let bottom = self.kill_plane_y() - TERRAIN_BASE_HEIGHT;
+        let top = self.max_height() + 100.0;
+        let half_height = (top - bottom) / 2.0;
+        let center_y = (top + bottom) / 2.0;
+        // just outside the edge, long enough to close the corners
+        let offset = self.extent / 2.0 + THICKNESS / 2.0;
+        let half_length = self.extent / 2.0 + THICKNESS;
+        let along_z = Collider::cuboid(THICKNESS / 2.0, half_height, half_length.y);
+        let along_x = Collider::cuboid(half_length.x, half_height, THICKNESS / 2.0);
+        [
+            (
+                Transform::from_xyz(-offset.x, center_y, 0.0),
+                along_z.clone(),
+            ),
+            (Transform::from_xyz(offset.x, center_y, 0.0), along_z),
+            (
+                Transform::from_xyz(0.0, center_y, -offset.y),
+                along_x.clone(),
+            ),
+            (Transform::from_xyz(0.0, center_y, offset.y), along_x),
+        ]
+    }
+
+    pub fn kill_plane_y(&self) -> f32 {
+        // generated heights never go below 0, leave room for digging with the brush
+        self.kill_plane_y
+            .unwrap_or(TERRAIN_BASE_HEIGHT - self.height_scale.abs().max(50.0))
+    }
+
+    // A copy with fewer vertices along each side if there are more than
+    // `max_vertices`, and a warning saying so. `interpolate_step` shrinks with
+    // the vertex count, so the hills come out about the same size, just with
+    // less detail. The sizes stay multiples of it so wrapping stays seamless.
+    pub fn fit_budget(&self) -> (TerrainConfig, Option<String>) {
+        let count = self.x_max * self.y_max;
+        let Some(max) = self.max_vertices.filter(|&max| count > max) else {
+            return (self.clone(), None);
+        };
+        let factor = (max.max(4) as f32 / count as f32).sqrt();
+        let step = ((self.interpolate_step as f32 * factor).round() as usize).max(1);
+        let shrink = |vertices: usize| {
+            let quads = ((vertices - 1) as f32 * factor) as usize;
+            // a whole number of steps, unless that would leave nothing
+            let quads = if quads >= step {
+                quads / step * step
+            } else {
+                quads.max(1)
+            };
+            quads + 1
+        };
+        let fitted = TerrainConfig {
+            x_max: shrink(self.x_max),
+            y_max: shrink(self.y_max),
+            interpolate_step: step,
+            ..self.clone()
+        };
+        let warning = format!(
+            "a {} by {} terrain is over the budget of {} vertices, generating {} by {} instead",
+            self.x_max, self.y_max, max, fitted.x_max, fitted.y_max
+        );
+        (fitted, Some(warning))
+    }
+
+    // Whether the generator can build a terrain with these values.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.x_max < 2 || self.y_max < 2 {
+            return Err("x_max and y_max need to be at least 2".to_string());
+        }
+        if !(self.extent.is_finite() && self.extent.cmpgt(Vec2::ZERO).all()) {
+            return Err("extent has to be bigger than 0 along both axes".to_string());
+        }
+        if self.interpolate_step == 0 {
+            return Err("interpolate_step can't be 0".to_string());
+        }
+        if self.layers.iter().any(|layer| layer.frequency_mult == 0) {
+            return Err("a layer's frequency_mult can't be 0".to_string());
+        }
+        if self.layers.iter().any(|layer| !layer.amplitude.is_finite()) {
+            return Err("a layer's amplitude has to be a number".to_string());
+        }
+        // higher frequency layers read points further along, see `NoiseLayer`
+        let max_frequency = self
+            .layers
+            .iter()
+            .map(|layer| layer.frequency_mult)
+            .max()
+            .unwrap_or(1);
+        if self.x_max.max(self.y_max) * max_frequency / self.interpolate_step + 1 >= RANDOM_POINTS {
+            return Err(format!(
+                "x_max and y_max are too big for an interpolate_step of {} with a frequency_mult of {}",
+                self.interpolate_step, max_frequency
+            ));
+        }
+        if !self.height_scale.is_finite() {
+            return Err("height_scale has to be a number".to_string());
+        }
+        if !(self.island.radius.is_finite() && self.island.falloff.is_finite()) {
+            return Err("the island mask's radius and falloff have to be numbers".to_string());
+        }
+        Ok(())
+    }
+}
+
+// random points generated along each side, far more than the terrain needs
+const RANDOM_POINTS: usize = 1000;
+
+// smoothly interpolates between some points using a special polynomial from this video's beginning:
+// https://www.youtube.com/watch?v=BFld4EBO2RE
+// interpolate_step is how many points there are between random points
+// wrap is how many random points there are before they repeat along x and y, if they do
+pub fn interpolate_random_points(
+    points: &[Vec<f32>],
+    xi: usize,
+    yi: usize,
+    interpolate_step: usize,
+    wrap: Option<(usize, usize)>,
+) -> f32 {
+    let s_polynomial = |val: f32| 3.0 * (val * val * val) - 2.0 * val * val;
+    let (px, py) = (xi / interpolate_step, yi / interpolate_step);
+    let point = |px: usize, py: usize| match wrap {
+        Some((wrap_x, wrap_y)) => points[px % wrap_x][py % wrap_y],
+        None => points[px][py],
+    };
+    let rand_a = point(px, py);
+    let rand_b = point(px + 1, py);
+    let rand_c = point(px, py + 1);
+    let rand_d = point(px + 1, py + 1);
+
+    let rel_x =
+        ((xi - (interpolate_step * (xi / interpolate_step))) as f32) / (interpolate_step as f32);
+    let rel_y =
+        ((yi - (interpolate_step * (yi / interpolate_step))) as f32) / (interpolate_step as f32);
+    // interpolate smoothly between them
+    rand_a
+        + (rand_b - rand_a) * s_polynomial(rel_x)
+        + (rand_c - rand_a) * s_polynomial(rel_y)
+        + (rand_a - rand_b - rand_c + rand_d) * s_polynomial(rel_x) * s_polynomial(rel_y)
+}
+
+// Generated height of vertex (xi, zi), in the mountain's local space. With
+// `BoundaryMode::Wrap` the heights repeat every `x_max - 1` vertices along x and
+// `y_max - 1` along z, so the first and last rows are the same. The island
+// mask is applied on top.
+pub fn sample_height(config: &TerrainConfig, points: &[Vec<f32>], xi: usize, zi: usize) -> f32 {
+    let step = config.interpolate_step;
+    // higher frequency layers repeat more often, n times for a frequency_mult of n
+    let wrap = |frequency: usize| {
+        config.wraps().then(|| {
+            (
+                ((config.x_max - 1) * frequency / step).max(1),
+                ((config.y_max - 1) * frequency / step).max(1),
+            )
+        })
+    };
+    config
+        .layers
+        .iter()
+        .map(|layer| {
+            let frequency = layer.frequency_mult;
+            layer.amplitude
+                * interpolate_random_points(
+                    points,
+                    xi * frequency,
+                    zi * frequency,
+                    step,
+                    wrap(frequency),
+                )
+        })
+        .sum::<f32>()
+        * config.height_scale
+        * config.island.factor(config.vertex_xz(xi, zi))
+}
+
+// Interpolation of a grid of `width` by `height` values, stored like the
+// terrain's at `x * height + z`, at (x, z) in grid cells. Exactly the grid's
+// value at every node, and (x, z) outside the grid is clamped onto its edge.
+// Each cell is split into two flat triangles along its diagonal from (x, z) to
+// (x + 1, z + 1), the same split the terrain mesh and Parry's heightfield use, so
+// everything reading heights between vertices through here agrees with the
+// surface that's drawn and stood on, not just at the vertices.
+pub fn sample_grid(grid: &[f32], width: usize, height: usize, x: f32, z: f32) -> f32 {
+    let value = |xi: usize, zi: usize| grid[xi * height + zi];
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let z = z.clamp(0.0, (height - 1) as f32);
+    // the last row and column belong to the cell before them
+    let xi = (x.floor() as usize).min(width.saturating_sub(2));
+    let zi = (z.floor() as usize).min(height.saturating_sub(2));
+    let (rel_x, rel_z) = (x - xi as f32, z - zi as f32);
+    let (next_x, next_z) = ((xi + 1).min(width - 1), (zi + 1).min(height - 1));
+    let corner = value(xi, zi);
+    let opposite = value(next_x, next_z);
+    if rel_x >= rel_z {
+        // the triangle with the cell's +x corner
+        let side = value(next_x, zi);
+        corner + (side - corner) * rel_x + (opposite - side) * rel_z
+    } else {
+        // and the one with its +z corner
+        let side = value(xi, next_z);
+        corner + (side - corner) * rel_z + (opposite - side) * rel_x
+    }
+}
+
+// The generated height of every terrain vertex, kept around so the terrain can be
+// edited and the collider rebuilt from it.
+#[derive(Resource, Debug, Clone)]
+pub struct TerrainHeights {
+    pub x_max: usize,
+    pub y_max: usize,
+    // world units the vertices span along x and z, see `TerrainConfig::extent`
+    pub extent: Vec2,
+    // indexed by `xi * y_max + zi`, the same order as the mesh vertices
+    pub heights: Vec<f32>,
+}
+
+impl TerrainHeights {
+    pub fn index(&self, xi: usize, zi: usize) -> usize {
+        xi * self.y_max + zi
+    }
+
+    // distance between neighboring vertices along x and z, the first and last
+    // vertices are on the edges of the terrain
+    pub fn spacing(&self) -> Vec2 {
+        self.extent / Vec2::new((self.x_max - 1) as f32, (self.y_max - 1) as f32)
+    }
+
+    // the xz rectangle the terrain covers, in the mountain's local space
+    pub fn bounds(&self) -> Rect {
+        Rect::from_center_size(Vec2::ZERO, self.extent)
+    }
+
+    // position of a grid vertex on the xz plane, in the mountain's local space
+    pub fn vertex_xz(&self, xi: usize, zi: usize) -> Vec2 {
+        Vec2::new(xi as f32, zi as f32) * self.spacing() - self.extent / 2.0
+    }
+
+    // The rows and columns of vertices inside `region`, a rectangle in the
+    // mountain's local space. Empty if it's off the terrain.
+    pub fn vertices_in(&self, region: Rect) -> (Range<usize>, Range<usize>) {
+        let min = ((region.min + self.extent / 2.0) / self.spacing()).ceil();
+        let max = ((region.max + self.extent / 2.0) / self.spacing()).floor();
+        let range = |min: f32, max: f32, len: usize| {
+            let start = min.max(0.0) as usize;
+            let end = (max.max(-1.0) + 1.0) as usize;
+            start.min(len)..end.min(len)
+        };
+        (
+            range(min.x, max.x, self.x_max),
+            range(min.y, max.y, self.y_max),
+        )
+    }
+
+    // World space height of the terrain surface at world (x, z), on the triangle of
+    // the mesh over it. None if (x, z) isn't over the terrain.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let grid = (Vec2::new(x, z) + self.extent / 2.0) / self.spacing();
+        let (grid_x, grid_z) = (grid.x, grid.y);
+        let max_x = (self.x_max - 1) as f32;
+        let max_z = (self.y_max - 1) as f32;
+        if !(0.0..=max_x).contains(&grid_x) || !(0.0..=max_z).contains(&grid_z) {
+            return None;
+        }
+
+        let height = sample_grid(&self.heights, self.x_max, self.y_max, grid_x, grid_z);
+        Some(height + TERRAIN_BASE_HEIGHT)
+    }
+
+    // Surface normal at world (x, z), from the height gradient one vertex away on
+    // each side so it's averaged over the neighboring faces. None if (x, z) isn't
+    // over the terrain.
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vec3> {
+        let height = self.height_at(x, z)?;
+        let step = self.spacing();
+        let sample = |dx: f32, dz: f32| self.height_at(x + dx, z + dz).unwrap_or(height);
+        let dx = (sample(step.x, 0.0) - sample(-step.x, 0.0)) / (2.0 * step.x);
+        let dz = (sample(0.0, step.y) - sample(0.0, -step.y)) / (2.0 * step.y);
+        Some(Vec3::new(-dx, 1.0, -dz).normalize())
+    }
+}
+
+// Steps `create_mountain_mesh` reports through its progress counter: one for the
+// random points (or loading the cache), one per row of vertices, and one for the collider.
+pub fn generation_steps(config: &TerrainConfig) -> usize {
+    config.x_max + 2
+}
+
+// `progress` counts up to `generation_steps` as the terrain is built, so another
+// thread can show how far along it is.
+pub fn create_mountain_mesh(
+    config: &TerrainConfig,
+    seed: TerrainSeed,
+    progress: &AtomicUsize,
+) -> (Collider, Mesh, TerrainHeights) {
+    let cached = terrain_cache::load(config, seed, IVec2::ZERO);
+    let mut random_positions: Vec<Vec<f32>> = vec![];
+    if cached.is_none() {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed.0);
+        // add a ton of random positions so we never go out of bounds
+        for _i in 0..RANDOM_POINTS {
+            let mut random_row = vec![];
+            for _j in 0..RANDOM_POINTS {
+                random_row.push(rng.gen());
+            }
+            random_positions.push(random_row);
+        }
+    }
+    progress.fetch_add(1, Ordering::Relaxed);
+
+    let mut collision_heights = vec![];
+
+    let x_max = config.x_max;
+    let interpolate_step = config.interpolate_step;
+    let y_max = config.y_max;
+    if config.wraps()
+        && (!(x_max - 1).is_multiple_of(interpolate_step)
+            || !(y_max - 1).is_multiple_of(interpolate_step))
+    {
+        warn!("the terrain's size isn't a multiple of interpolate_step, there will be seams where it wraps");
+    }
+
+    for xi in 0..x_max {
+        for zi in 0..y_max {
+            let y = match &cached {
+                Some(cached) => cached.heights[cached.index(xi, zi)],
+                None => sample_height(config, &random_positions, xi, zi),
+            };
+
+            collision_heights.push(y);
+        }
+        // once per row is plenty, and cheap next to the row itself
+        progress.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let heights = TerrainHeights {
+        x_max,
+        y_max,
+        extent: config.extent,
+        heights: collision_heights,
+    };
+    if cached.is_none() {
+        terrain_cache::save(config, seed, IVec2::ZERO, &heights);
+    }
+    let mesh = terrain_mesh(config, &heights);
+    let collider = terrain_collider(config, &heights, &mesh);
+    progress.fetch_add(1, Ordering::Relaxed);
+    (collider, mesh, heights)
+}
+
+// The terrain's mesh for a set of heights, one vertex per height shared by the
+// triangles around it, so its normals are smooth.
+pub fn terrain_mesh(config: &TerrainConfig, heights: &TerrainHeights) -> Mesh {
+    let mut vertex_positions = vec![];
+    let mut uv_positions = vec![];
+    let mut triangles = vec![];
+    let mut normals = vec![];
+
+    let (x_max, y_max) = (heights.x_max, heights.y_max);
+    let extent = heights.extent;
+    // UVs are in units of the longer side, so a texture isn't stretched along
+    // the shorter one
+    let uv_scale = extent / extent.max_element();
+    for xi in 0..x_max {
+        for zi in 0..y_max {
+            let y = heights.heights[heights.index(xi, zi)];
+            let u = (xi as f32) / ((x_max - 1) as f32);
+            let v = (zi as f32) / ((y_max - 1) as f32);
+            vertex_positions.push([
+                u * extent.x - extent.x / 2.0,
+                y,
+                v * extent.y - extent.y / 2.0,
+            ]);
+            uv_positions.push([u * uv_scale.x, v * uv_scale.y]);
+            normals.push([0.0, 0.0, 1.0]);
+
+            // we make squares, so two triangles per index, and the last row and
+            // column have no square of their own
+            if xi + 1 < x_max && zi + 1 < y_max {
+                for triangle in cell_triangles(xi, zi, y_max) {
+                    triangles.extend(triangle.map(|index| index as u32));
+                }
+            }
+        }
+    }
+
+    // Keep the mesh data accessible in future frames to be able to mutate it in toggle_texture.
+    let mut mesh = Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        // Each array is an [x, y, z] coordinate in local space.
+        // The camera coordinate space is right-handed x-right, y-up, z-back. This means "forward" is -Z.
+        // Meshes always rotate around their local [0, 0, 0] when a rotation is applied to their Transform.
+        // By centering our mesh around the origin, rotating the mesh preserves its center of mass.
+        vertex_positions,
+    )
+    // make uv the same as vertex positions XD
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uv_positions)
+    //.with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_indices(Indices::U32(triangles))
+    .with_computed_normals();
+    smooth_mesh_normals(&mut mesh, x_max, y_max, config.normal_smoothing);
+    mesh
+}
+
+// Gives every triangle of a mesh its own vertices, all with the triangle's
+// normal, for flat shaded low poly facets.
+pub fn facet_mesh(mesh: &mut Mesh) {
+    mesh.duplicate_vertices();
+    mesh.compute_flat_normals();
+}
+
+// The two triangles of the mesh's square with its corner at vertex (xi, zi), as
+// indices of their vertices. The diagonal between them runs from (xi, zi) to
+// (xi + 1, zi + 1).
+pub fn cell_triangles(xi: usize, zi: usize, y_max: usize) -> [[usize; 3]; 2] {
+    let index = xi * y_max + zi;
+    let (right, down) = (index + y_max, index + 1);
+    let down_right = right + 1;
+    [[right, index, down_right], [down_right, index, down]]
+}
+
+// The squares vertex (xi, zi) is a corner of, up to four of them.
+pub fn cells_around(
+    xi: usize,
+    zi: usize,
+    x_max: usize,
+    y_max: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    (xi.saturating_sub(1)..=xi.min(x_max - 2)).flat_map(move |cell_x| {
+        (zi.saturating_sub(1)..=zi.min(y_max - 2)).map(move |cell_z| (cell_x, cell_z))
+    })
+}
+
+// A faceted mesh, see `facet_mesh`, has the three vertices of every triangle one
+// after another in the order `terrain_mesh` lists the triangles, square by square.
+// These are the faceted vertices of the square at (xi, zi), each along with the
+// vertex of the smooth mesh it's a copy of.
+pub fn facet_vertices(xi: usize, zi: usize, y_max: usize) -> impl Iterator<Item = (usize, usize)> {
+    let first = 6 * (xi * (y_max - 1) + zi);
+    cell_triangles(xi, zi, y_max)
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(move |(i, index)| (first + i, index))
+}
+
+// The faceted vertices that are copies of the smooth mesh's vertex at `index`, one
+// for every triangle it's a corner of.
+pub fn facet_copies(index: usize, x_max: usize, y_max: usize) -> impl Iterator<Item = usize> {
+    cells_around(index / y_max, index % y_max, x_max, y_max)
+        .flat_map(move |(xi, zi)| facet_vertices(xi, zi, y_max))
+        .filter_map(move |(vertex, of)| (of == index).then_some(vertex))
+}
+
+// Every vertex of an `x_max` by `y_max` grid, laid out like the terrain's, at most
+// `distance` vertices along x and along z from any of `indices`.
+pub fn grid_neighborhood(
+    indices: &[usize],
+    x_max: usize,
+    y_max: usize,
+    distance: usize,
+) -> HashSet<usize> {
+    let mut neighborhood = HashSet::new();
+    for &index in indices {
+        let (xi, zi) = (index / y_max, index % y_max);
+        for x in xi.saturating_sub(distance)..=(xi + distance).min(x_max - 1) {
+            for z in zi.saturating_sub(distance)..=(zi + distance).min(y_max - 1) {
+                neighborhood.insert(x * y_max + z);
+            }
+        }
+    }
+    neighborhood
+}
+
+// Same as Bevy's, which isn't public.
+fn face_normal([a, b, c]: [[f32; 3]; 3]) -> Vec3 {
+    let (a, b, c) = (Vec3::from(a), Vec3::from(b), Vec3::from(c));
+    (b - a).cross(c - a).normalize()
+}
+
+// The normal `compute_normals` gives the terrain mesh's vertex at `index`, the
+// average of the normals of the triangles it's a corner of.
+fn vertex_normal(positions: &[[f32; 3]], x_max: usize, y_max: usize, index: usize) -> Vec3 {
+    let (mut sum, mut count) = (Vec3::ZERO, 0.0);
+    for (xi, zi) in cells_around(index / y_max, index % y_max, x_max, y_max) {
+        for triangle in cell_triangles(xi, zi, y_max) {
+            if triangle.contains(&index) {
+                sum += face_normal(triangle.map(|corner| positions[corner]));
+                count += 1.0;
+            }
+        }
+    }
+    (sum / count).normalize()
+}
+
+// The normal of the vertex at (xi, zi) averaged with its four neighbors'.
+fn smoothed_normal(
+    normal: impl Fn(usize, usize) -> Vec3,
+    x_max: usize,
+    y_max: usize,
+    xi: usize,
+    zi: usize,
+) -> Vec3 {
+    let mut sum = normal(xi, zi);
+    if xi > 0 {
+        sum += normal(xi - 1, zi);
+    }
+    if xi + 1 < x_max {
+        sum += normal(xi + 1, zi);
+    }
+    if zi > 0 {
+        sum += normal(xi, zi - 1);
+    }
+    if zi + 1 < y_max {
+        sum += normal(xi, zi + 1);
+    }
+    sum.normalize_or(Vec3::Y)
+}
+
+// One pass of averaging every normal of an `x_max` by `y_max` vertex grid with
+// its four neighbors, laid out like the terrain's vertices.
+pub fn smooth_normals(normals: &[[f32; 3]], x_max: usize, y_max: usize) -> Vec<[f32; 3]> {
+    let normal = |xi: usize, zi: usize| Vec3::from(normals[xi * y_max + zi]);
+    let mut smoothed = Vec::with_capacity(normals.len());
+    for xi in 0..x_max {
+        for zi in 0..y_max {
+            smoothed.push(smoothed_normal(normal, x_max, y_max, xi, zi).to_array());
+        }
+    }
+    smoothed
+}
+
+// Smooths the terrain mesh's normals `iterations` times, see `smooth_normals`.
+pub fn smooth_mesh_normals(mesh: &mut Mesh, x_max: usize, y_max: usize, iterations: u32) {
+    if iterations == 0 {
+        return;
+    }
+    let Some(VertexAttributeValues::Float32x3(normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return;
+    };
+    if normals.len() != x_max * y_max {
+        return;
+    }
+    for _ in 0..iterations {
+        *normals = smooth_normals(normals, x_max, y_max);
+    }
+}
+
+// Brings the terrain mesh's normals up to date with its vertices at `moved`, to
+// what `compute_normals` followed by `smooth_mesh_normals` gives the whole mesh.
+// A moved vertex tilts the triangles around it and every pass of smoothing
+// spreads that a vertex further, so only the normals that close to `moved` are
+// redone, and the work grows with how many moved rather than with the mesh.
+pub fn update_mesh_normals(
+    mesh: &mut Mesh,
+    x_max: usize,
+    y_max: usize,
+    iterations: u32,
+    moved: &[usize],
+) {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    if positions.len() != x_max * y_max {
+        return;
+    }
+    let iterations = iterations as usize;
+    // every pass of smoothing reads the normals a vertex further out than it
+    // writes, so the unsmoothed ones are needed twice as far out as the changes go
+    let mut normals: HashMap<usize, Vec3> =
+        grid_neighborhood(moved, x_max, y_max, 1 + 2 * iterations)
+            .into_iter()
+            .map(|index| (index, vertex_normal(positions, x_max, y_max, index)))
+            .collect();
+    for pass in 1..=iterations {
+        let previous = normals;
+        let normal = |xi: usize, zi: usize| previous[&(xi * y_max + zi)];
+        normals = grid_neighborhood(moved, x_max, y_max, 1 + 2 * iterations - pass)
+            .into_iter()
+            .map(|index| {
+                let smoothed = smoothed_normal(normal, x_max, y_max, index / y_max, index % y_max);
+                (index, smoothed)
+            })
+            .collect();
+    }
+    if let Some(VertexAttributeValues::Float32x3(mesh_normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        for (index, normal) in normals {
+            mesh_normals[index] = normal.to_array();
+        }
+    }
+}
+
+// Brings a faceted terrain mesh's positions and flat normals up to date with the
+// heights at `moved`, redoing only the triangles they're corners of.
+pub fn update_facets(mesh: &mut Mesh, heights: &TerrainHeights, moved: &[usize]) {
+    let (x_max, y_max) = (heights.x_max, heights.y_max);
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    let cells: HashSet<(usize, usize)> = moved
+        .iter()
+        .flat_map(|&index| cells_around(index / y_max, index % y_max, x_max, y_max))
+        .collect();
+    let mut normals = Vec::with_capacity(cells.len() * 6);
+    for (xi, zi) in cells {
+        let vertices: Vec<(usize, usize)> = facet_vertices(xi, zi, y_max).collect();
+        for &(vertex, index) in &vertices {
+            positions[vertex][1] = heights.heights[index];
+        }
+        for triangle in vertices.chunks_exact(3) {
+            let normal = face_normal([0, 1, 2].map(|corner| positions[triangle[corner].0]));
+            normals.extend(triangle.iter().map(|&(vertex, _)| (vertex, normal)));
+        }
+    }
+    if let Some(VertexAttributeValues::Float32x3(mesh_normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        for (vertex, normal) in normals {
+            mesh_normals[vertex] = normal.to_array();
+        }
+    }
+}
+
+// Parry's heightfield rows run along z and its columns along x, and it reads the
+// heights column by column, which is the `xi * y_max + zi` order they're kept in.
+// Parry splits cells along their other diagonal unless they're marked zigzag, so
+// they all are, which puts its triangles where the mesh's are.
+pub fn heightfield_collider(heights: &TerrainHeights) -> Collider {
+    let grid = DMatrix::from_vec(heights.y_max, heights.x_max, heights.heights.clone());
+    let scale = Vec3::new(heights.extent.x, 1.0, heights.extent.y);
+    let mut heightfield = HeightField::new(grid, scale.into());
+    heightfield
+        .cells_statuses_mut()
+        .fill(HeightFieldCellStatus::ZIGZAG_SUBDIVISION);
+    SharedShape::new(heightfield).into()
+}
+
+pub fn terrain_collider(config: &TerrainConfig, heights: &TerrainHeights, mesh: &Mesh) -> Collider {
+    match config.collider {
+        ColliderKind::Heightfield => heightfield_collider(heights),
+        ColliderKind::Trimesh => trimesh_collider(mesh).unwrap_or_else(|err| {
+            error!("can't build the terrain trimesh, using a heightfield instead: {err}");
+            heightfield_collider(heights)
+        }),
+    }
+}
+
+#[derive(Debug)]
+pub enum TrimeshError {
+    MissingPositions,
+    UnsupportedIndices,
+}
+
+impl std::fmt::Display for TrimeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TrimeshError::MissingPositions => {
+                write!(
+                    f,
+                    "mesh has no Float32x3 ATTRIBUTE_POSITION to build a trimesh from"
+                )
+            }
+            TrimeshError::UnsupportedIndices => {
+                write!(f, "mesh needs U32 triangle indices to build a trimesh")
+            }
+        }
+    }
+}
+
+// Builds a triangle mesh collider from the vertices and indices of a mesh, which
+// unlike a heightfield can handle overhangs and arbitrary imported geometry.
+pub fn trimesh_collider(mesh: &Mesh) -> Result<Collider, TrimeshError> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return Err(TrimeshError::MissingPositions);
+    };
+    let Some(Indices::U32(indices)) = mesh.indices() else {
+        return Err(TrimeshError::UnsupportedIndices);
+    };
+    if indices.len() % 3 != 0 {
+        return Err(TrimeshError::UnsupportedIndices);
+    }
+
+    let vertices = positions.iter().map(|&p| Vec3::from(p)).collect();
+    let triangles = indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .collect();
+    Ok(Collider::trimesh(vertices, triangles))
+}
+
+// Keeps the player on the terrain when it wraps, moving them to the opposite
+// edge when they walk off one. The heights match on both sides, so they land at
+// the same height they left.
+pub fn wrap_player(config: Res<TerrainConfig>, mut player: Query<&mut Transform, With<Player>>) {
+    if !config.wraps() {
+        return;
+    }
+    for mut transform in player.iter_mut() {
+        let half = config.extent / 2.0;
+        let wrapped = (transform.translation.xz() + half).rem_euclid(config.extent) - half;
+        if wrapped != transform.translation.xz() {
+            transform.translation.x = wrapped.x;
+            transform.translation.z = wrapped.y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn island_corners_are_under_the_sea() {
+        let config = TerrainConfig {
+            island: IslandMask {
+                enabled: true,
+                ..default()
+            },
+            ..default()
+        };
+        // every random point at its highest, so only the mask can bring the edges down
+        let points = vec![vec![1.0; 100]; 100];
+        // a little above the lowest generated height
+        let sea_level = 1.0;
+        let (last_x, last_z) = (config.x_max - 1, config.y_max - 1);
+        for (xi, zi) in [(0, 0), (last_x, 0), (0, last_z), (last_x, last_z)] {
+            let height = sample_height(&config, &points, xi, zi);
+            assert!(height < sea_level, "{} at {}, {}", height, xi, zi);
+        }
+        // the middle is left alone
+        let unmasked = TerrainConfig {
+            island: IslandMask::default(),
+            ..config.clone()
+        };
+        let (mid_x, mid_z) = (last_x / 2, last_z / 2);
+        assert_eq!(
+            sample_height(&config, &points, mid_x, mid_z),
+            sample_height(&unmasked, &points, mid_x, mid_z)
+        );
+    }
+
+    #[test]
+    fn wrapped_edges_match() {
+        let config = TerrainConfig {
+            boundary: BoundaryMode::Wrap,
+            ..default()
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let points: Vec<Vec<f32>> = (0..100)
+            .map(|_| (0..100).map(|_| rng.gen()).collect())
+            .collect();
+        let (last_x, last_z) = (config.x_max - 1, config.y_max - 1);
+        for i in 0..config.x_max.min(config.y_max) {
+            assert_eq!(
+                sample_height(&config, &points, 0, i),
+                sample_height(&config, &points, last_x, i)
+            );
+            assert_eq!(
+                sample_height(&config, &points, i, 0),
+                sample_height(&config, &points, i, last_z)
+            );
+        }
+        // and the terrain isn't just flat
+        assert_ne!(
+            sample_height(&config, &points, 0, 0),
+            sample_height(&config, &points, last_x / 2, last_z / 2)
+        );
+    }
+
+    // The default layers are the two octaves the terrain was made of before layers
+    // could be configured.
+    #[test]
+    fn default_layers_match_two_octaves() {
+        let config = TerrainConfig::default();
+        let step = config.interpolate_step;
+        let mut rng = ChaCha8Rng::seed_from_u64(11);
+        let points: Vec<Vec<f32>> = (0..100)
+            .map(|_| (0..100).map(|_| rng.gen()).collect())
+            .collect();
+        for xi in (0..config.x_max).step_by(7) {
+            for zi in (0..config.y_max).step_by(5) {
+                let octaves = (interpolate_random_points(&points, xi, zi, step, None)
+                    + 0.5 * interpolate_random_points(&points, xi * 2, zi * 2, step, None))
+                    * config.height_scale;
+                assert_eq!(sample_height(&config, &points, xi, zi), octaves);
+            }
+        }
+    }
+
+    #[test]
+    fn smoothing_reduces_normal_variance() {
+        let (x_max, y_max) = (20, 20);
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let mut normals: Vec<[f32; 3]> = (0..x_max * y_max)
+            .map(|_| {
+                Vec3::new(rng.gen_range(-1.0..1.0), 1.0, rng.gen_range(-1.0..1.0))
+                    .normalize()
+                    .to_array()
+            })
+            .collect();
+        // mean squared distance from the average normal
+        let variance = |normals: &[[f32; 3]]| {
+            let mean = normals.iter().map(|&n| Vec3::from(n)).sum::<Vec3>() / normals.len() as f32;
+            normals
+                .iter()
+                .map(|&n| Vec3::from(n).distance_squared(mean))
+                .sum::<f32>()
+                / normals.len() as f32
+        };
+        let mut previous = variance(&normals);
+        for _ in 0..4 {
+            normals = smooth_normals(&normals, x_max, y_max);
+            let current = variance(&normals);
+            assert!(
+                current < previous,
+                "{} isn't less than {}",
+                current,
+                previous
+            );
+            previous = current;
+        }
+        // and the same input always gives the same result
+        assert_eq!(
+            smooth_normals(&normals, x_max, y_max),
+            smooth_normals(&normals, x_max, y_max)
+        );
+    }
+
+    // 4 by 3, every value different
+    fn small_grid() -> Vec<f32> {
+        (0..12).map(|i| (i * i) as f32 * 0.5 - 3.0).collect()
+    }
+
+    #[test]
+    fn sampling_hits_nodes_and_midpoints() {
+        let grid = small_grid();
+        let (width, height) = (4, 3);
+        for x in 0..width {
+            for z in 0..height {
+                assert_eq!(
+                    sample_grid(&grid, width, height, x as f32, z as f32),
+                    grid[x * height + z]
+                );
+            }
+        }
+        for x in 0..width - 1 {
+            for z in 0..height - 1 {
+                // the middle of a cell is on the diagonal between its triangles
+                let diagonal = grid[x * height + z] + grid[(x + 1) * height + z + 1];
+                let middle = sample_grid(&grid, width, height, x as f32 + 0.5, z as f32 + 0.5);
+                assert!((middle - diagonal / 2.0).abs() < 1e-5);
+                let edge = sample_grid(&grid, width, height, x as f32 + 0.5, z as f32);
+                assert!(
+                    (edge - (grid[x * height + z] + grid[(x + 1) * height + z]) / 2.0).abs() < 1e-5
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sampling_is_continuous_across_cells() {
+        let (width, height) = (12, 9);
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let grid: Vec<f32> = (0..width * height)
+            .map(|_| rng.gen_range(-10.0..10.0))
+            .collect();
+        let sample = |x: f32, z: f32| sample_grid(&grid, width, height, x, z);
+        // neighboring values can differ by 20, so over 1e-3 of a cell the
+        // surface can't move by more than a few hundredths
+        let epsilon = 1e-3;
+        let limit = 20.0 * 2.0 * epsilon * 2.0;
+        for _ in 0..1000 {
+            // a point on an inner cell boundary, sampled from both sides
+            let boundary = rng.gen_range(1..width - 1) as f32;
+            let along = rng.gen_range(0.0..(height - 1) as f32);
+            let across_x =
+                (sample(boundary - epsilon, along) - sample(boundary + epsilon, along)).abs();
+            let boundary = rng.gen_range(1..height - 1) as f32;
+            let along = rng.gen_range(0.0..(width - 1) as f32);
+            let across_z =
+                (sample(along, boundary - epsilon) - sample(along, boundary + epsilon)).abs();
+            assert!(across_x < limit, "jumped {} across x", across_x);
+            assert!(across_z < limit, "jumped {} across z", across_z);
+        }
+    }
+
+    // The mesh's vertices, the heights the collider is built from and height
+    // queries all agree at every vertex.
+    #[test]
+    fn mesh_collider_and_queries_agree() {
+        let config = TerrainConfig {
+            x_max: 21,
+            y_max: 31,
+            cache_dir: None,
+            ..default()
+        };
+        let (_, mesh, heights) =
+            create_mountain_mesh(&config, TerrainSeed(3), &AtomicUsize::new(0));
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the terrain mesh has no positions");
+        };
+        for xi in 0..heights.x_max {
+            for zi in 0..heights.y_max {
+                let index = heights.index(xi, zi);
+                let [x, y, z] = positions[index];
+                assert_eq!(y, heights.heights[index]);
+                let queried = heights.height_at(x, z).unwrap() - TERRAIN_BASE_HEIGHT;
+                assert!(
+                    (queried - y).abs() < 1e-3,
+                    "{} != {} at {}, {}",
+                    queried,
+                    y,
+                    xi,
+                    zi
+                );
+            }
+        }
+
+        // and inside the cells, on both of their triangles, the queries give the
+        // height of the surface the colliders put there
+        let colliders = [
+            ("heightfield", heightfield_collider(&heights)),
+            ("trimesh", trimesh_collider(&mesh).unwrap()),
+        ];
+        let top = config.height_scale * 10.0;
+        for xi in 0..heights.x_max - 1 {
+            for zi in 0..heights.y_max - 1 {
+                for offset in [Vec2::new(0.3, 0.7), Vec2::new(0.8, 0.1), Vec2::splat(0.45)] {
+                    let xz = heights.vertex_xz(xi, zi) + offset * heights.spacing();
+                    let queried = heights.height_at(xz.x, xz.y).unwrap() - TERRAIN_BASE_HEIGHT;
+                    for (name, collider) in &colliders {
+                        let hit = collider
+                            .cast_local_ray(Vec3::new(xz.x, top, xz.y), Vec3::NEG_Y, f32::MAX, true)
+                            .unwrap_or_else(|| panic!("no {} under {}", name, xz));
+                        assert!(
+                            (top - hit - queried).abs() < 1e-3,
+                            "{} at {} but the query at {} at {}",
+                            name,
+                            top - hit,
+                            queried,
+                            xz
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn heightfield_collider_matches_the_heights() {
+        let config = TerrainConfig {
+            x_max: 41,
+            y_max: 11,
+            extent: Vec2::new(400.0, 60.0),
+            collider: ColliderKind::Heightfield,
+            cache_dir: None,
+            ..default()
+        };
+        let (collider, _, heights) =
+            create_mountain_mesh(&config, TerrainSeed(5), &AtomicUsize::new(0));
+        let top = config.height_scale * 10.0;
+        for xi in 0..heights.x_max {
+            for zi in 0..heights.y_max {
+                // rays right along the edges of the cells can slip between them,
+                // so each one is a hair inside a cell
+                let inside = heights.bounds().inflate(-1e-3);
+                let xz = (heights.vertex_xz(xi, zi) + heights.spacing() * 1e-3)
+                    .clamp(inside.min, inside.max);
+                let hit = collider
+                    .cast_local_ray(Vec3::new(xz.x, top, xz.y), Vec3::NEG_Y, f32::MAX, true)
+                    .unwrap_or_else(|| panic!("no collider under {}, {}", xi, zi));
+                let queried = heights.height_at(xz.x, xz.y).unwrap() - TERRAIN_BASE_HEIGHT;
+                assert!(
+                    (top - hit - queried).abs() < 1e-3,
+                    "collider at {} but heights at {} at {}, {}",
+                    top - hit,
+                    queried,
+                    xi,
+                    zi
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mesh_spans_the_configured_extent() {
+        let config = TerrainConfig {
+            x_max: 41,
+            y_max: 11,
+            extent: Vec2::new(400.0, 60.0),
+            cache_dir: None,
+            ..default()
+        };
+        let (_, mesh, heights) =
+            create_mountain_mesh(&config, TerrainSeed(5), &AtomicUsize::new(0));
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the terrain mesh has no positions");
+        };
+        let (min, max) = positions.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), &[x, _, z]| (min.min(Vec2::new(x, z)), max.max(Vec2::new(x, z))),
+        );
+        assert_eq!(Rect::from_corners(min, max), heights.bounds());
+        assert_eq!(max - min, config.extent);
+        // the UVs are as long as the mesh along each axis, relative to each other
+        let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+        else {
+            panic!("the terrain mesh has no UVs");
+        };
+        let uv_max = uvs
+            .iter()
+            .fold(Vec2::ZERO, |max, &uv| max.max(Vec2::from(uv)));
+        assert_eq!(uv_max, config.extent / config.extent.max_element());
+    }
+
+    #[test]
+    fn faceted_mesh_keeps_the_heights() {
+        let config = TerrainConfig {
+            x_max: 9,
+            y_max: 7,
+            cache_dir: None,
+            ..default()
+        };
+        let (_, smooth, heights) =
+            create_mountain_mesh(&config, TerrainSeed(3), &AtomicUsize::new(0));
+        let mut faceted = terrain_mesh(&config, &heights);
+        facet_mesh(&mut faceted);
+        let (
+            Some(VertexAttributeValues::Float32x3(positions)),
+            Some(VertexAttributeValues::Float32x3(faceted_positions)),
+            Some(VertexAttributeValues::Float32x3(normals)),
+            Some(Indices::U32(indices)),
+        ) = (
+            smooth.attribute(Mesh::ATTRIBUTE_POSITION),
+            faceted.attribute(Mesh::ATTRIBUTE_POSITION),
+            faceted.attribute(Mesh::ATTRIBUTE_NORMAL),
+            smooth.indices(),
+        )
+        else {
+            panic!("a terrain mesh is missing its positions, normals or indices");
+        };
+        // a copy of the smooth mesh's vertices for every corner of every triangle
+        assert_eq!(faceted_positions.len(), indices.len());
+        for (corner, &index) in faceted_positions.iter().zip(indices) {
+            assert_eq!(*corner, positions[index as usize]);
+        }
+        // and the corners of a triangle all face the same way
+        for triangle in normals.chunks_exact(3) {
+            assert_eq!(triangle[0], triangle[1]);
+            assert_eq!(triangle[0], triangle[2]);
+        }
+    }
+
+    // FNV-1a over the bits of every vertex position. Unlike std's hashers it's
+    // the same on every platform and Rust version, so it can be written down.
+    fn position_hash(mesh: &Mesh) -> u64 {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("the terrain mesh has no positions");
+        };
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in positions
+            .iter()
+            .flatten()
+            .flat_map(|coordinate| coordinate.to_bits().to_le_bytes())
+        {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        hash
+    }
+
+    // The random numbers the golden hashes below are made from. ChaCha8Rng's
+    // output is fixed by its algorithm, not by the rand release, so this only
+    // fails if the generator or how heights are drawn from it has changed, which
+    // tells that apart from a change to the terrain math.
+    #[test]
+    fn seeded_numbers_are_pinned() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let numbers = [rng.gen::<u64>(), rng.gen::<u64>()];
+        let float: f32 = rng.gen();
+        assert_eq!(
+            numbers,
+            [0x6709_4cea_8ca4_0db1, 0x1494_06d8_fc0e_8e6b],
+            "{numbers:#x?}"
+        );
+        assert_eq!(float.to_bits(), 0x3e58_1c18, "{:#x}", float.to_bits());
+    }
+
+    // Golden hashes of the generated terrain for a few seeds and configs, which
+    // only depend on the terrain math and ChaCha8Rng, see above. Saved terrain
+    // caches and daily seeds rely on the same seed always making the same
+    // terrain, so these failing means every existing cache and shared seed now
+    // gives a different world. If that's intended, run the test, check the new
+    // terrain looks right, replace the hashes with the ones it prints, and bump
+    // the terrain cache's version.
+    #[test]
+    fn generation_matches_golden_hashes() {
+        let small = TerrainConfig {
+            x_max: 41,
+            y_max: 41,
+            // don't read an old cache, or leave a new one behind
+            cache_dir: None,
+            ..default()
+        };
+        let cases = [
+            (TerrainSeed(1), small.clone(), 0xc70b_f1c9_2fd0_14d0),
+            (
+                TerrainSeed(42),
+                TerrainConfig {
+                    boundary: BoundaryMode::Wrap,
+                    ..small.clone()
+                },
+                0x8be2_4d20_05ff_61a8,
+            ),
+            (
+                TerrainSeed(0xdead_beef),
+                TerrainConfig {
+                    x_max: 31,
+                    y_max: 51,
+                    interpolate_step: 10,
+                    height_scale: 30.0,
+                    ..small.clone()
+                },
+                0x4547_7ddc_a9a5_db06,
+            ),
+        ];
+        let mut mismatches = vec![];
+        for (seed, config, golden) in cases {
+            let (_, mesh, _) = create_mountain_mesh(&config, seed, &AtomicUsize::new(0));
+            let hash = position_hash(&mesh);
+            if hash != golden {
+                mismatches.push(format!("seed {}: {:#018x}", seed.0, hash));
+            }
+        }
+        assert!(
+            mismatches.is_empty(),
+            "terrain generation changed, new hashes:\n{}",
+            mismatches.join("\n")
+        );
+    }
+
+    #[test]
+    fn vertices_in_covers_exactly_the_region() {
+        let heights = TerrainHeights {
+            x_max: 11,
+            y_max: 21,
+            extent: Vec2::new(10.0, 20.0),
+            heights: vec![0.0; 11 * 21],
+        };
+        let region = Rect::new(-2.5, 3.0, 1.5, 7.2);
+        let (xs, zs) = heights.vertices_in(region);
+        for xi in 0..heights.x_max {
+            for zi in 0..heights.y_max {
+                assert_eq!(
+                    xs.contains(&xi) && zs.contains(&zi),
+                    region.contains(heights.vertex_xz(xi, zi)),
+                    "vertex ({}, {})",
+                    xi,
+                    zi
+                );
+            }
+        }
+        // hanging off the edge clamps to it, all the way off is empty
+        assert_eq!(
+            heights.vertices_in(Rect::new(-20.0, -20.0, -4.0, 0.0)),
+            (0..2, 0..11)
+        );
+        let (xs, zs) = heights.vertices_in(Rect::new(6.0, 11.0, 9.0, 15.0));
+        assert!(xs.is_empty() && zs.is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+//! Generated terrain heights saved to disk, so starting again with the same seed
+//! loads them instead of generating them again. Files are keyed by the seed and a
+//! chunk's coordinates. The terrain is a single chunk at (0, 0) for now. Each file
+//! starts with a format version and the config the heights were generated with,
+//! and it's ignored if either doesn't match. Edits aren't saved, only what the
+//! generator made.
+
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+use crate::terrain::{TerrainConfig, TerrainHeights, TerrainSeed};
+
+// bump whenever the file layout or the height generation changes
+const CACHE_VERSION: u32 = 4;
+const MAGIC: &[u8; 4] = b"THGT";
+
+fn cache_path(dir: &str, seed: TerrainSeed, chunk: IVec2) -> PathBuf {
+    PathBuf::from(dir).join(format!("{:016x}_{}_{}.bin", seed.0, chunk.x, chunk.y))
+}
+
+// Everything before the heights, the file is only used if this matches exactly.
+fn header(config: &TerrainConfig) -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.extend(CACHE_VERSION.to_le_bytes());
+    header.extend((config.x_max as u64).to_le_bytes());
+    header.extend((config.y_max as u64).to_le_bytes());
+    header.extend((config.interpolate_step as u64).to_le_bytes());
+    header.extend(config.height_scale.to_le_bytes());
+    header.push(config.wraps() as u8);
+    header.extend((config.layers.len() as u64).to_le_bytes());
+    for layer in &config.layers {
+        header.extend((layer.frequency_mult as u64).to_le_bytes());
+        header.extend(layer.amplitude.to_le_bytes());
+    }
+    // the island mask is worked out in world units
+    header.extend(config.extent.x.to_le_bytes());
+    header.extend(config.extent.y.to_le_bytes());
+    header.push(config.island.enabled as u8);
+    header.extend(config.island.radius.to_le_bytes());
+    header.extend(config.island.falloff.to_le_bytes());
+    header
+}
+
+pub fn load(config: &TerrainConfig, seed: TerrainSeed, chunk: IVec2) -> Option<TerrainHeights> {
+    if cfg!(target_arch = "wasm32") {
+        return None;
+    }
+    let dir = config.cache_dir.as_ref()?;
+    let path = cache_path(dir, seed, chunk);
+    let data = std::fs::read(&path).ok()?;
+    let header = header(config);
+    let Some(body) = data.strip_prefix(header.as_slice()) else {
+        info!(
+            "ignoring {}, it's from another version or config",
+            path.display()
+        );
+        return None;
+    };
+    if body.len() != config.x_max * config.y_max * 4 {
+        warn!("ignoring {}, it's the wrong size", path.display());
+        return None;
+    }
+    let heights = body
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    Some(TerrainHeights {
+        x_max: config.x_max,
+        y_max: config.y_max,
+        extent: config.extent,
+        heights,
+    })
+}
+
+pub fn save(config: &TerrainConfig, seed: TerrainSeed, chunk: IVec2, heights: &TerrainHeights) {
+    if cfg!(target_arch = "wasm32") {
+        return;
+    }
+    let Some(dir) = &config.cache_dir else {
+        return;
+    };
+    let mut data = header(config);
+    for height in &heights.heights {
+        data.extend(height.to_le_bytes());
+    }
+    let path = cache_path(dir, seed, chunk);
+    let result = std::fs::create_dir_all(dir).and_then(|()| std::fs::write(&path, data));
+    if let Err(err) = result {
+        warn!("couldn't cache the terrain in {}: {}", path.display(), err);
+    }
+}
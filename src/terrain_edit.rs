@@ -0,0 +1,500 @@
+//! Editing the terrain at runtime. Brushes change `TerrainHeights` right away and
+//! mark the vertices they touched as dirty; the mesh and collider then catch up
+//! over the next frames within `TerrainEditBudget`, so a big brush doesn't rebuild
+//! everything in a single frame. The brush either raises and lowers the ground
+//! or smooths it out, switched with the brush mode key.
+
+use bevy::prelude::*;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+use bevy::utils::HashSet;
+use bevy_rapier3d::prelude::{Collider, QueryFilter, RapierContext};
+use std::ops::Range;
+
+use crate::controls::KeyBindings;
+use crate::curvature::{self, CurvatureConfig};
+use crate::flatten::FlattenTool;
+use crate::measure::MeasureTool;
+use crate::path::PathTool;
+use crate::scatter::RescatterRegion;
+use crate::shading::TerrainShading;
+use crate::terrain::{self, ColliderKind, TerrainConfig, TerrainHeights};
+use crate::wetness::{self, WetnessConfig};
+use crate::{Mountain, Player};
+
+#[derive(Resource, Debug, Clone)]
+pub struct TerrainEditBudget {
+    // most mesh vertices rewritten in a single frame
+    pub vertices_per_frame: usize,
+    // seconds between collider rebuilds while edits are coming in
+    pub collider_rebuild_interval: f32,
+}
+
+impl Default for TerrainEditBudget {
+    fn default() -> Self {
+        TerrainEditBudget {
+            vertices_per_frame: 2000,
+            collider_rebuild_interval: 0.25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushMode {
+    // left click raises, right click lowers
+    RaiseLower,
+    // left click pulls heights towards the average of their neighbors
+    Smooth,
+}
+
+impl BrushMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            BrushMode::RaiseLower => "raise/lower",
+            BrushMode::Smooth => "smooth",
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct BrushConfig {
+    pub mode: BrushMode,
+    // world units
+    pub radius: f32,
+    // height change per second at the center of the brush
+    pub strength: f32,
+    // how quickly smoothing evens things out at the center of the brush, about a
+    // third of a bump is left after 1 / smooth_strength seconds
+    pub smooth_strength: f32,
+    // how far away from the camera the brush can reach
+    pub reach: f32,
+}
+
+impl Default for BrushConfig {
+    fn default() -> Self {
+        BrushConfig {
+            mode: BrushMode::RaiseLower,
+            radius: 5.0,
+            strength: 5.0,
+            smooth_strength: 4.0,
+            reach: 100.0,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct TerrainEdits {
+    // indices into `TerrainHeights` whose mesh vertex is out of date
+    dirty: HashSet<usize>,
+    // world space xz area touched since the last rescatter
+    edited_region: Option<Rect>,
+    collider_dirty: bool,
+    since_collider_rebuild: f32,
+    // collider being rebuilt on the async task pool. There's only ever one in
+    // flight, the old collider stays in place until it's done.
+    collider_task: Option<Task<Collider>>,
+}
+
+impl TerrainEdits {
+    pub fn mark_dirty(&mut self, index: usize) {
+        self.dirty.insert(index);
+        self.collider_dirty = true;
+    }
+
+    // Has the collider rebuilt from scratch, throwing away a rebuild in flight
+    // that was started with an older config.
+    pub fn rebuild_collider(&mut self) {
+        self.collider_dirty = true;
+        self.collider_task = None;
+    }
+
+    pub fn mark_region(&mut self, region: Rect) {
+        self.edited_region = Some(match self.edited_region {
+            Some(edited) => edited.union(region),
+            None => region,
+        });
+    }
+}
+
+// Where the camera is looking on the terrain, in world space.
+pub fn terrain_under_crosshair(
+    context: &RapierContext,
+    camera: &Transform,
+    player: Entity,
+    mountain: Entity,
+    reach: f32,
+) -> Option<Vec3> {
+    let direction = *camera.forward();
+    let (hit, distance) = context.cast_ray(
+        camera.translation,
+        direction,
+        reach,
+        true,
+        QueryFilter::new().exclude_collider(player),
+    )?;
+    (hit == mountain).then(|| camera.translation + direction * distance)
+}
+
+pub fn toggle_brush_mode(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut brush: ResMut<BrushConfig>,
+) {
+    if !keys.just_pressed(bindings.brush_mode) {
+        return;
+    }
+    brush.mode = match brush.mode {
+        BrushMode::RaiseLower => BrushMode::Smooth,
+        BrushMode::Smooth => BrushMode::RaiseLower,
+    };
+    info!("terrain brush: {}", brush.mode.name());
+}
+
+// Switches the terrain between colliding as a heightfield and as a trimesh, to
+// compare the two. The new collider is built in the background like an edited
+// one, see `apply_terrain_edits`.
+pub fn toggle_terrain_collider(
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut config: ResMut<TerrainConfig>,
+    mut edits: ResMut<TerrainEdits>,
+) {
+    if !keys.just_pressed(bindings.toggle_trimesh) {
+        return;
+    }
+    config.collider = match config.collider {
+        ColliderKind::Heightfield => ColliderKind::Trimesh,
+        ColliderKind::Trimesh => ColliderKind::Heightfield,
+    };
+    info!("the terrain now collides as a {:?}", config.collider);
+    edits.rebuild_collider();
+}
+
+// Average of a vertex and its neighbors, up to one vertex away on each side.
+fn neighborhood_mean(window: &HeightWindow, xi: usize, zi: usize) -> f32 {
+    let (mut sum, mut count) = (0.0, 0.0);
+    for x in xi.saturating_sub(1).max(window.xs.start)..=(xi + 1).min(window.xs.end - 1) {
+        for z in zi.saturating_sub(1).max(window.zs.start)..=(zi + 1).min(window.zs.end - 1) {
+            sum += window.get(x, z);
+            count += 1.0;
+        }
+    }
+    sum / count
+}
+
+// A copy of the heights in a block of rows and columns, so smoothing only has to
+// copy what's around the brush. Where it stops short of the edges of the terrain
+// there has to be a vertex of border around the ones being averaged.
+struct HeightWindow {
+    xs: Range<usize>,
+    zs: Range<usize>,
+    heights: Vec<f32>,
+}
+
+impl HeightWindow {
+    fn new(heights: &TerrainHeights, xs: Range<usize>, zs: Range<usize>) -> Self {
+        let heights = xs
+            .clone()
+            .flat_map(|xi| {
+                heights.heights[heights.index(xi, zs.start)..heights.index(xi, zs.end)]
+                    .iter()
+                    .copied()
+            })
+            .collect();
+        HeightWindow { xs, zs, heights }
+    }
+
+    fn get(&self, xi: usize, zi: usize) -> f32 {
+        self.heights[(xi - self.xs.start) * self.zs.len() + zi - self.zs.start]
+    }
+}
+
+// Edits the terrain under the crosshair with the brush while a mouse button is
+// held, see `BrushMode` for what each button does.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_brush(
+    time: Res<Time>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    brush: Res<BrushConfig>,
+    context: Res<RapierContext>,
+    mut heights: ResMut<TerrainHeights>,
+    mut edits: ResMut<TerrainEdits>,
+    measure: Res<MeasureTool>,
+    path: Res<PathTool>,
+    flatten: Res<FlattenTool>,
+    camera: Query<&Transform, With<Camera3d>>,
+    player: Query<Entity, With<Player>>,
+    mountain: Query<(Entity, &Transform), With<Mountain>>,
+) {
+    // clicks are for the other tools while one's out
+    if measure.active || path.active || flatten.active {
+        return;
+    }
+    let direction = if mouse.pressed(MouseButton::Left) {
+        1.0
+    } else if mouse.pressed(MouseButton::Right) && brush.mode == BrushMode::RaiseLower {
+        -1.0
+    } else {
+        return;
+    };
+    let Ok((mountain, mountain_transform)) = mountain.get_single() else {
+        return;
+    };
+    let Some(hit) = terrain_under_crosshair(
+        &context,
+        camera.single(),
+        player.single(),
+        mountain,
+        brush.reach,
+    ) else {
+        return;
+    };
+
+    let center = hit.xz() - mountain_transform.translation.xz();
+    edits.mark_region(Rect::from_center_half_size(
+        hit.xz(),
+        Vec2::splat(brush.radius),
+    ));
+    let dt = time.delta_seconds();
+    // only the vertices in the square around the brush can be in reach
+    let (xs, zs) = heights.vertices_in(Rect::from_center_half_size(
+        center,
+        Vec2::splat(brush.radius),
+    ));
+    if xs.is_empty() || zs.is_empty() {
+        return;
+    }
+    // smoothing reads the heights from before this frame's changes, so the result
+    // doesn't depend on the order the vertices are visited in. That's the square
+    // and the vertices around it, which the ones on its edge average with.
+    let before = (brush.mode == BrushMode::Smooth).then(|| {
+        let grow = |range: &Range<usize>, len: usize| {
+            range.start.saturating_sub(1)..(range.end + 1).min(len)
+        };
+        HeightWindow::new(&heights, grow(&xs, heights.x_max), grow(&zs, heights.y_max))
+    });
+    for xi in xs {
+        for zi in zs.clone() {
+            let distance = heights.vertex_xz(xi, zi).distance(center);
+            if distance > brush.radius {
+                continue;
+            }
+            // smooth falloff towards the edge of the brush
+            let falloff = 1.0 - (distance / brush.radius).powi(2);
+            let index = heights.index(xi, zi);
+            heights.heights[index] += match &before {
+                None => direction * brush.strength * dt * falloff,
+                Some(before) => {
+                    let pull = 1.0 - (-brush.smooth_strength * falloff * dt).exp();
+                    (neighborhood_mean(before, xi, zi) - before.get(xi, zi)) * pull
+                }
+            };
+            edits.mark_dirty(index);
+        }
+    }
+}
+
+// Brings the terrain mesh up to date with the heights at `batch`, and the normals
+// and attributes that depend on them. Only the vertices near the batch are
+// rewritten, so the work grows with the batch rather than the mesh. A faceted mesh
+// has a copy of each vertex for every triangle around it, and they all move.
+fn update_mesh(
+    mesh: &mut Mesh,
+    batch: &[usize],
+    config: &TerrainConfig,
+    heights: &TerrainHeights,
+    (wetness, curvature): (&WetnessConfig, &CurvatureConfig),
+    faceted: bool,
+) {
+    let (x_max, y_max) = (heights.x_max, heights.y_max);
+    let moved: Vec<usize> = if faceted {
+        terrain::update_facets(mesh, heights, batch);
+        batch
+            .iter()
+            .flat_map(|&index| terrain::facet_copies(index, x_max, y_max))
+            .collect()
+    } else {
+        if let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
+        {
+            for &index in batch {
+                positions[index][1] = heights.heights[index];
+            }
+        }
+        terrain::update_mesh_normals(mesh, x_max, y_max, config.normal_smoothing, batch);
+        batch.to_vec()
+    };
+    // the shoreline moves with the ground
+    if wetness.sea_level.is_some() {
+        wetness::write_wetness_at(mesh, wetness, &moved);
+    }
+    curvature::write_curvature_around(mesh, heights, curvature, batch);
+}
+
+// Copies dirty heights into the mesh, at most `vertices_per_frame` of them a frame,
+// and rebuilds the collider in the background at most every
+// `collider_rebuild_interval` seconds. The collider keeps getting rebuilt until it
+// has seen every edit, so it ends up matching.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_terrain_edits(
+    mut commands: Commands,
+    time: Res<Time>,
+    budget: Res<TerrainEditBudget>,
+    config: Res<TerrainConfig>,
+    heights: Res<TerrainHeights>,
+    (wetness, curvature, shading): (
+        Res<WetnessConfig>,
+        Res<CurvatureConfig>,
+        Res<TerrainShading>,
+    ),
+    mut edits: ResMut<TerrainEdits>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut rescatter: EventWriter<RescatterRegion>,
+    mountain: Query<(Entity, &Handle<Mesh>), With<Mountain>>,
+) {
+    let Ok((mountain, mesh_handle)) = mountain.get_single() else {
+        return;
+    };
+    edits.since_collider_rebuild += time.delta_seconds();
+
+    if !edits.dirty.is_empty() {
+        let Some(mesh) = meshes.get_mut(mesh_handle) else {
+            return;
+        };
+        let batch: Vec<usize> = edits
+            .dirty
+            .iter()
+            .take(budget.vertices_per_frame)
+            .copied()
+            .collect();
+        for index in &batch {
+            edits.dirty.remove(index);
+        }
+        update_mesh(
+            mesh,
+            &batch,
+            &config,
+            &heights,
+            (&wetness, &curvature),
+            shading.faceted,
+        );
+    }
+
+    // once the mesh has caught up, put the scattered objects back on the new surface
+    if edits.dirty.is_empty() {
+        if let Some(region) = edits.edited_region.take() {
+            rescatter.send(RescatterRegion(region));
+        }
+    }
+
+    if edits.collider_dirty
+        && edits.collider_task.is_none()
+        && edits.since_collider_rebuild >= budget.collider_rebuild_interval
+    {
+        let Some(mesh) = meshes.get(mesh_handle) else {
+            return;
+        };
+        let config = config.clone();
+        let heights = heights.clone();
+        // a faceted mesh has no indices to build a trimesh from, so that's built
+        // from a smooth mesh of the same heights
+        let mesh = (!shading.faceted).then(|| mesh.clone());
+        edits.collider_task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            let mesh = mesh.unwrap_or_else(|| terrain::terrain_mesh(&config, &heights));
+            terrain::terrain_collider(&config, &heights, &mesh)
+        }));
+        // a trimesh collider is built from the mesh, so it's only up to date once
+        // the mesh has caught up with every edit
+        edits.collider_dirty = !edits.dirty.is_empty();
+        edits.since_collider_rebuild = 0.0;
+    }
+
+    if let Some(task) = &mut edits.collider_task {
+        if let Some(collider) = block_on(future::poll_once(task)) {
+            commands.entity(mountain).insert(collider);
+            edits.collider_task = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shading;
+
+    #[test]
+    fn edited_mesh_matches_a_rebuilt_one() {
+        let config = TerrainConfig {
+            normal_smoothing: 2,
+            ..default()
+        };
+        let wetness = WetnessConfig {
+            sea_level: Some(1.0),
+            ..default()
+        };
+        let curvature = CurvatureConfig::default();
+        let (x_max, y_max) = (16, 12);
+        let mut heights = TerrainHeights {
+            x_max,
+            y_max,
+            extent: Vec2::new(30.0, 20.0),
+            heights: (0..x_max * y_max)
+                .map(|index| ((index / y_max) as f32 * 0.7).sin() + (index % y_max) as f32 * 0.2)
+                .collect(),
+        };
+        for faceted in [false, true] {
+            let shading = TerrainShading { faceted };
+            let attributes = (&wetness, &curvature);
+            let mut mesh = shading::shaded_terrain_mesh(&config, &heights, attributes, &shading);
+            // a bump in the middle and another on the edge
+            let batch: Vec<usize> = [(7, 5), (8, 5), (7, 6), (15, 0)]
+                .into_iter()
+                .map(|(xi, zi)| heights.index(xi, zi))
+                .collect();
+            for &index in &batch {
+                heights.heights[index] += 1.5;
+            }
+            update_mesh(&mut mesh, &batch, &config, &heights, attributes, faceted);
+
+            let rebuilt = shading::shaded_terrain_mesh(&config, &heights, attributes, &shading);
+            for attribute in [Mesh::ATTRIBUTE_POSITION, Mesh::ATTRIBUTE_NORMAL] {
+                let (
+                    Some(VertexAttributeValues::Float32x3(edited)),
+                    Some(VertexAttributeValues::Float32x3(expected)),
+                ) = (
+                    mesh.attribute(attribute.id),
+                    rebuilt.attribute(attribute.id),
+                )
+                else {
+                    panic!("no {} in the mesh", attribute.name);
+                };
+                for (vertex, (edited, expected)) in edited.iter().zip(expected).enumerate() {
+                    assert!(
+                        Vec3::from(*edited).distance(Vec3::from(*expected)) < 1e-5,
+                        "{} of vertex {vertex} is {edited:?} instead of {expected:?}, faceted: {faceted}",
+                        attribute.name
+                    );
+                }
+            }
+            for attribute in [wetness::ATTRIBUTE_WETNESS, curvature::ATTRIBUTE_CURVATURE] {
+                let (
+                    Some(VertexAttributeValues::Float32(edited)),
+                    Some(VertexAttributeValues::Float32(expected)),
+                ) = (
+                    mesh.attribute(attribute.id),
+                    rebuilt.attribute(attribute.id),
+                )
+                else {
+                    panic!("no {} in the mesh", attribute.name);
+                };
+                for (vertex, (edited, expected)) in edited.iter().zip(expected).enumerate() {
+                    assert!(
+                        (edited - expected).abs() < 1e-5,
+                        "{} of vertex {vertex} is {edited} instead of {expected}, faceted: {faceted}",
+                        attribute.name
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Sampler settings for the textures on `MountainMaterial`. They're read when the
+//! textures are created, so they only take effect at startup.
+
+use bevy::prelude::*;
+use bevy::render::texture::{ImageSampler, ImageSamplerDescriptor};
+
+// Anisotropic filtering keeps textures seen at a glancing angle sharp without
+// the shimmer of a lower mip bias. Each doubling of `anisotropy` can take up to
+// twice the texture samples on steep surfaces; it's nearly free on desktop
+// GPUs but shows up on integrated and mobile ones, where 4 is a good compromise.
+// `mip_bias` trades the other way: negative is sharper but shimmers in the
+// distance, positive is blurrier but stable. It costs nothing.
+#[derive(Resource, Debug, Clone)]
+pub struct TextureFilteringConfig {
+    // 1 turns anisotropic filtering off, at most 16
+    pub anisotropy: u16,
+    // added to the mip level the GPU picks, in mip levels
+    pub mip_bias: f32,
+}
+
+impl Default for TextureFilteringConfig {
+    fn default() -> Self {
+        TextureFilteringConfig {
+            anisotropy: 16,
+            mip_bias: 0.0,
+        }
+    }
+}
+
+impl TextureFilteringConfig {
+    pub fn sampler(&self) -> ImageSampler {
+        ImageSampler::Descriptor(ImageSamplerDescriptor {
+            // anisotropic filtering needs every filter set to linear, which this is
+            anisotropy_clamp: self.anisotropy.clamp(1, 16),
+            ..ImageSamplerDescriptor::linear()
+        })
+    }
+}
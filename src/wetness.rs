@@ -0,0 +1,129 @@
+//! Wet ground along the shore. Every terrain vertex gets a wetness from 0.0 to
+//! 1.0 in its own vertex attribute: fully wet at and below the sea level, drying
+//! off over a band above it. The terrain shader darkens wet ground and gives it a
+//! shine at low angles. It's worked out from the vertex heights, so edits move
+//! the shoreline with them. There's no water yet, so without a sea level
+//! nothing is wet.
+
+use bevy::prelude::*;
+use bevy::render::mesh::{MeshVertexAttribute, VertexAttributeValues};
+use bevy::render::render_resource::VertexFormat;
+
+use crate::{Mountain, MountainMaterial, TERRAIN_BASE_HEIGHT};
+
+pub const ATTRIBUTE_WETNESS: MeshVertexAttribute =
+    MeshVertexAttribute::new("Wetness", 988_540_917, VertexFormat::Float32);
+
+#[derive(Resource, Debug, Clone)]
+pub struct WetnessConfig {
+    // world space height of the water surface, None while there's no water
+    pub sea_level: Option<f32>,
+    // world units above the sea level the ground takes to dry off
+    pub band: f32,
+    // fraction fully wet ground is darkened by, 0.0 to 1.0
+    pub darkening: f32,
+    // brightness of the shine on wet ground seen at a low angle
+    pub sheen: f32,
+}
+
+impl Default for WetnessConfig {
+    fn default() -> Self {
+        WetnessConfig {
+            sea_level: None,
+            band: 3.0,
+            darkening: 0.35,
+            sheen: 0.25,
+        }
+    }
+}
+
+pub use uniform::WetnessUniform;
+
+// ShaderType's generated size checks count as dead code in test builds, see
+// height_gradient.rs
+#[allow(dead_code)]
+mod uniform {
+    use bevy::render::render_resource::ShaderType;
+
+    // The config as the shader sees it.
+    #[derive(ShaderType, Debug, Clone, Copy, Default, PartialEq)]
+    pub struct WetnessUniform {
+        pub darkening: f32,
+        pub sheen: f32,
+    }
+}
+
+impl WetnessConfig {
+    pub fn uniform(&self) -> WetnessUniform {
+        WetnessUniform {
+            darkening: self.darkening.clamp(0.0, 1.0),
+            sheen: self.sheen.max(0.0),
+        }
+    }
+
+    // How wet ground at a world space height is.
+    pub fn wetness_at(&self, height: f32) -> f32 {
+        let Some(sea_level) = self.sea_level else {
+            return 0.0;
+        };
+        let t = ((height - sea_level) / self.band.max(f32::EPSILON)).clamp(0.0, 1.0);
+        // smoothstep, so there's no hard line where the band starts or ends
+        1.0 - t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// Sets the wetness of every vertex of the terrain mesh from its height.
+pub fn write_wetness(mesh: &mut Mesh, config: &WetnessConfig) {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    let wetness: Vec<f32> = positions
+        .iter()
+        .map(|position| config.wetness_at(position[1] + TERRAIN_BASE_HEIGHT))
+        .collect();
+    mesh.insert_attribute(ATTRIBUTE_WETNESS, wetness);
+}
+
+// Sets the wetness of just the terrain mesh's vertices at `vertices`, for when
+// only those have moved since the mesh's wetness was last written.
+pub fn write_wetness_at(mesh: &mut Mesh, config: &WetnessConfig, vertices: &[usize]) {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return;
+    };
+    let wetness: Vec<(usize, f32)> = vertices
+        .iter()
+        .map(|&vertex| {
+            let height = positions[vertex][1] + TERRAIN_BASE_HEIGHT;
+            (vertex, config.wetness_at(height))
+        })
+        .collect();
+    if let Some(VertexAttributeValues::Float32(values)) = mesh.attribute_mut(ATTRIBUTE_WETNESS) {
+        for (vertex, wetness) in wetness {
+            values[vertex] = wetness;
+        }
+    }
+}
+
+// Passes config changes on to the terrain.
+pub fn apply_wetness_config(
+    config: Res<WetnessConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<MountainMaterial>>,
+    mountain: Query<(&Handle<Mesh>, &Handle<MountainMaterial>), With<Mountain>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    for (mesh, material) in mountain.iter() {
+        if let Some(mesh) = meshes.get_mut(mesh) {
+            write_wetness(mesh, &config);
+        }
+        if let Some(material) = materials.get_mut(material) {
+            material.wetness = config.uniform();
+        }
+    }
+}
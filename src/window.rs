@@ -0,0 +1,182 @@
+//! Settings for the primary window, and what happens when it loses focus. The
+//! title keeps up with the seed, the terrain preset and whether the terrain is
+//! still being generated.
+
+use bevy::prelude::*;
+use bevy::utils::{Duration, Instant};
+use bevy::window::{
+    CursorGrabMode, PresentMode, PrimaryWindow, WindowFocused, WindowMode, WindowResolution,
+};
+
+use crate::loading::TerrainTask;
+use crate::preset::PresetCycle;
+use crate::settings::Settings;
+use crate::terrain::{SeedLabel, TerrainHeights};
+use crate::GameState;
+
+#[derive(Resource, Debug, Clone)]
+pub struct WindowConfig {
+    pub title: String,
+    pub resolution: Vec2,
+    pub mode: WindowMode,
+    // turn off for benchmarking so the frame rate isn't tied to the monitor,
+    // `Settings::max_fps` can still hold it down then
+    pub vsync: bool,
+    // true: losing focus while playing opens the pause menu.
+    // false: it only frees the cursor and stops mouse look until focus comes back.
+    pub pause_on_focus_loss: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: "Shader PLSE Game Jam".to_string(),
+            resolution: Vec2::new(1280.0, 720.0),
+            mode: WindowMode::Windowed,
+            vsync: true,
+            pause_on_focus_loss: true,
+        }
+    }
+}
+
+impl WindowConfig {
+    pub fn present_mode(&self) -> PresentMode {
+        if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        }
+    }
+
+    // "Title (seed 1234, Rolling hills) - Ready", leaving out whatever isn't known
+    pub fn status_title(
+        &self,
+        seed: Option<&SeedLabel>,
+        preset: Option<&str>,
+        status: Option<&str>,
+    ) -> String {
+        let details: Vec<&str> = seed
+            .map(|seed| seed.0.as_str())
+            .into_iter()
+            .chain(preset)
+            .collect();
+        let mut title = self.title.clone();
+        if !details.is_empty() {
+            title = format!("{} ({})", title, details.join(", "));
+        }
+        match status {
+            Some(status) => format!("{} - {}", title, status),
+            None => title,
+        }
+    }
+
+    pub fn window(&self, seed: Option<&SeedLabel>) -> Window {
+        Window {
+            title: self.status_title(seed, None, None),
+            resolution: WindowResolution::new(self.resolution.x, self.resolution.y),
+            mode: self.mode,
+            present_mode: self.present_mode(),
+            ..default()
+        }
+    }
+}
+
+// Keeps the window title up to date as the terrain is regenerated.
+pub fn update_window_title(
+    config: Res<WindowConfig>,
+    seed: Option<Res<SeedLabel>>,
+    cycle: Option<Res<PresetCycle>>,
+    task: Option<Res<TerrainTask>>,
+    heights: Option<Res<TerrainHeights>>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let status = match (task, heights) {
+        (Some(task), _) => Some(format!("Generating… {:.0}%", task.fraction_done() * 100.0)),
+        (None, Some(_)) => Some("Ready".to_string()),
+        (None, None) => None,
+    };
+    let title = config.status_title(
+        seed.as_deref(),
+        cycle.as_ref().and_then(|cycle| cycle.name()),
+        status.as_deref(),
+    );
+    for mut window in window.iter_mut() {
+        // only touching the window when the title changes keeps it from being
+        // sent to the OS every frame
+        if window.title != title {
+            window.title = title.clone();
+        }
+    }
+}
+
+// Locks and hides the cursor for mouse look, or frees it.
+pub fn grab_cursor(window: &mut Window, grab: bool) {
+    if grab {
+        // use `Locked` mode to keep the cursor in one place
+        window.cursor.grab_mode = CursorGrabMode::Locked;
+        window.cursor.visible = false;
+    } else {
+        window.cursor.grab_mode = CursorGrabMode::None;
+        window.cursor.visible = true;
+    }
+}
+
+// So alt-tabbing away doesn't leave the game running with the cursor stuck.
+pub fn handle_focus_change(
+    config: Res<WindowConfig>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut focus_events: EventReader<WindowFocused>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for event in focus_events.read() {
+        if *state.get() != GameState::Playing {
+            continue;
+        }
+        let Ok(mut window) = window.get_mut(event.window) else {
+            continue;
+        };
+        if !event.focused && config.pause_on_focus_loss {
+            // leaving Playing frees the cursor
+            next_state.set(GameState::Menu);
+        } else {
+            grab_cursor(&mut window, event.focused);
+        }
+    }
+}
+
+// Seconds the last frame took before `limit_frame_rate` slept away the rest of
+// it, so how long the frame itself took however low the frame rate is capped.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct FrameWork {
+    pub seconds: f32,
+}
+
+// Sleeps away the rest of the frame when it finished sooner than the frame rate
+// cap allows. Runs last, so the time spent waiting falls between frames. With
+// vsync on the monitor already paces frames and the cap isn't used.
+pub fn limit_frame_rate(
+    config: Res<WindowConfig>,
+    settings: Res<Settings>,
+    mut work: ResMut<FrameWork>,
+    mut frame_start: Local<Option<Instant>>,
+) {
+    if let Some(start) = *frame_start {
+        let elapsed = start.elapsed();
+        work.seconds = elapsed.as_secs_f32();
+        // the browser paces frames itself, and threads can't sleep there
+        let capped = !cfg!(target_arch = "wasm32") && !config.vsync && settings.max_fps != 0;
+        let target = Duration::from_secs_f64(1.0 / settings.max_fps.max(1) as f64);
+        if capped && elapsed < target {
+            // sleeping can overshoot by a millisecond or so, spin the end of it
+            let remaining = target - elapsed;
+            if let Some(sleep) = remaining.checked_sub(Duration::from_millis(2)) {
+                std::thread::sleep(sleep);
+            }
+            while start.elapsed() < target {
+                std::hint::spin_loop();
+            }
+        }
+    }
+    *frame_start = Some(Instant::now());
+}